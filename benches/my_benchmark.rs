@@ -1,8 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion, Throughput};
-use spectre::math::{self, simd};
+use spectre::math;
 
-// NOTE: Due to current criterion limitations, you must rename main.rs into
-//       lib.rs in order to be able to run this benchmark.
 pub fn criterion_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("sum");
     for input_len in [
@@ -27,70 +25,70 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             b.iter(|| input.iter().sum::<f32>());
         });
         group.bench_with_input(format!("optimized/{input_len}"), &input, |b, input| {
-            b.iter(|| math::sum_f32(&input[..]));
+            b.iter(|| math::sum_f32_fast(&input[..]));
         });
         /*
-        // These benchmarks are useful for tuning simd::sum_f32, but require
-        // making simd::sum_f32_impl public.
+        // These benchmarks are useful for tuning math::sum_f32_fast, but
+        // require making math::simd and simd::sum_f32_fast_impl public.
         group.bench_with_input(format!("simd1x1/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<1, 1>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<1, 1>(&input[..]));
         });
         group.bench_with_input(format!("simd1x2/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<1, 2>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<1, 2>(&input[..]));
         });
         group.bench_with_input(format!("simd1x4/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<1, 4>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<1, 4>(&input[..]));
         });
         group.bench_with_input(format!("simd1x8/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<1, 8>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<1, 8>(&input[..]));
         });
         group.bench_with_input(format!("simd2x1/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<2, 1>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<2, 1>(&input[..]));
         });
         group.bench_with_input(format!("simd2x2/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<2, 2>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<2, 2>(&input[..]));
         });
         group.bench_with_input(format!("simd2x4/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<2, 4>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<2, 4>(&input[..]));
         });
         group.bench_with_input(format!("simd2x8/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<2, 8>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<2, 8>(&input[..]));
         });
         group.bench_with_input(format!("simd4x1/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<4, 1>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<4, 1>(&input[..]));
         });
         group.bench_with_input(format!("simd4x2/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<4, 2>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<4, 2>(&input[..]));
         });
         group.bench_with_input(format!("simd4x4/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<4, 4>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<4, 4>(&input[..]));
         });
         group.bench_with_input(format!("simd4x8/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<4, 8>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<4, 8>(&input[..]));
         });
         group.bench_with_input(format!("simd8x1/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<8, 1>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<8, 1>(&input[..]));
         });
         group.bench_with_input(format!("simd8x2/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<8, 2>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<8, 2>(&input[..]));
         });
         group.bench_with_input(format!("simd8x4/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<8, 4>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<8, 4>(&input[..]));
         });
         group.bench_with_input(format!("simd8x8/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<8, 8>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<8, 8>(&input[..]));
         });
         group.bench_with_input(format!("simd16x1/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<16, 1>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<16, 1>(&input[..]));
         });
         group.bench_with_input(format!("simd16x2/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<16, 2>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<16, 2>(&input[..]));
         });
         group.bench_with_input(format!("simd16x4/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<16, 4>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<16, 4>(&input[..]));
         });
         group.bench_with_input(format!("simd16x8/{input_len}"), &input, |b, input| {
-            b.iter(|| simd::sum_f32_impl::<16, 8>(&input[..]));
+            b.iter(|| math::simd::sum_f32_fast_impl::<16, 8>(&input[..]));
         });
         */
     }
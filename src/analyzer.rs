@@ -0,0 +1,341 @@
+//! High-level spectrum analysis pipeline
+
+use crate::{
+    detrend::{Detrending, HighPassFilter},
+    fourier::{PlanOptions, SteadyQPlan, SteadyQTransform},
+    math::Float,
+    resampler::{FourierResampler, ResamplingMode},
+    scale::FrequencyScale,
+};
+
+/// Builder for an [`Analyzer`]
+///
+/// Defaults match those of the `spectre` command-line application.
+///
+#[derive(Clone, Debug)]
+pub struct AnalyzerBuilder {
+    /// Audio sampling rate in Hz
+    sample_rate: usize,
+
+    /// Minimal frequency resolution at the low anchor frequency, in Hz
+    freq_res: f32,
+
+    /// Minimal time resolution at the high anchor frequency, in ms
+    time_res: f32,
+
+    /// Window function
+    window: String,
+
+    /// Removal of DC offset and slow trends from the input
+    detrending: Detrending,
+
+    /// Minimum output frequency in Hz
+    min_freq: f32,
+
+    /// Maximum output frequency in Hz
+    max_freq: f32,
+
+    /// Anchor frequencies of the steady-Q transform in Hz, if not the default
+    anchors: Option<(f32, f32)>,
+
+    /// How the steady-Q transform picks and combines FFTs
+    plan_options: PlanOptions,
+
+    /// Frequency scale of the output
+    freq_scale: FrequencyScale,
+
+    /// Strategy for mapping Fourier transform bins to output bins
+    resampling: ResamplingMode,
+
+    /// Number of output bins
+    output_len: usize,
+}
+//
+impl AnalyzerBuilder {
+    /// Start configuring an analyzer for a certain audio sampling rate
+    pub fn new(sample_rate: usize) -> Self {
+        assert!(sample_rate > 0);
+        Self {
+            sample_rate,
+            freq_res: 1.0,
+            time_res: 7.0,
+            window: "hann".to_owned(),
+            detrending: Detrending::Mean,
+            min_freq: 20.0,
+            max_freq: 20_000.0f32.min((sample_rate / 2) as f32),
+            anchors: None,
+            plan_options: PlanOptions::default(),
+            freq_scale: FrequencyScale::Logarithmic,
+            resampling: ResamplingMode::Decibel,
+            output_len: 1024,
+        }
+    }
+
+    /// Set the minimal frequency resolution at the low anchor frequency, in Hz
+    ///
+    /// This is the minimal FFT bin spacing at the low anchor frequency.
+    /// Actual frequency resolution will be a bit more, depending on the
+    /// window function.
+    ///
+    pub fn freq_res(mut self, freq_res: f32) -> Self {
+        self.freq_res = freq_res;
+        self
+    }
+
+    /// Set the minimal time resolution at the high anchor frequency, in ms
+    pub fn time_res(mut self, time_res: f32) -> Self {
+        self.time_res = time_res;
+        self
+    }
+
+    /// Set the window function ("rectangular", "triangular", "hann",
+    /// "blackman", "nuttall" or "flattop")
+    pub fn window(mut self, window: impl Into<String>) -> Self {
+        self.window = window.into();
+        self
+    }
+
+    /// Set how DC offset and slow trends are removed from the input
+    pub fn detrending(mut self, detrending: Detrending) -> Self {
+        self.detrending = detrending;
+        self
+    }
+
+    /// Set the range of output frequencies in Hz
+    pub fn freq_range(mut self, min_freq: f32, max_freq: f32) -> Self {
+        self.min_freq = min_freq;
+        self.max_freq = max_freq;
+        self
+    }
+
+    /// Set the anchor frequencies of the steady-Q transform in Hz, i.e. the
+    /// frequencies at which the frequency and time resolution are specified
+    ///
+    /// By default, they are derived from the range of output frequencies
+    /// using `SteadyQTransform::default_anchors()`.
+    ///
+    pub fn anchor_freqs(mut self, low_anchor: f32, high_anchor: f32) -> Self {
+        self.anchors = Some((low_anchor, high_anchor));
+        self
+    }
+
+    /// Set how the steady-Q transform picks and combines FFTs
+    pub fn plan_options(mut self, plan_options: PlanOptions) -> Self {
+        self.plan_options = plan_options;
+        self
+    }
+
+    /// Set the frequency scale of the output
+    pub fn freq_scale(mut self, freq_scale: FrequencyScale) -> Self {
+        self.freq_scale = freq_scale;
+        self
+    }
+
+    /// Set the strategy for mapping Fourier transform bins to output bins
+    pub fn resampling(mut self, resampling: ResamplingMode) -> Self {
+        self.resampling = resampling;
+        self
+    }
+
+    /// Set the number of output bins
+    pub fn output_len(mut self, output_len: usize) -> Self {
+        self.output_len = output_len;
+        self
+    }
+
+    /// Build the analyzer
+    ///
+    /// # Panics
+    ///
+    /// If the configuration is not valid, e.g. the frequency range is empty,
+    /// does not fit below the Nyquist frequency, or includes 0Hz on a
+    /// logarithmic frequency scale.
+    ///
+    pub fn build(self) -> Analyzer {
+        self.build_with_precision()
+    }
+
+    /// Build an analyzer that computes in an arbitrary floating-point
+    /// precision, e.g. `build_with_precision::<f64>()`
+    ///
+    /// # Panics
+    ///
+    /// Under the same conditions as `build()`.
+    ///
+    pub fn build_with_precision<T: Float>(self) -> Analyzer<T> {
+        assert!(self.freq_res.is_finite() && self.freq_res > 0.0);
+        assert!(self.time_res.is_finite() && self.time_res > 0.0);
+        assert!(self.min_freq.is_finite() && self.min_freq >= 0.0);
+        assert!(self.max_freq.is_finite() && self.max_freq > self.min_freq);
+        assert!(self.freq_scale.position(self.min_freq).is_finite());
+        assert!(self.output_len > 0);
+        let anchors = self.anchors.unwrap_or_else(|| {
+            SteadyQTransform::default_anchors(self.min_freq, self.max_freq, self.sample_rate)
+        });
+        let mut fourier = SteadyQTransform::with_precision(
+            self.freq_res,
+            self.time_res,
+            anchors,
+            self.sample_rate,
+            &self.window,
+            self.plan_options,
+        );
+        fourier.set_detrending(self.detrending);
+        let resampler = FourierResampler::new(
+            fourier.output_len(),
+            self.sample_rate,
+            self.output_len,
+            self.min_freq,
+            self.max_freq,
+            &self.freq_scale,
+            self.resampling,
+        );
+
+        // Output bins are evenly spaced in the units of the frequency scale
+        let min_pos = self.freq_scale.position(self.min_freq);
+        let max_pos = self.freq_scale.position(self.max_freq);
+        let output_freqs = (0..self.output_len)
+            .map(|bin| {
+                let rel_pos = (bin as f32 + 0.5) / self.output_len as f32;
+                self.freq_scale
+                    .frequency(min_pos + rel_pos * (max_pos - min_pos))
+            })
+            .collect();
+
+        // Initially, the audio history is silent
+        let history = vec![0.0; fourier.input().len()].into_boxed_slice();
+        Analyzer {
+            sample_rate: self.sample_rate,
+            history,
+            high_pass: self.detrending.high_pass_filter(self.sample_rate),
+            fourier,
+            resampler,
+            output_freqs,
+        }
+    }
+}
+
+/// Spectrum analyzer
+///
+/// Keeps track of the latest audio samples, and turns them into a spectrum
+/// with a fixed number of bins on an arbitrary frequency scale, using a
+/// [`SteadyQTransform`] followed by a [`FourierResampler`]. The steady-Q
+/// transform is computed in single precision by default.
+///
+pub struct Analyzer<T: Float = f32> {
+    /// Audio sampling rate in Hz
+    sample_rate: usize,
+
+    /// Latest audio samples, oldest first
+    history: Box<[f32]>,
+
+    /// High-pass filter applied to incoming audio samples, if any
+    high_pass: Option<HighPassFilter>,
+
+    /// Approximate constant-Q transform
+    fourier: SteadyQTransform<T>,
+
+    /// Resampler from Fourier transform bins to output bins
+    resampler: FourierResampler,
+
+    /// Center frequency of each output bin in Hz
+    output_freqs: Box<[f32]>,
+}
+//
+impl Analyzer {
+    /// Start configuring an analyzer for a certain audio sampling rate
+    pub fn builder(sample_rate: usize) -> AnalyzerBuilder {
+        AnalyzerBuilder::new(sample_rate)
+    }
+}
+//
+impl<T: Float> Analyzer<T> {
+    /// Audio sampling rate in Hz
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// Number of audio samples that are used to compute each spectrum
+    pub fn input_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// FFTs used by the underlying steady-Q transform, and their resolution
+    pub fn plan(&self) -> &SteadyQPlan {
+        self.fourier.plan()
+    }
+
+    /// Center frequency of each output bin in Hz
+    pub fn output_freqs(&self) -> &[f32] {
+        &self.output_freqs[..]
+    }
+
+    /// Record new audio samples, forgetting about the oldest ones
+    ///
+    /// Audio can be pushed in chunks of any size. Only the last
+    /// `input_len()` samples are used, and the history is initially silent.
+    ///
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        // Go through history-sized chunks so that the high-pass filter, if
+        // any, sees every sample even if only the last ones are kept
+        let history_len = self.history.len();
+        for chunk in samples.chunks(history_len) {
+            self.history.copy_within(chunk.len().., 0);
+            let new_samples = &mut self.history[history_len - chunk.len()..];
+            new_samples.copy_from_slice(chunk);
+            if let Some(high_pass) = &mut self.high_pass {
+                high_pass.apply(new_samples);
+            }
+        }
+    }
+
+    /// Compute the spectrum of the latest audio samples, in dBFS
+    pub fn compute(&mut self) -> &[f32] {
+        self.fourier.input().copy_from_slice(&self.history[..]);
+        let magnitudes = self.fourier.compute();
+        self.resampler.resample(magnitudes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use more_asserts::*;
+
+    #[test]
+    fn sine_peak() {
+        // Push a -6dBFS 1kHz sine in small chunks
+        let sample_rate = 48_000;
+        let mut analyzer = Analyzer::builder(sample_rate)
+            .resampling(ResamplingMode::Max)
+            .output_len(512)
+            .build();
+        let omega = std::f32::consts::TAU * 1000.0 / sample_rate as f32;
+        let signal = (0..analyzer.input_len() + 100)
+            .map(|idx| 0.5 * (omega * idx as f32).sin())
+            .collect::<Vec<_>>();
+        for chunk in signal.chunks(100) {
+            analyzer.push_samples(chunk);
+        }
+
+        // The peak should be at 1kHz. Merging the FFTs of the steady-Q
+        // transform blurs it a little, so its amplitude is only roughly right,
+        // but the spectrum should be much lower away from it.
+        let spectrum = analyzer.compute().to_owned();
+        let (peak_bin, &peak_level) = spectrum
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).expect("Levels should not be NaN"))
+            .expect("There has to be output bins");
+        assert_lt!(
+            (analyzer.output_freqs()[peak_bin] / 1000.0 - 1.0).abs(),
+            0.01
+        );
+        assert_gt!(peak_level, -12.0);
+        for (&freq, &level) in analyzer.output_freqs().iter().zip(&spectrum) {
+            if !(500.0..2000.0).contains(&freq) {
+                assert_lt!(level, peak_level - 60.0);
+            }
+        }
+    }
+}
@@ -1,8 +1,6 @@
 //! Lock-free error handling for the audio thread
 
 use jack::Control;
-use num_derive::FromPrimitive;
-use num_traits::FromPrimitive;
 use std::{
     panic::{catch_unwind, UnwindSafe},
     sync::{
@@ -12,7 +10,7 @@ use std::{
 };
 
 /// Fatal errors that can occur within the audio threads
-#[derive(Debug, FromPrimitive)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum AudioError {
     /// An audio callback has panicked
     CallbackPanicked = 0,
@@ -23,6 +21,19 @@ pub enum AudioError {
     /// The history buffer must be reallocated (and we aren't ready to do so)
     MustReallocateHistory,
 }
+//
+impl AudioError {
+    /// Convert an error number back into an AudioError
+    fn from_u32(code: u32) -> Option<Self> {
+        [
+            Self::CallbackPanicked,
+            Self::SampleRateChanged,
+            Self::MustReallocateHistory,
+        ]
+        .into_iter()
+        .find(|&error| error as u32 == code)
+    }
+}
 
 /// Setup audio thread error notification mechanism
 pub fn setup_error_channel() -> (ErrorInput, ErrorOutput) {
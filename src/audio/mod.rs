@@ -3,16 +3,30 @@
 mod errors;
 
 use self::errors::{ErrorInput, ErrorOutput};
+use crate::detrend::HighPassFilter;
 use jack::{
-    AsyncClient, AudioIn, Client, Control, Frames, NotificationHandler, Port, ProcessHandler,
-    ProcessScope,
+    AsyncClient, AudioIn, AudioOut, Client, Control, Frames, MidiIn, NotificationHandler, Port,
+    ProcessHandler, ProcessScope,
+};
+use rt_history::{Clock, Overrun, RTHistory};
+use std::{
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
-use rt_history::{Overrun, RTHistory};
-use std::panic::AssertUnwindSafe;
 
 // Expose audio thread errors so the main thread can process them
 pub use errors::AudioError;
 
+/// Number of MIDI messages that can be received between two readouts
+const MIDI_HISTORY_LEN: usize = 256;
+
+/// MIDI channel voice message (status byte and two data bytes)
+pub type MidiMessage = [u8; 3];
+
 /// Handle to a prepared audio setup that is not recording data yet
 pub struct AudioSetup(Client);
 //
@@ -36,14 +50,54 @@ impl AudioSetup {
         self.0.buffer_size() as usize
     }
 
-    /// Start recording audio data into a history buffer of a certain length
-    pub fn start_recording(self, history_len: usize) -> crate::Result<AudioRecording> {
-        // Allocate history buffer
-        let (hist_input, hist_output) = RTHistory::new(history_len).split();
-
-        // Setup audio input port
+    /// Start recording audio data from a set of named input ports, each into
+    /// its own history buffer of a certain length
+    ///
+    /// If a high-pass filter is specified, each port's audio stream goes
+    /// through its own copy of this filter before being recorded.
+    ///
+    /// If a MIDI port name is specified, a MIDI input port is also set up,
+    /// and the note on/off and control change messages that it receives can
+    /// be read using `AudioRecording::read_midi()`.
+    ///
+    pub fn start_recording(
+        self,
+        history_len: usize,
+        port_names: &[&str],
+        high_pass: Option<HighPassFilter>,
+        midi_port_name: Option<&str>,
+    ) -> crate::Result<AudioRecording> {
+        // Setup audio input ports and the associated history buffers
+        assert!(!port_names.is_empty(), "Must record at least one input");
         let jack_client = self.0;
-        let input_port = jack_client.register_port("input", AudioIn)?;
+        let mut inputs = Vec::with_capacity(port_names.len());
+        let mut hist_outputs = Vec::with_capacity(port_names.len());
+        for &port_name in port_names {
+            let (hist_input, hist_output) = RTHistory::new(history_len).split();
+            inputs.push(RecordedInput {
+                port: jack_client.register_port(port_name, AudioIn)?,
+                history: hist_input,
+                high_pass: high_pass.clone(),
+            });
+            hist_outputs.push(hist_output);
+        }
+
+        // Setup the MIDI input port, if any
+        let (midi_input, received_midi) = match midi_port_name {
+            Some(port_name) => {
+                let (hist_input, hist_output) = RTHistory::new(MIDI_HISTORY_LEN).split();
+                let buffer = vec![0; hist_output.capacity()].into_boxed_slice();
+                (
+                    Some((jack_client.register_port(port_name, MidiIn)?, hist_input)),
+                    Some(ReceivedMidi {
+                        history: hist_output,
+                        buffer,
+                        last_clock: 0,
+                    }),
+                )
+            }
+            None => (None, None),
+        };
 
         // Prepare to handle audio thread errors
         let (error_input, error_output) = errors::setup_error_channel();
@@ -53,9 +107,11 @@ impl AudioSetup {
             sample_rate: jack_client.sample_rate() as Frames,
             error_input: error_input.clone(),
         };
+        let capacity = inputs[0].history.capacity();
         let process_handler = ProcessState {
-            input_port,
-            output_hist: hist_input,
+            inputs: inputs.into_boxed_slice(),
+            scratch: vec![0.0; capacity].into_boxed_slice(),
+            midi_input,
             error_input,
         };
         let _jack_client = jack_client.activate_async(notification_handler, process_handler)?;
@@ -64,9 +120,81 @@ impl AudioSetup {
         Ok(AudioRecording {
             _jack_client,
             error_output,
-            hist_output,
+            hist_outputs: hist_outputs.into_boxed_slice(),
+            received_midi,
         })
     }
+
+    /// Play a signal on the "output" port while recording the "input" port,
+    /// and return the recording once `record_len` samples have been recorded
+    ///
+    /// If port names are specified, the output port will be connected to
+    /// `playback_port` and the input port to `capture_port` before playback
+    /// starts. Otherwise, the user must have connected them beforehand, or
+    /// silence will be recorded.
+    ///
+    pub fn play_and_record(
+        self,
+        signal: Box<[f32]>,
+        record_len: usize,
+        playback_port: Option<&str>,
+        capture_port: Option<&str>,
+    ) -> crate::Result<Box<[f32]>> {
+        // Setup audio ports
+        let jack_client = self.0;
+        let output_port = jack_client.register_port("output", AudioOut)?;
+        let input_port = jack_client.register_port("input", AudioIn)?;
+        let output_port_name = output_port.name()?;
+        let input_port_name = input_port.name()?;
+
+        // Prepare to handle audio thread errors and playback progress
+        let (error_input, mut error_output) = errors::setup_error_channel();
+        let started = Arc::new(AtomicBool::new(false));
+        let done = Arc::new(AtomicBool::new(false));
+
+        // Start the audio threads, which will output silence until told to
+        // start the measurement
+        let notification_handler = NotificationState {
+            sample_rate: jack_client.sample_rate() as Frames,
+            error_input: error_input.clone(),
+        };
+        let process_handler = MeasurementState {
+            output_port,
+            input_port,
+            signal,
+            recording: vec![0.0; record_len].into_boxed_slice(),
+            position: 0,
+            started: started.clone(),
+            done: done.clone(),
+            error_input,
+        };
+        let jack_client = jack_client.activate_async(notification_handler, process_handler)?;
+
+        // Connect the ports if requested to, then start the measurement
+        if let Some(playback_port) = playback_port {
+            jack_client
+                .as_client()
+                .connect_ports_by_name(&output_port_name, playback_port)?;
+        }
+        if let Some(capture_port) = capture_port {
+            jack_client
+                .as_client()
+                .connect_ports_by_name(capture_port, &input_port_name)?;
+        }
+        started.store(true, Ordering::Release);
+
+        // Wait for the measurement to complete, watching for audio errors
+        while !done.load(Ordering::Acquire) {
+            if let Some(error) = error_output.next_error() {
+                anyhow::bail!("Audio thread error during measurement: {error:?}");
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // Retrieve the recording
+        let (_client, _notification_handler, process_handler) = jack_client.deactivate()?;
+        Ok(process_handler.recording)
+    }
 }
 
 /// Handle to an active audio recording pipeline
@@ -77,24 +205,85 @@ pub struct AudioRecording {
     /// Mechanism to query errors from the audio threads
     error_output: ErrorOutput,
 
-    /// Mechanism to read the latest audio history from the audio threads
-    hist_output: rt_history::Output<f32>,
+    /// Mechanism to read the latest audio history of each input port from the
+    /// audio threads, in the order where ports were specified
+    hist_outputs: Box<[rt_history::Output<f32>]>,
+
+    /// MIDI messages received by the audio threads, if MIDI input is enabled
+    received_midi: Option<ReceivedMidi>,
 }
 //
 impl AudioRecording {
-    /// Read latest audio history after checking for audio thread errors
+    /// Read latest audio history of an input port after checking for audio
+    /// thread errors
     pub fn read_history(
         &mut self,
+        port_idx: usize,
         target: &mut [f32],
     ) -> Result<Result<rt_history::Clock, Overrun>, AudioError> {
         if let Some(error) = self.error_output.next_error() {
             Err(error)
         } else {
-            Ok(self.hist_output.read(target))
+            Ok(self.hist_outputs[port_idx].read(target))
+        }
+    }
+
+    /// Append the MIDI messages received since the last call to a buffer
+    ///
+    /// If too many messages were received in between, the oldest ones are
+    /// lost. Nothing happens if MIDI input is not enabled.
+    ///
+    pub fn read_midi(&mut self, messages: &mut Vec<MidiMessage>) {
+        let midi = if let Some(midi) = &mut self.received_midi {
+            midi
+        } else {
+            return;
+        };
+        match midi.history.read(&mut midi.buffer) {
+            Ok(clock) => {
+                let num_new = clock.wrapping_sub(midi.last_clock).min(midi.buffer.len());
+                if num_new < clock.wrapping_sub(midi.last_clock) {
+                    log::warn!("Some MIDI messages were lost");
+                }
+                messages.extend(
+                    midi.buffer[midi.buffer.len() - num_new..]
+                        .iter()
+                        .map(|&packed| unpack_midi(packed)),
+                );
+                midi.last_clock = clock;
+            }
+            Err(Overrun { clock, .. }) => {
+                log::warn!("Some MIDI messages were lost");
+                midi.last_clock = clock;
+            }
         }
     }
 }
 
+/// MIDI messages that were received by the audio threads
+struct ReceivedMidi {
+    /// Packed MIDI messages, see `pack_midi()`
+    history: rt_history::Output<u32>,
+
+    /// Buffer for reading out the packed MIDI messages
+    buffer: Box<[u32]>,
+
+    /// Timestamp of the last message that was read out
+    last_clock: Clock,
+}
+
+/// Pack a MIDI message into an integer, so that it can go through a history
+/// buffer
+fn pack_midi(message: MidiMessage) -> u32 {
+    u32::from_le_bytes([message[0], message[1], message[2], 0])
+}
+
+/// Unpack a MIDI message that was packed by `pack_midi()`
+fn unpack_midi(packed: u32) -> MidiMessage {
+    let bytes = packed.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
+
 struct NotificationState {
     /// Last supported sample rate
     ///
@@ -125,12 +314,28 @@ impl NotificationHandler for NotificationState {
     }
 }
 
-struct ProcessState {
+/// Audio input that is being recorded
+struct RecordedInput {
     /// Port which input data is coming from
-    input_port: Port<AudioIn>,
+    port: Port<AudioIn>,
+
+    /// Output location to which the audio frames from this port are sent
+    history: rt_history::Input<f32>,
+
+    /// Filter that audio frames go through on the way, if any
+    high_pass: Option<HighPassFilter>,
+}
 
-    /// Output location to which audio frames are sent
-    output_hist: rt_history::Input<f32>,
+struct ProcessState {
+    /// Audio inputs that are being recorded
+    inputs: Box<[RecordedInput]>,
+
+    /// Scratch space for filtering audio frames, which is as large as the
+    /// history buffers and thus can hold any supported JACK buffer
+    scratch: Box<[f32]>,
+
+    /// MIDI input port, and destination of the messages that it receives
+    midi_input: Option<(Port<MidiIn>, rt_history::Input<u32>)>,
 
     /// Audio thread error notification mechanism
     error_input: ErrorInput,
@@ -140,12 +345,33 @@ impl ProcessHandler for ProcessState {
     fn process(&mut self, _: &jack::Client, process_scope: &ProcessScope) -> Control {
         // AssertUnwindSafe seems reasonable here because JACK will not call us
         // back if Control::Quit is returned and the state is not accessible
-        // after the thread has exited, except for output_hist but that can't
-        // be too badly corrupted by a panic.
+        // after the thread has exited, except for the history buffers but
+        // those can't be too badly corrupted by a panic.
         self.error_input.handle_panics(AssertUnwindSafe(|| {
-            // Forward new audio data from JACK into our history ring buffer
-            self.output_hist
-                .write(self.input_port.as_slice(process_scope));
+            // Forward new audio data from JACK into our history ring buffers
+            for input in self.inputs.iter_mut() {
+                let frames = input.port.as_slice(process_scope);
+                if let Some(high_pass) = &mut input.high_pass {
+                    let scratch = &mut self.scratch[..frames.len()];
+                    scratch.copy_from_slice(frames);
+                    high_pass.apply(scratch);
+                    input.history.write(scratch);
+                } else {
+                    input.history.write(frames);
+                }
+            }
+
+            // Forward note on/off and control change messages, which are the
+            // only ones that we care about, to the main thread
+            if let Some((port, history)) = &mut self.midi_input {
+                for event in port.iter(process_scope) {
+                    if let &[status, data1, data2] = event.bytes {
+                        if matches!(status & 0xF0, 0x80 | 0x90 | 0xB0) {
+                            history.write(&[pack_midi([status, data1, data2])]);
+                        }
+                    }
+                }
+            }
             Control::Continue
         }))
     }
@@ -155,26 +381,26 @@ impl ProcessHandler for ProcessState {
     fn buffer_size(&mut self, _: &jack::Client, size: Frames) -> Control {
         // AssertUnwindSafe seems reasonable for the same reason as above.
         self.error_input.handle_panics(AssertUnwindSafe(|| {
-            // FIXME: Implement support for reallocating self.output_hist storage,
+            // FIXME: Implement support for reallocating history buffer storage,
             //        this should be easy-ish to do since the buffer_size callback
             //        is allowed to do RT-unsafe things like allocating memory and
             //        the main thread has no RT-safety requirements.
             use log::{error, info, warn};
-            if size as usize > self.output_hist.capacity() {
+            let capacity = self.inputs[0].history.capacity();
+            debug_assert_eq!(capacity, self.scratch.len());
+            if size as usize > capacity {
                 error!(
                     "New JACK buffer size {size} is above history capacity {capacity}. \
-                     Must reallocate history buffer!",
-                    capacity = self.output_hist.capacity()
+                     Must reallocate history buffer!"
                 );
                 self.error_input
                     .notify_error(AudioError::MustReallocateHistory);
                 Control::Quit
             } else {
-                if size as usize > self.output_hist.capacity() / 4 {
+                if size as usize > capacity / 4 {
                     warn!(
                         "New JACK buffer size {size} is more than 1/4 of history capacity {capacity}. \
-                         Overruns are likely to occur. Should reallocate history buffer!",
-                        capacity = self.output_hist.capacity()
+                         Overruns are likely to occur. Should reallocate history buffer!"
                     );
                 } else {
                     info!("Switching to new supported JACK buffer size {size}");
@@ -184,3 +410,66 @@ impl ProcessHandler for ProcessState {
         }))
     }
 }
+
+struct MeasurementState {
+    /// Port which the measurement signal is sent to
+    output_port: Port<AudioOut>,
+
+    /// Port which the system response is coming from
+    input_port: Port<AudioIn>,
+
+    /// Measurement signal
+    signal: Box<[f32]>,
+
+    /// Recording of the system response
+    recording: Box<[f32]>,
+
+    /// Number of samples that have been played and recorded so far
+    position: usize,
+
+    /// Truth that the main thread is ready for the measurement to start
+    started: Arc<AtomicBool>,
+
+    /// Truth that the recording buffer is full
+    done: Arc<AtomicBool>,
+
+    /// Audio thread error notification mechanism
+    error_input: ErrorInput,
+}
+
+impl ProcessHandler for MeasurementState {
+    fn process(&mut self, _: &jack::Client, process_scope: &ProcessScope) -> Control {
+        // AssertUnwindSafe seems reasonable here because JACK will not call us
+        // back if Control::Quit is returned, and the recording only gets out
+        // of the audio thread once it is complete.
+        self.error_input.handle_panics(AssertUnwindSafe(|| {
+            // Output silence until the measurement has started and after the
+            // measurement signal has been fully played
+            let output = self.output_port.as_mut_slice(process_scope);
+            output.iter_mut().for_each(|x| *x = 0.0);
+            if !self.started.load(Ordering::Acquire) || self.done.load(Ordering::Relaxed) {
+                return Control::Continue;
+            }
+
+            // Play the next chunk of the measurement signal
+            if self.position < self.signal.len() {
+                let signal = &self.signal[self.position..];
+                let play_len = signal.len().min(output.len());
+                output[..play_len].copy_from_slice(&signal[..play_len]);
+            }
+
+            // Record the next chunk of the system response
+            let input = self.input_port.as_slice(process_scope);
+            let recording = &mut self.recording[self.position..];
+            let record_len = recording.len().min(input.len());
+            recording[..record_len].copy_from_slice(&input[..record_len]);
+            self.position += record_len;
+
+            // Tell the main thread when the recording is complete
+            if self.position == self.recording.len() {
+                self.done.store(true, Ordering::Release);
+            }
+            Control::Continue
+        }))
+    }
+}
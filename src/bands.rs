@@ -0,0 +1,480 @@
+//! Fractional-octave band analysis
+
+use crate::fourier::WindowProperties;
+use log::info;
+use std::fmt::{self, Display, Formatter};
+
+/// Octave frequency ratio of base-10 octave bands, as defined by IEC 61260-1
+const OCTAVE_RATIO: f64 = 1.995_262_3; // 10^(3/10)
+
+/// Reference frequency of IEC 61260-1 octave bands, in Hz
+const REFERENCE_FREQ: f64 = 1000.0;
+
+/// Preferred numbers from the R10 series, which give the nominal center
+/// frequencies of octave and third-octave bands (up to a power of ten)
+const R10_SERIES: [f32; 10] = [1.0, 1.25, 1.6, 2.0, 2.5, 3.15, 4.0, 5.0, 6.3, 8.0];
+
+/// Level that is reported outside of analyzed bands, in dBFS
+///
+/// This is far below anything that can be displayed, but unlike negative
+/// infinity it can be safely interpolated by the display resampler.
+///
+const MIN_LEVEL: f32 = -200.0;
+
+/// Fractional-octave band analyzer
+///
+/// Sums the power of the input signal within each of a set of standard
+/// fractional-octave bands (1/1, 1/3, 1/6 or 1/12 octave), as an acoustician's
+/// real-time analyzer would. The spectrum that is being analyzed must have
+/// uniformly spaced bins, such as the finest FFT of a `SteadyQTransform`.
+///
+pub struct OctaveBands {
+    /// Exact center frequency of each band in Hz
+    center_freqs: Box<[f32]>,
+
+    /// For each band, first spectrum bin that overlaps with it, and fraction
+    /// of each overlapping spectrum bin that falls inside of the band
+    bin_weights: Box<[(usize, Box<[f32]>)]>,
+
+    /// Factor that converts the sum of squared magnitudes across bins into a
+    /// mean square amplitude, relative to that of a full-scale sinusoid
+    power_norm: f32,
+
+    /// Power level of each band in dBFS
+    levels: Box<[f32]>,
+
+    /// Ratio between the upper edge and the center frequency of each band
+    half_band_ratio: f32,
+
+    /// Band levels, expanded back into the spectrum's bins
+    expanded: Box<[f32]>,
+
+    /// Band levels, drawn as a bar graph for display
+    bars: Vec<f32>,
+}
+//
+impl OctaveBands {
+    /// Prepare to analyze spectra with a certain number of bands per octave,
+    /// given the spectrum length, the properties of the window function, the
+    /// audio sampling rate and the range of band center frequencies in Hz
+    pub fn new(
+        bands_per_octave: usize,
+        spectrum_len: usize,
+        window: WindowProperties,
+        sample_rate: usize,
+        min_freq: f32,
+        max_freq: f32,
+    ) -> Self {
+        // Validate parameters
+        Self::check_params(bands_per_octave, sample_rate, min_freq, max_freq)
+            .unwrap_or_else(|e| panic!("{e}"));
+        assert!(spectrum_len >= 2);
+        let nyquist_freq = (sample_rate / 2) as f32;
+        let bin_width = nyquist_freq / (spectrum_len - 1) as f32;
+
+        // Enumerate band center frequencies
+        let half_band_ratio = half_band_ratio(bands_per_octave);
+        let center_freqs = center_freqs(bands_per_octave, nyquist_freq, min_freq, max_freq);
+        info!(
+            "Analyzing {} bands with nominal center frequencies {:?}",
+            center_freqs.len(),
+            center_freqs
+                .iter()
+                .map(|&freq| nominal_freq(freq, bands_per_octave))
+                .collect::<Vec<_>>()
+        );
+
+        // Determine which fraction of each spectrum bin falls into each band,
+        // considering that bin i covers frequencies from (i-0.5) to (i+0.5)
+        let bin_weights = center_freqs
+            .iter()
+            .map(|&center_freq| {
+                let start = center_freq / half_band_ratio / bin_width;
+                let end = center_freq * half_band_ratio / bin_width;
+                let first_bin = start.round() as usize;
+                let last_bin = (end.round() as usize).min(spectrum_len - 1);
+                let weights = (first_bin..=last_bin)
+                    .map(|bin| {
+                        let bin = bin as f32;
+                        ((bin + 0.5).min(end) - (bin - 0.5).max(start)).max(0.0)
+                    })
+                    .collect();
+                (first_bin, weights)
+            })
+            .collect();
+
+        // Allocate output buffers
+        let num_bands = center_freqs.len();
+        Self {
+            center_freqs,
+            bin_weights,
+            power_norm: 1.0 / window.noise_bandwidth,
+            levels: vec![MIN_LEVEL; num_bands].into_boxed_slice(),
+            half_band_ratio,
+            expanded: vec![MIN_LEVEL; spectrum_len].into_boxed_slice(),
+            bars: Vec::new(),
+        }
+    }
+
+    /// Check that a band analyzer can be set up with certain parameters,
+    /// which have the same meaning as in `new()`
+    ///
+    /// The constructor panics with the error reported by this function, so
+    /// it should be used when the parameters come from the user.
+    ///
+    pub fn check_params(
+        bands_per_octave: usize,
+        sample_rate: usize,
+        min_freq: f32,
+        max_freq: f32,
+    ) -> Result<(), BandsError> {
+        if ![1, 3, 6, 12].contains(&bands_per_octave) {
+            return Err(BandsError::BandsPerOctave(bands_per_octave));
+        }
+        if !(min_freq > 0.0 && max_freq > min_freq) {
+            return Err(BandsError::FreqRange((min_freq, max_freq)));
+        }
+        let nyquist = (sample_rate / 2) as f32;
+        if center_freqs(bands_per_octave, nyquist, min_freq, max_freq).is_empty() {
+            return Err(BandsError::NoBand {
+                bands_per_octave,
+                range: (min_freq, max_freq),
+                nyquist,
+            });
+        }
+        Ok(())
+    }
+
+    /// Exact center frequency of each band in Hz
+    pub fn center_freqs(&self) -> &[f32] {
+        &self.center_freqs[..]
+    }
+
+    /// Power level of each band in dBFS, as of the last analysis
+    pub fn levels(&self) -> &[f32] {
+        &self.levels[..]
+    }
+
+    /// Analyze a spectrum, given as magnitudes in dBFS, and return the band
+    /// levels expanded back into the spectrum's bins
+    ///
+    /// Every bin that mostly belongs to a band gets that band's level, bands
+    /// that are narrower than a bin get the bin that they overlap most, and
+    /// other bins get a very low level. See `bar_graph()` for display.
+    ///
+    pub fn analyze(&mut self, spectrum: &[f32]) -> &[f32] {
+        assert_eq!(spectrum.len(), self.expanded.len());
+        self.expanded.fill(MIN_LEVEL);
+        for (level, (first_bin, weights)) in self.levels.iter_mut().zip(self.bin_weights.iter()) {
+            // Integrate the power within this band
+            let bins = *first_bin..*first_bin + weights.len();
+            let power = spectrum[bins.clone()]
+                .iter()
+                .zip(weights.iter())
+                .map(|(&magnitude, &weight)| weight * 10.0f32.powf(magnitude / 10.0))
+                .sum::<f32>();
+            *level = (10.0 * (self.power_norm * power).log10()).max(MIN_LEVEL);
+
+            // Expand it into the bins that are mostly covered by this band, or
+            // at least into the one that it overlaps most
+            let mut covered = false;
+            for (dest, &weight) in self.expanded[bins.clone()].iter_mut().zip(weights.iter()) {
+                if weight >= 0.5 {
+                    *dest = *level;
+                    covered = true;
+                }
+            }
+            if !covered {
+                let nearest = weights
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, w1), (_, w2)| w1.partial_cmp(w2).expect("Weights can't be NaN"))
+                    .map(|(idx, _)| bins.start + idx);
+                if let Some(nearest) = nearest {
+                    self.expanded[nearest] = self.expanded[nearest].max(*level);
+                }
+            }
+        }
+        &self.expanded[..]
+    }
+
+    /// Draw the band levels of the last analysis as a bar graph, given the
+    /// center frequency of each display bin in Hz, in increasing order
+    ///
+    /// Each display bin gets the level of the band that its center frequency
+    /// falls into. Bars that span at least 3 display bins are separated from
+    /// the previous one by a gap, and visible bands that are too narrow to
+    /// contain any display bin center are drawn on the nearest display bin.
+    ///
+    pub fn bar_graph(&mut self, bin_freqs: &[f32]) -> &[f32] {
+        self.bars.clear();
+        self.bars.resize(bin_freqs.len(), MIN_LEVEL);
+        let (first_freq, last_freq) = match (bin_freqs.first(), bin_freqs.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return &self.bars[..],
+        };
+        for (&center_freq, &level) in self.center_freqs.iter().zip(self.levels.iter()) {
+            let start_freq = center_freq / self.half_band_ratio;
+            let end_freq = center_freq * self.half_band_ratio;
+            let start = bin_freqs.partition_point(|&freq| freq < start_freq);
+            let end = bin_freqs.partition_point(|&freq| freq < end_freq);
+            match end - start {
+                0 if end_freq >= first_freq && start_freq <= last_freq => {
+                    // Pick the display bin that is closest on a log scale
+                    let nearest = if start == bin_freqs.len()
+                        || (start > 0
+                            && center_freq / bin_freqs[start - 1] < bin_freqs[start] / center_freq)
+                    {
+                        start - 1
+                    } else {
+                        start
+                    };
+                    self.bars[nearest] = self.bars[nearest].max(level);
+                }
+                0 => {}
+                1 | 2 => self.bars[start..end].fill(level),
+                _ => self.bars[start + 1..end].fill(level),
+            }
+        }
+        &self.bars[..]
+    }
+}
+
+/// Reason why an [`OctaveBands`] analyzer cannot be set up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BandsError {
+    /// Only 1/1, 1/3, 1/6 and 1/12 octave bands are supported
+    BandsPerOctave(usize),
+
+    /// The frequency range is empty or starts at 0Hz
+    FreqRange((f32, f32)),
+
+    /// No band has its nominal center frequency in the frequency range and
+    /// its upper edge below the Nyquist frequency
+    NoBand {
+        /// Requested number of bands per octave
+        bands_per_octave: usize,
+
+        /// Requested frequency range in Hz
+        range: (f32, f32),
+
+        /// Nyquist frequency in Hz
+        nyquist: f32,
+    },
+}
+//
+impl Display for BandsError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::BandsPerOctave(bands) => write!(
+                f,
+                "Cannot analyze {bands} bands per octave, only 1, 3, 6 and 12 are supported"
+            ),
+            Self::FreqRange((min, max)) => write!(
+                f,
+                "Frequency range {min}-{max} Hz is invalid, it should be non-empty and \
+                 start above 0 Hz"
+            ),
+            Self::NoBand {
+                bands_per_octave,
+                range: (min, max),
+                nyquist,
+            } => write!(
+                f,
+                "No 1/{bands_per_octave}-octave band has a nominal center frequency between \
+                 {min} Hz and {max} Hz and fits below {nyquist} Hz, please widen the frequency \
+                 range or use narrower bands"
+            ),
+        }
+    }
+}
+//
+impl std::error::Error for BandsError {}
+
+/// Ratio between the upper edge and the center frequency of fractional-octave
+/// bands, given the number of bands per octave
+fn half_band_ratio(bands_per_octave: usize) -> f32 {
+    OCTAVE_RATIO.powf(0.5 / bands_per_octave as f64) as f32
+}
+
+/// Exact center frequencies of the fractional-octave bands whose nominal
+/// center frequency is in a certain range, and that fit below the Nyquist
+/// frequency, following IEC 61260-1 rules
+fn center_freqs(
+    bands_per_octave: usize,
+    nyquist_freq: f32,
+    min_freq: f32,
+    max_freq: f32,
+) -> Box<[f32]> {
+    let center_freq = |idx: i32| -> f32 {
+        let b = bands_per_octave as f64;
+        let exponent = if bands_per_octave % 2 == 1 {
+            idx as f64 / b
+        } else {
+            (2 * idx + 1) as f64 / (2.0 * b)
+        };
+        (REFERENCE_FREQ * OCTAVE_RATIO.powf(exponent)) as f32
+    };
+    let half_band_ratio = half_band_ratio(bands_per_octave);
+    let in_range = |freq: f32| nominal_freq(freq, bands_per_octave) >= min_freq;
+    let first_idx = (0..)
+        .map(|idx| -idx)
+        .take_while(|&idx| in_range(center_freq(idx)))
+        .last()
+        .unwrap_or(0);
+    (first_idx..)
+        .map(center_freq)
+        .skip_while(|&freq| !in_range(freq))
+        .take_while(|&freq| {
+            nominal_freq(freq, bands_per_octave) <= max_freq
+                && freq * half_band_ratio <= nyquist_freq
+        })
+        .collect()
+}
+
+/// Nominal center frequency of a fractional-octave band, given its exact
+/// center frequency and the number of bands per octave
+///
+/// For octave and third-octave bands, this is the standard frequency from the
+/// R10 series of preferred numbers. For narrower bands, the exact frequency
+/// is rounded to three significant digits.
+///
+pub fn nominal_freq(exact_freq: f32, bands_per_octave: usize) -> f32 {
+    let decade = 10.0f32.powf(exact_freq.log10().floor());
+    let mantissa = exact_freq / decade;
+    if bands_per_octave <= 3 {
+        let closest = |a: &&f32, b: &&f32| {
+            (mantissa / **a)
+                .ln()
+                .abs()
+                .partial_cmp(&(mantissa / **b).ln().abs())
+                .expect("Frequencies should not be NaN")
+        };
+        let nominal_mantissa = R10_SERIES
+            .iter()
+            .chain(std::iter::once(&10.0))
+            .min_by(closest)
+            .expect("There has to be preferred numbers");
+        nominal_mantissa * decade
+    } else {
+        (mantissa * 100.0).round() / 100.0 * decade
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::SteadyQTransform;
+    use more_asserts::*;
+
+    #[test]
+    fn third_octave_bands() {
+        // Set up a third-octave band analyzer
+        let sample_rate = 48_000;
+        let mut fourier = SteadyQTransform::new(1.0, 20.0, (20.0, 20_000.0), sample_rate, "hann");
+        let mut bands = OctaveBands::new(
+            3,
+            fourier.output_len(),
+            fourier.window_properties(),
+            sample_rate,
+            20.0,
+            20_000.0,
+        );
+
+        // Check that the standard bands are used
+        const NOMINAL_FREQS: [f32; 31] = [
+            20.0, 25.0, 31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0,
+            400.0, 500.0, 630.0, 800.0, 1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0,
+            5000.0, 6300.0, 8000.0, 10000.0, 12500.0, 16000.0, 20000.0,
+        ];
+        assert_eq!(bands.center_freqs().len(), NOMINAL_FREQS.len());
+        for (&exact, &nominal) in bands.center_freqs().iter().zip(NOMINAL_FREQS.iter()) {
+            assert_lt!((nominal_freq(exact, 3) / nominal - 1.0).abs(), 1e-6);
+        }
+
+        // Analyze a -6dBFS sine in the 1kHz band
+        let omega = std::f32::consts::TAU * 1030.0 / sample_rate as f32;
+        for (idx, x) in fourier.input().iter_mut().enumerate() {
+            *x = 0.5 * (omega * idx as f32).sin();
+        }
+        fourier.compute();
+        bands.analyze(fourier.finest_magnitudes());
+
+        // All the power should end up in the 1kHz band
+        for (&freq, &level) in bands.center_freqs().iter().zip(bands.levels()) {
+            if nominal_freq(freq, 3) == 1000.0 {
+                assert_lt!((level + 6.02).abs(), 0.05);
+            } else {
+                assert_lt!(level, -60.0);
+            }
+        }
+
+        // Bands are drawn as distinct bars, one display bin per 10 Hz here
+        let bin_freqs = (0..2400)
+            .map(|bin| 5.0 + 10.0 * bin as f32)
+            .collect::<Box<[_]>>();
+        let bars = bands.bar_graph(&bin_freqs);
+        let (start, end) = (891.0, 1122.0); // Edges of the 1kHz band
+        for (&freq, &level) in bin_freqs.iter().zip(bars) {
+            if freq > start + 10.0 && freq < end {
+                assert_lt!((level + 6.02).abs(), 0.05);
+            } else {
+                assert_lt!(level, -60.0);
+            }
+        }
+
+        // Bands that are narrower than one spectrum or display bin are still
+        // drawn, at 1/12 octave that is the case of the lowest ones
+        let mut bands = OctaveBands::new(
+            12,
+            fourier.output_len(),
+            fourier.window_properties(),
+            sample_rate,
+            20.0,
+            20_000.0,
+        );
+        let expanded = bands.analyze(&vec![0.0; fourier.output_len()]).to_owned();
+        let bars = bands.bar_graph(&bin_freqs).to_owned();
+        for (&freq, &level) in bands.center_freqs().iter().zip(bands.levels()) {
+            assert_gt!(level, MIN_LEVEL);
+            let bin_width = (sample_rate / 2) as f32 / (expanded.len() - 1) as f32;
+            let nearest_bin = (freq / bin_width).round() as usize;
+            assert!(
+                (nearest_bin.saturating_sub(1)..=nearest_bin + 1).any(|bin| expanded[bin] >= level)
+            );
+            let nearest_bar = ((freq - 5.0) / 10.0).round() as usize;
+            assert!((nearest_bar.saturating_sub(1)..=nearest_bar + 1)
+                .any(|bin| bars.get(bin).map_or(false, |&bar| bar >= level)));
+        }
+    }
+    #[test]
+    fn band_errors() {
+        // Frequency ranges must contain the nominal center of a band
+        assert!(OctaveBands::check_params(3, 48_000, 1200.0, 1900.0).is_ok());
+        assert_eq!(
+            OctaveBands::check_params(1, 48_000, 1200.0, 1900.0),
+            Err(BandsError::NoBand {
+                bands_per_octave: 1,
+                range: (1200.0, 1900.0),
+                nyquist: 24_000.0,
+            })
+        );
+
+        // ...and that band must fit below the Nyquist frequency
+        assert!(OctaveBands::check_params(1, 48_000, 10_000.0, 20_000.0).is_ok());
+        assert!(matches!(
+            OctaveBands::check_params(1, 32_000, 10_000.0, 16_000.0),
+            Err(BandsError::NoBand { .. })
+        ));
+
+        // Other invalid parameters are reported as such
+        assert_eq!(
+            OctaveBands::check_params(2, 48_000, 20.0, 20_000.0),
+            Err(BandsError::BandsPerOctave(2))
+        );
+        assert!(matches!(
+            OctaveBands::check_params(3, 48_000, 0.0, 20_000.0),
+            Err(BandsError::FreqRange(_))
+        ));
+    }
+}
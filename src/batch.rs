@@ -0,0 +1,197 @@
+//! Offline analysis of whole recordings into time-frequency matrices
+
+use crate::{analyzer::Analyzer, math::Float, Result};
+use std::io::Write;
+
+/// Time-frequency matrix of spectrum levels, with its axes
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrogram {
+    /// Time at the end of each analysis window, in s
+    times: Box<[f32]>,
+
+    /// Center frequency of each frequency bin, in Hz
+    freqs: Box<[f32]>,
+
+    /// Levels in dBFS, one row of frequency bins per analysis window
+    levels: Box<[f32]>,
+}
+//
+impl Spectrogram {
+    /// Analyze a whole recording with a fixed hop between consecutive analysis
+    /// windows, given in samples
+    ///
+    /// The recording is considered to be preceded by silence, so the first
+    /// analysis windows will be partially silent. Since the end of each
+    /// analysis window is a multiple of the hop, the last few samples are
+    /// ignored unless the recording length is a multiple of the hop.
+    ///
+    pub fn compute<T: Float>(analyzer: &mut Analyzer<T>, samples: &[f32], hop: usize) -> Self {
+        assert!(hop > 0);
+        let sample_rate = analyzer.sample_rate() as f32;
+        let num_frames = samples.len() / hop;
+        let freqs: Box<[f32]> = analyzer.output_freqs().into();
+        let mut times = Vec::with_capacity(num_frames);
+        let mut levels = Vec::with_capacity(num_frames * freqs.len());
+        for (idx, chunk) in samples.chunks_exact(hop).enumerate() {
+            analyzer.push_samples(chunk);
+            levels.extend_from_slice(analyzer.compute());
+            times.push(((idx + 1) * hop) as f32 / sample_rate);
+        }
+        Self {
+            times: times.into_boxed_slice(),
+            freqs,
+            levels: levels.into_boxed_slice(),
+        }
+    }
+
+    /// Time at the end of each analysis window, in s
+    pub fn times(&self) -> &[f32] {
+        &self.times[..]
+    }
+
+    /// Center frequency of each frequency bin, in Hz
+    pub fn freqs(&self) -> &[f32] {
+        &self.freqs[..]
+    }
+
+    /// Levels in dBFS, as a row-major matrix with one row per analysis window
+    /// and one column per frequency bin
+    pub fn levels(&self) -> &[f32] {
+        &self.levels[..]
+    }
+
+    /// Levels in dBFS of a certain analysis window
+    pub fn frame(&self, idx: usize) -> &[f32] {
+        let num_freqs = self.freqs.len();
+        &self.levels[idx * num_freqs..(idx + 1) * num_freqs]
+    }
+
+    /// Write the levels as CSV, with the time axis in the first column and
+    /// the frequency axis in the first row
+    pub fn write_csv(&self, mut writer: impl Write) -> Result<()> {
+        write!(writer, "time_s\\freq_hz")?;
+        for freq in self.freqs.iter() {
+            write!(writer, ",{freq}")?;
+        }
+        writeln!(writer)?;
+        for (idx, time) in self.times.iter().enumerate() {
+            write!(writer, "{time}")?;
+            for level in self.frame(idx) {
+                write!(writer, ",{level}")?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write the levels as a 2D NPY array (time x frequency)
+    ///
+    /// NPY files only contain one array, so the axes must be written
+    /// separately using `write_npy_times()` and `write_npy_freqs()`.
+    ///
+    pub fn write_npy(&self, writer: impl Write) -> Result<()> {
+        write_npy(
+            writer,
+            &[self.times.len(), self.freqs.len()],
+            &self.levels[..],
+        )
+    }
+
+    /// Write the time axis as a 1D NPY array
+    pub fn write_npy_times(&self, writer: impl Write) -> Result<()> {
+        write_npy(writer, &[self.times.len()], &self.times[..])
+    }
+
+    /// Write the frequency axis as a 1D NPY array
+    pub fn write_npy_freqs(&self, writer: impl Write) -> Result<()> {
+        write_npy(writer, &[self.freqs.len()], &self.freqs[..])
+    }
+}
+
+/// Write a row-major array of f32s with a certain shape in NPY format
+fn write_npy(mut writer: impl Write, shape: &[usize], data: &[f32]) -> Result<()> {
+    assert_eq!(shape.iter().product::<usize>(), data.len());
+
+    // Describe the array using a Python dict literal
+    let shape = shape
+        .iter()
+        .map(|dim| format!("{dim},"))
+        .collect::<String>();
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({shape}), }}");
+
+    // Pad the header with spaces and a newline, so that the array data is
+    // aligned on 64 bytes after the magic string, version and header length
+    const PREAMBLE_LEN: usize = 10;
+    let padded_len = (PREAMBLE_LEN + header.len() + 1 + 63) / 64 * 64 - PREAMBLE_LEN;
+    while header.len() < padded_len - 1 {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    // Write the file
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for x in data {
+        writer.write_all(&x.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resampler::ResamplingMode;
+    use more_asserts::*;
+
+    #[test]
+    fn sine_spectrogram() {
+        // Analyze 2s of a 1kHz sine with a 100ms hop
+        let sample_rate = 48_000;
+        let mut analyzer = Analyzer::builder(sample_rate)
+            .resampling(ResamplingMode::Max)
+            .output_len(64)
+            .build();
+        let omega = std::f32::consts::TAU * 1000.0 / sample_rate as f32;
+        let signal = (0..2 * sample_rate)
+            .map(|idx| 0.5 * (omega * idx as f32).sin())
+            .collect::<Vec<_>>();
+        let spectrogram = Spectrogram::compute(&mut analyzer, &signal, sample_rate / 10);
+
+        // Check the axes
+        assert_eq!(spectrogram.times().len(), 20);
+        assert_lt!((spectrogram.times()[19] - 2.0).abs(), 1e-6);
+        assert_eq!(spectrogram.freqs(), analyzer.output_freqs());
+        assert_eq!(spectrogram.levels().len(), 20 * 64);
+
+        // Once the sine has filled the analysis window, the peak should be
+        // at 1kHz in every frame
+        let first_full_frame = analyzer.input_len() / (sample_rate / 10);
+        assert_lt!(first_full_frame, 20);
+        for idx in first_full_frame..20 {
+            let frame = spectrogram.frame(idx);
+            let peak_bin = (0..frame.len())
+                .max_by(|&a, &b| frame[a].partial_cmp(&frame[b]).unwrap())
+                .unwrap();
+            assert_lt!((spectrogram.freqs()[peak_bin] / 1000.0 - 1.0).abs(), 0.1);
+        }
+
+        // Check the CSV output layout
+        let mut csv = Vec::new();
+        spectrogram.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 21);
+        assert!(csv.lines().all(|line| line.split(',').count() == 65));
+
+        // Check the NPY output layout
+        let mut npy = Vec::new();
+        spectrogram.write_npy(&mut npy).unwrap();
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&npy[10..10 + header_len]).unwrap();
+        assert!(header.contains("'shape': (20,64,)"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(npy.len(), 10 + header_len + 4 * 20 * 64);
+    }
+}
@@ -0,0 +1,286 @@
+//! Configuration file with named profiles
+//!
+//! The configuration file is a TOML file, located in
+//! `$XDG_CONFIG_HOME/spectre/config.toml` by default (or in
+//! `~/.config/spectre/config.toml` if `XDG_CONFIG_HOME` is not set). Each
+//! table of this file is a named profile, whose keys are the long names of
+//! command-line options and whose values are the option values:
+//!
+//! ```toml
+//! [room]
+//! min-freq = 20
+//! max-freq = 20000
+//! freq-res = 0.5
+//! smoothing = 6
+//! amp-range = 80
+//!
+//! [tuner]
+//! min-freq = 25
+//! max-freq = 4200
+//! freq-res = 0.1
+//! time-res = 100
+//! window = "blackman"
+//! resampling = "max"
+//!
+//! [speech]
+//! min-freq = 50
+//! max-freq = 8000
+//! freq-scale = "mel"
+//! weighting = "a"
+//! ```
+//!
+//! Boolean options such as `no-blend` are enabled by setting them to `true`.
+//! Options that are specified on the command line take precedence over those
+//! of the profile, and the profile named "default", if any, is used when no
+//! profile is specified. Flags that a profile enables can be turned back off
+//! from the command line with their negation, i.e. `--blend` and `--no-midi`.
+//!
+//! This module also defines the errors that are reported when the
+//! configuration, whether it comes from the command line or from a profile,
+//! is not valid.
+
+use crate::Result;
+use std::{
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use toml::{value::Table, Value};
+
+/// Name of the profile that is used when none is specified
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Options that cannot be set from a profile
+const FORBIDDEN_KEYS: &[&str] = &["config", "profile", "help", "version"];
+
+/// Parsed configuration file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config(Table);
+//
+impl Config {
+    /// Default location of the configuration file, if it can be determined
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_dir.join("spectre").join("config.toml"))
+    }
+
+    /// Load a configuration file
+    pub fn load(path: &Path) -> Result<Self> {
+        let config = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::format_err!("Failed to read {}: {e}", path.display()))?;
+        config
+            .parse()
+            .map_err(|e| anyhow::format_err!("Invalid configuration file {}: {e}", path.display()))
+    }
+
+    /// Translate a profile into command-line arguments, if it exists
+    pub fn profile_args(&self, name: &str) -> Result<Option<Vec<String>>> {
+        let profile = match self.0.get(name) {
+            Some(Value::Table(profile)) => profile,
+            Some(_) => anyhow::bail!("Profile {name} should be a table"),
+            None => return Ok(None),
+        };
+        let mut args = Vec::new();
+        for (key, value) in profile {
+            let option = key.replace('_', "-");
+            anyhow::ensure!(
+                !FORBIDDEN_KEYS.contains(&option.as_str()),
+                "Option {key} cannot be set from profile {name}"
+            );
+            let flag = format!("--{option}");
+            match value {
+                Value::String(s) => args.extend([flag, s.clone()]),
+                Value::Integer(i) => args.extend([flag, i.to_string()]),
+                Value::Float(f) => args.extend([flag, f.to_string()]),
+                Value::Boolean(true) => args.push(flag),
+                Value::Boolean(false) => {}
+                _ => anyhow::bail!(
+                    "Option {key} of profile {name} should be a string, number or boolean"
+                ),
+            }
+        }
+        Ok(Some(args))
+    }
+}
+//
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self(toml::from_str::<Table>(s)?))
+    }
+}
+
+/// Invalid configuration, detected before the analysis starts
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    /// A configuration file (profiles, MIDI mapping...) is invalid
+    File(String),
+
+    /// An option has an invalid value
+    InvalidValue {
+        /// Long name of the option
+        option: &'static str,
+
+        /// Value of the option
+        value: String,
+
+        /// Description of the valid values
+        expected: String,
+    },
+
+    /// Some options cannot be used together
+    Incompatible(&'static str),
+
+    /// A frequency cannot be probed at the current sampling rate
+    AboveNyquist {
+        /// Long name of the option
+        option: &'static str,
+
+        /// Requested frequency in Hz
+        freq: f32,
+
+        /// Sampling rate in Hz
+        sample_rate: usize,
+    },
+}
+//
+impl ConfigError {
+    /// Check that an option has a valid value
+    pub fn check_value(
+        valid: bool,
+        option: &'static str,
+        value: impl Display,
+        expected: impl Into<String>,
+    ) -> std::result::Result<(), Self> {
+        if valid {
+            Ok(())
+        } else {
+            Err(Self::InvalidValue {
+                option,
+                value: value.to_string(),
+                expected: expected.into(),
+            })
+        }
+    }
+
+    /// Check that options can be used together
+    pub fn check_compatible(valid: bool, reason: &'static str) -> std::result::Result<(), Self> {
+        if valid {
+            Ok(())
+        } else {
+            Err(Self::Incompatible(reason))
+        }
+    }
+
+    /// Check that a frequency can be probed at a certain sampling rate
+    pub fn check_nyquist(
+        option: &'static str,
+        freq: f32,
+        sample_rate: usize,
+    ) -> std::result::Result<(), Self> {
+        if freq <= (sample_rate / 2) as f32 {
+            Ok(())
+        } else {
+            Err(Self::AboveNyquist {
+                option,
+                freq,
+                sample_rate,
+            })
+        }
+    }
+}
+//
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::File(message) => write!(f, "{message}"),
+            Self::InvalidValue {
+                option,
+                value,
+                expected,
+            } => write!(
+                f,
+                "Invalid value {value} for --{option}, please specify {expected}"
+            ),
+            Self::Incompatible(reason) => write!(f, "{reason}"),
+            Self::AboveNyquist {
+                option,
+                freq,
+                sample_rate,
+            } => write!(
+                f,
+                "Frequency {freq} Hz (--{option}) can't be probed at a sampling rate of \
+                 {sample_rate} Hz, please specify a frequency up to {} Hz",
+                sample_rate / 2
+            ),
+        }
+    }
+}
+//
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles() {
+        let config = "
+            [tuner]
+            min-freq = 25
+            freq_res = 0.1
+            window = \"blackman\"
+            no-blend = true
+            print-plan = false
+
+            [broken]
+            amp-range = [20, 100]
+        "
+        .parse::<Config>()
+        .unwrap();
+
+        // Profiles are translated into command-line arguments
+        assert_eq!(
+            config.profile_args("tuner").unwrap(),
+            Some(
+                [
+                    "--freq-res",
+                    "0.1",
+                    "--min-freq",
+                    "25",
+                    "--no-blend",
+                    "--window",
+                    "blackman"
+                ]
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
+            )
+        );
+
+        // Missing profiles are reported as such, invalid ones are errors
+        assert_eq!(config.profile_args("room").unwrap(), None);
+        assert!(config.profile_args("broken").is_err());
+        assert!("[room]\nprofile = \"speech\""
+            .parse::<Config>()
+            .unwrap()
+            .profile_args("room")
+            .is_err());
+        assert!("[room".parse::<Config>().is_err());
+
+        // Configuration errors suggest valid values
+        let error = ConfigError::check_value(false, "freq-res", -1.0, "a positive frequency")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "Invalid value -1 for --freq-res, please specify a positive frequency"
+        );
+        assert!(ConfigError::check_nyquist("max-freq", 24_000.0, 48_000).is_ok());
+        assert!(ConfigError::check_nyquist("max-freq", 24_001.0, 48_000).is_err());
+    }
+}
@@ -0,0 +1,449 @@
+//! Local control socket, for reconfiguring spectre from scripts
+//!
+//! The protocol is line-based: clients send one command per line, and get
+//! one reply line per command, which starts with "ok" on success (possibly
+//! followed by a JSON payload for queries) and "error: " on failure.
+//!
+//! Supported commands are:
+//!
+//! - `amp-range <dB>`: set the displayed amplitude range
+//! - `freq-range <min Hz> <max Hz>`: set the displayed frequency range, which
+//!   is clamped to the displayable frequencies and widened to the maximal zoom
+//!   level as needed, and reply with the range that was actually applied as
+//!   `ok {"min_freq":...,"max_freq":...}`
+//! - `window <name>`: switch to another window function
+//! - `averages <count>`: set the number of transfer function averages (only
+//!   available in transfer function mode)
+//! - `pause` and `resume`: freeze and unfreeze the display
+//! - `spectrum`: query the latest displayed spectrum, as
+//!   `ok {"freqs":[...],"levels":[...]}`
+//! - `peaks [count]`: query the highest local maxima of the latest displayed
+//!   spectrum (5 by default), as `ok [{"freq":...,"level":...},...]`
+
+use crate::Result;
+use log::{debug, warn};
+use spectre::fourier;
+use std::{
+    fmt::Write as _,
+    io::{ErrorKind, Read, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Default number of peaks reported by the "peaks" command
+const DEFAULT_NUM_PEAKS: usize = 5;
+
+/// Maximal length of a command line, beyond which clients are disconnected
+const MAX_LINE_LEN: usize = 4096;
+
+/// Command received on the control socket
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControlCommand {
+    /// Set the displayed amplitude range in dB
+    AmpRange(f32),
+
+    /// Set the displayed frequency range in Hz
+    FreqRange(f32, f32),
+
+    /// Switch to another window function
+    Window(String),
+
+    /// Set the number of spectra averaged together in transfer mode
+    Averages(usize),
+
+    /// Stop updating the display
+    Pause,
+
+    /// Resume updating the display
+    Resume,
+
+    /// Query the latest displayed spectrum
+    Spectrum,
+
+    /// Query a certain number of peaks of the latest displayed spectrum
+    Peaks(usize),
+}
+//
+impl FromStr for ControlCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut words = s.split_whitespace();
+        let command = words
+            .next()
+            .ok_or_else(|| anyhow::format_err!("Empty command"))?;
+        let args = words.collect::<Vec<_>>();
+        let expect_args = |min: usize, max: usize| {
+            if (min..=max).contains(&args.len()) {
+                Ok(())
+            } else {
+                Err(anyhow::format_err!(
+                    "Wrong number of arguments to {command}"
+                ))
+            }
+        };
+        let parse_f32 = |arg: &str| -> Result<f32> {
+            let value = arg.parse::<f32>()?;
+            anyhow::ensure!(value.is_finite(), "{arg} is not a finite number");
+            Ok(value)
+        };
+        match command {
+            "amp-range" => {
+                expect_args(1, 1)?;
+                let amp_range = parse_f32(args[0])?.abs();
+                anyhow::ensure!(amp_range > 0.0, "Amplitude range must be nonzero");
+                Ok(Self::AmpRange(amp_range))
+            }
+            "freq-range" => {
+                expect_args(2, 2)?;
+                let (min_freq, max_freq) = (parse_f32(args[0])?, parse_f32(args[1])?);
+                anyhow::ensure!(
+                    min_freq >= 0.0 && max_freq > min_freq,
+                    "Frequency range must be nonnegative and nonempty"
+                );
+                Ok(Self::FreqRange(min_freq, max_freq))
+            }
+            "window" => {
+                expect_args(1, 1)?;
+                anyhow::ensure!(
+                    fourier::WINDOWS.contains(&args[0]),
+                    "Window type {} is not supported",
+                    args[0]
+                );
+                Ok(Self::Window(args[0].to_owned()))
+            }
+            "averages" => {
+                expect_args(1, 1)?;
+                let averages = args[0].parse::<usize>()?;
+                anyhow::ensure!(averages > 0, "Number of averages must be nonzero");
+                Ok(Self::Averages(averages))
+            }
+            "pause" => expect_args(0, 0).map(|()| Self::Pause),
+            "resume" => expect_args(0, 0).map(|()| Self::Resume),
+            "spectrum" => expect_args(0, 0).map(|()| Self::Spectrum),
+            "peaks" => {
+                expect_args(0, 1)?;
+                let num_peaks = args
+                    .first()
+                    .map_or(Ok(DEFAULT_NUM_PEAKS), |arg| arg.parse::<usize>())?;
+                Ok(Self::Peaks(num_peaks))
+            }
+            _ => Err(anyhow::format_err!("Command {command} is not supported")),
+        }
+    }
+}
+
+/// Command received from a control socket client, which expects a reply
+pub struct ControlRequest {
+    /// Command to be executed
+    pub command: ControlCommand,
+
+    /// Client that sent the command
+    client: usize,
+}
+
+/// Client of the control socket
+struct Client {
+    /// Connection to the client
+    stream: UnixStream,
+
+    /// Incoming data that does not form a full line yet
+    buffer: Vec<u8>,
+
+    /// Truth that the client may still send commands, which stops being the
+    /// case once it has closed its end of the connection
+    reading: bool,
+
+    /// Truth that the client can still receive replies
+    connected: bool,
+}
+//
+impl Client {
+    /// Send a reply line to the client, disconnect it on failure
+    fn send(&mut self, reply: &str) {
+        if !self.connected {
+            return;
+        }
+        let result = self
+            .stream
+            .write_all(reply.as_bytes())
+            .and_then(|()| self.stream.write_all(b"\n"));
+        if let Err(e) = result {
+            warn!("Failed to reply to control socket client: {e}");
+            self.connected = false;
+        }
+    }
+}
+
+/// Unix domain socket that accepts control commands
+///
+/// The socket is polled without blocking, typically once per displayed
+/// frame, and removed from the filesystem when this struct is dropped.
+///
+pub struct ControlSocket {
+    /// Filesystem path of the socket
+    path: PathBuf,
+
+    /// Listener for new connections
+    listener: UnixListener,
+
+    /// Currently connected clients
+    clients: Vec<Client>,
+}
+//
+impl ControlSocket {
+    /// Start listening for control commands at a certain filesystem path
+    ///
+    /// A socket left over by a previous run at the same path is replaced.
+    ///
+    pub fn bind(path: &Path) -> Result<Self> {
+        if std::fs::symlink_metadata(path).map_or(false, |meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path).map_err(|e| {
+            anyhow::format_err!("Failed to bind control socket {}: {e}", path.display())
+        })?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            path: path.to_owned(),
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    /// Accept new clients and collect the commands that they have sent
+    ///
+    /// Malformed commands are directly answered with an error. Every other
+    /// command must be answered using `reply()` before the next call to
+    /// `poll()`.
+    ///
+    pub fn poll(&mut self) -> Vec<ControlRequest> {
+        // Forget about clients that have disconnected, or that will not send
+        // commands anymore and have received all their replies
+        self.clients
+            .retain(|client| client.connected && client.reading);
+
+        // Accept new clients
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        warn!("Failed to set up control socket client: {e}");
+                        continue;
+                    }
+                    debug!("New control socket client");
+                    self.clients.push(Client {
+                        stream,
+                        buffer: Vec::new(),
+                        reading: true,
+                        connected: true,
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Failed to accept control socket client: {e}");
+                    break;
+                }
+            }
+        }
+
+        // Read and parse incoming commands
+        let mut requests = Vec::new();
+        for (idx, client) in self.clients.iter_mut().enumerate() {
+            let mut chunk = [0; 1024];
+            while client.reading {
+                match client.stream.read(&mut chunk) {
+                    // The client won't send anything else, but still expects
+                    // replies to its commands, including an unterminated one
+                    Ok(0) => {
+                        if client.buffer.last().map_or(false, |&b| b != b'\n') {
+                            client.buffer.push(b'\n');
+                        }
+                        client.reading = false;
+                    }
+                    Ok(len) => client.buffer.extend_from_slice(&chunk[..len]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        warn!("Failed to read from control socket client: {e}");
+                        client.connected = false;
+                        break;
+                    }
+                }
+            }
+            while let Some(end) = client.buffer.iter().position(|&b| b == b'\n') {
+                let line = client.buffer.drain(..=end).collect::<Vec<_>>();
+                let command = std::str::from_utf8(&line)
+                    .map_err(anyhow::Error::from)
+                    .and_then(ControlCommand::from_str);
+                match command {
+                    Ok(command) => {
+                        debug!("Got control command {command:?}");
+                        requests.push(ControlRequest {
+                            command,
+                            client: idx,
+                        });
+                    }
+                    Err(e) => client.send(&format!("error: {e}")),
+                }
+            }
+            if client.buffer.len() > MAX_LINE_LEN {
+                warn!("Control socket client sent an overly long line, disconnecting it");
+                client.connected = false;
+            }
+        }
+        requests
+    }
+
+    /// Reply to a previously received command
+    pub fn reply(&mut self, request: &ControlRequest, reply: Result<String>) {
+        let reply = match reply {
+            Ok(payload) if payload.is_empty() => "ok".to_owned(),
+            Ok(payload) => format!("ok {payload}"),
+            Err(e) => format!("error: {e}"),
+        };
+        self.clients[request.client].send(&reply);
+    }
+}
+//
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        // Not much can be done about failures at this point
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Format a spectrum as a JSON reply payload
+pub fn spectrum_payload(freqs: &[f32], levels: &[f32]) -> String {
+    let mut payload = String::from("{\"freqs\":");
+    write_json_array(&mut payload, freqs);
+    payload.push_str(",\"levels\":");
+    write_json_array(&mut payload, levels);
+    payload.push('}');
+    payload
+}
+
+/// Format a frequency range as a JSON reply payload
+pub fn range_payload(min_freq: f32, max_freq: f32) -> String {
+    format!("{{\"min_freq\":{min_freq},\"max_freq\":{max_freq}}}")
+}
+
+/// Find the highest local maxima of a spectrum, format them as a JSON reply
+/// payload
+pub fn peaks_payload(freqs: &[f32], levels: &[f32], num_peaks: usize) -> String {
+    assert_eq!(freqs.len(), levels.len());
+    let mut peaks = (0..levels.len())
+        .filter(|&bin| {
+            let level = levels[bin];
+            level.is_finite()
+                && (bin == 0 || levels[bin - 1] < level)
+                && (bin == levels.len() - 1 || levels[bin + 1] <= level)
+        })
+        .collect::<Vec<_>>();
+    peaks.sort_unstable_by(|&a, &b| {
+        levels[b]
+            .partial_cmp(&levels[a])
+            .expect("Peak levels should be finite")
+    });
+    let mut payload = String::from("[");
+    for (idx, &bin) in peaks.iter().take(num_peaks).enumerate() {
+        if idx > 0 {
+            payload.push(',');
+        }
+        write!(
+            payload,
+            "{{\"freq\":{},\"level\":{}}}",
+            freqs[bin], levels[bin]
+        )
+        .expect("Writing to a String should not fail");
+    }
+    payload.push(']');
+    payload
+}
+
+/// Append an array of numbers to a JSON string, writing the infinities and
+/// NaNs that JSON cannot represent as null
+fn write_json_array(output: &mut String, data: &[f32]) {
+    output.push('[');
+    for (idx, x) in data.iter().enumerate() {
+        if idx > 0 {
+            output.push(',');
+        }
+        if x.is_finite() {
+            write!(output, "{x}").expect("Writing to a String should not fail");
+        } else {
+            output.push_str("null");
+        }
+    }
+    output.push(']');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn socket_roundtrip() {
+        // Set up a control socket and connect to it
+        let path = std::env::temp_dir().join(format!("spectre-test-{}.sock", std::process::id()));
+        let mut socket = ControlSocket::bind(&path).unwrap();
+        let mut client = UnixStream::connect(&path).unwrap();
+        client
+            .write_all(b"freq-range 100 1000\nwindow bartlett\npeaks 1\n")
+            .unwrap();
+
+        // Valid commands are forwarded, invalid ones are rejected directly
+        let requests = socket.poll();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].command,
+            ControlCommand::FreqRange(100.0, 1000.0)
+        );
+        assert_eq!(requests[1].command, ControlCommand::Peaks(1));
+        socket.reply(&requests[0], Ok(range_payload(100.0, 1000.0)));
+        let levels = [-60.0, -10.0, -50.0, -20.0, -30.0];
+        let freqs = [1.0, 2.0, 3.0, 4.0, 5.0];
+        socket.reply(&requests[1], Ok(peaks_payload(&freqs, &levels, 1)));
+        let mut replies = BufReader::new(client).lines();
+        assert_eq!(
+            replies.next().unwrap().unwrap(),
+            "error: Window type bartlett is not supported"
+        );
+        assert_eq!(
+            replies.next().unwrap().unwrap(),
+            "ok {\"min_freq\":100,\"max_freq\":1000}"
+        );
+        assert_eq!(
+            replies.next().unwrap().unwrap(),
+            "ok [{\"freq\":2,\"level\":-10}]"
+        );
+
+        // Clients that stop sending commands still get replies, e.g. when
+        // using `echo peaks | socat - UNIX-CONNECT:<path>`
+        let mut client = UnixStream::connect(&path).unwrap();
+        client.write_all(b"peaks 1\npause").unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        let requests = socket.poll();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].command, ControlCommand::Pause);
+        socket.reply(&requests[0], Ok(peaks_payload(&freqs, &levels, 1)));
+        socket.reply(&requests[1], Ok(String::new()));
+        assert!(socket.poll().is_empty());
+        let mut replies = BufReader::new(client).lines();
+        assert_eq!(
+            replies.next().unwrap().unwrap(),
+            "ok [{\"freq\":2,\"level\":-10}]"
+        );
+        assert_eq!(replies.next().unwrap().unwrap(), "ok");
+        assert!(replies.next().is_none());
+
+        // The socket is cleaned up on drop
+        std::mem::drop(socket);
+        assert!(!path.exists());
+    }
+}
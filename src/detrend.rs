@@ -0,0 +1,209 @@
+//! Removal of DC offset and slow trends from the input signal
+
+use crate::{math::Float, Result};
+use std::str::FromStr;
+
+/// Default cutoff frequency of the high-pass filter in Hz
+const DEFAULT_HIGH_PASS_CUTOFF: f32 = 5.0;
+
+/// Strategy for removing DC offset and slow trends before Fourier analysis
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Detrending {
+    /// Leave the input untouched
+    ///
+    /// This is the right choice for DC-coupled sensors and infrasound, where
+    /// the lowest frequency bins carry useful information.
+    ///
+    None,
+
+    /// Subtract the mean of each Fourier transform input
+    Mean,
+
+    /// Subtract the least-squares linear fit of each Fourier transform input
+    ///
+    /// This also removes slow drifts, which would otherwise leak into the low
+    /// frequency bins through the window function's sidelobes.
+    ///
+    Linear,
+
+    /// Continuously high-pass filter the incoming audio stream, with a
+    /// certain cutoff frequency in Hz
+    ///
+    /// Unlike per-frame detrending, this does not introduce discontinuities
+    /// from one frame to the next when the DC offset fluctuates.
+    ///
+    HighPass(f32),
+}
+//
+impl Detrending {
+    /// Detrend a Fourier transform input in place
+    ///
+    /// This does nothing for high-pass filtering, which must be applied
+    /// continuously to the input stream using a `HighPassFilter` instead.
+    ///
+    pub fn apply<T: Float>(&self, input: &mut [T]) {
+        match self {
+            Self::None | Self::HighPass(_) => {}
+            Self::Mean => remove_mean(input),
+            Self::Linear => remove_linear_trend(input),
+        }
+    }
+
+    /// Set up the high-pass filter that must be applied to the input stream,
+    /// if any, given the audio sampling rate
+    pub fn high_pass_filter(&self, sample_rate: usize) -> Option<HighPassFilter> {
+        if let Self::HighPass(cutoff) = *self {
+            Some(HighPassFilter::new(cutoff, sample_rate))
+        } else {
+            None
+        }
+    }
+}
+//
+impl FromStr for Detrending {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Self::None),
+            "mean" => Ok(Self::Mean),
+            "linear" => Ok(Self::Linear),
+            "highpass" => Ok(Self::HighPass(DEFAULT_HIGH_PASS_CUTOFF)),
+            _ => {
+                let cutoff = s
+                    .strip_prefix("highpass:")
+                    .ok_or_else(|| anyhow::format_err!("Detrending mode {s} is not supported"))?
+                    .trim()
+                    .parse::<f32>()?;
+                if !(cutoff.is_finite() && cutoff > 0.0) {
+                    anyhow::bail!("High-pass cutoff frequency must be positive");
+                }
+                Ok(Self::HighPass(cutoff))
+            }
+        }
+    }
+}
+
+/// First-order high-pass filter, for continuously removing DC offset and
+/// infrasonic content from an audio stream
+#[derive(Clone, Debug)]
+pub struct HighPassFilter {
+    /// Feedback coefficient, i.e. RC / (RC + dt)
+    coefficient: f32,
+
+    /// Last input sample
+    last_input: f32,
+
+    /// Last output sample
+    last_output: f32,
+}
+//
+impl HighPassFilter {
+    /// Set up a filter with a certain cutoff frequency in Hz, given the audio
+    /// sampling rate
+    pub fn new(cutoff: f32, sample_rate: usize) -> Self {
+        assert!(cutoff.is_finite() && cutoff > 0.0);
+        assert!(cutoff < (sample_rate / 2) as f32);
+        let rc = 1.0 / (std::f32::consts::TAU * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Self {
+            coefficient: rc / (rc + dt),
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+
+    /// Filter a chunk of the audio stream in place
+    pub fn apply(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let input = *sample;
+            self.last_output = self.coefficient * (self.last_output + input - self.last_input);
+            self.last_input = input;
+            *sample = self.last_output;
+        }
+    }
+}
+
+/// Subtract the mean of a signal from it
+fn remove_mean<T: Float>(input: &mut [T]) {
+    let len = T::from_usize(input.len()).expect("Length should be representable");
+    let average = T::sum_fast(input) / len;
+    input.iter_mut().for_each(|elem| *elem -= average);
+}
+
+/// Subtract the least-squares linear fit of a signal from it
+fn remove_linear_trend<T: Float>(input: &mut [T]) {
+    // Fit x[n] = mean + slope * (n - center), accumulating in double precision
+    // since inputs can be hundreds of thousands of samples long
+    let len = input.len() as f64;
+    let center = (len - 1.0) / 2.0;
+    let (sum, weighted_sum) =
+        input
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(sum, weighted_sum), (idx, &x)| {
+                let x = x.to_f64().expect("Input should be representable");
+                (sum + x, weighted_sum + (idx as f64 - center) * x)
+            });
+    let mean = sum / len;
+    let slope = if input.len() > 1 {
+        weighted_sum * 12.0 / (len * (len * len - 1.0))
+    } else {
+        0.0
+    };
+    for (idx, x) in input.iter_mut().enumerate() {
+        let trend = mean + slope * (idx as f64 - center);
+        *x -= T::from_f64(trend).expect("Trend should be representable");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use more_asserts::*;
+
+    #[test]
+    fn detrending() {
+        // A sine with a DC offset and a linear drift
+        let sample_rate = 48_000;
+        let omega = std::f32::consts::TAU * 1000.0 / sample_rate as f32;
+        let sine = |idx: usize| 0.5 * (omega * idx as f32).sin();
+        let signal = (0..sample_rate)
+            .map(|idx| sine(idx) + 0.25 + 1e-5 * idx as f32)
+            .collect::<Vec<_>>();
+
+        // Mean removal only removes the offset
+        let mut mean_removed = signal.clone();
+        Detrending::Mean.apply(&mut mean_removed);
+        let average = mean_removed.iter().sum::<f32>() / mean_removed.len() as f32;
+        assert_lt!(average.abs(), 1e-4);
+        assert_gt!((mean_removed[0] - sine(0)).abs(), 0.2);
+
+        // Linear detrending recovers the sine, up to the residual of its own
+        // least-squares fit
+        let mut linear_removed = signal.clone();
+        Detrending::Linear.apply(&mut linear_removed);
+        for (idx, &x) in linear_removed.iter().enumerate() {
+            assert_lt!((x - sine(idx)).abs(), 1e-3);
+        }
+
+        // The high-pass filter leaves Fourier transform inputs alone, but once
+        // it has settled, it removes the offset and passes the sine through
+        let mut untouched = signal.clone();
+        let detrending = Detrending::from_str("highpass:5").unwrap();
+        detrending.apply(&mut untouched);
+        assert_eq!(untouched, signal);
+        let mut filter = detrending.high_pass_filter(sample_rate).unwrap();
+        let mut filtered = vec![0.25; sample_rate];
+        for chunk in filtered.chunks_mut(256) {
+            filter.apply(chunk);
+        }
+        assert_lt!(filtered.last().unwrap().abs(), 1e-3);
+        let mut filtered = (0..sample_rate).map(sine).collect::<Vec<_>>();
+        filter.apply(&mut filtered);
+        let peak = filtered[sample_rate / 2..]
+            .iter()
+            .fold(0.0f32, |acc, x| acc.max(x.abs()));
+        assert_lt!((peak / 0.5 - 1.0).abs(), 1e-2);
+    }
+}
@@ -1,17 +1,197 @@
 //! In-terminal spectrum display
 
 use crate::{
-    display::{FrameInput, FrameResult},
+    display::{
+        Display, FrameCallback, FrameInput, FrameResult, UserCommand, AMP_RANGE_STEP,
+        AVERAGES_FACTOR, PAN_STEP, ZOOM_STEP,
+    },
     Result,
 };
-use crossterm::{cursor, terminal, QueueableCommand};
+use colorous::INFERNO;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    style::{Color, Colors, ResetColor, SetColors},
+    terminal, QueueableCommand,
+};
 use std::{
+    collections::VecDeque,
+    fmt::Write as _,
     io::Write,
+    str::FromStr,
     time::{Duration, Instant},
 };
 
 /// Useful Unicode chars for in-terminal graphs
-const SPARKLINE: [&'static str; 9] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+const SPARKLINE: [&str; 9] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+
+/// Braille pattern without any raised dot
+const BRAILLE_BLANK: u32 = 0x2800;
+
+/// Bits of the Braille dots of a terminal char, indexed by dot column and row
+const BRAILLE_DOTS: [[u8; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+/// Upper half block, whose foreground and background colors respectively
+/// display the upper and lower spectrogram row of a terminal char
+const HALF_BLOCK: char = '▀';
+
+/// Number of colors of the spectrogram palette
+const PALETTE_LEN: usize = 256;
+
+/// Longest time interval that the spectrogram accounts for between two
+/// frames, so that it does not fill up with copies of the same spectrum after
+/// the display was frozen
+const MAX_SPECTROGRAM_GAP: Duration = Duration::from_millis(100);
+
+/// Summary of the key bindings, displayed at the bottom of the terminal
+const HELP: &str = "q: quit  space: freeze  +/-: zoom  \u{2190}/\u{2192}: pan  home: reset  \
+                    \u{2191}/\u{2193}: amp range  [/]: averages  w: weighting  v: view  \
+                    b: braille  h: hide help";
+
+/// What the terminal display shows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CliView {
+    /// Live spectrum, drawn as a bar chart
+    Spectrum,
+
+    /// Scrolling spectrogram, with the most recent spectrum at the top
+    Spectrogram,
+
+    /// Live spectrum above a scrolling spectrogram
+    Split,
+}
+//
+impl CliView {
+    /// View that the "v" key switches to
+    fn next(self) -> Self {
+        match self {
+            Self::Spectrum => Self::Spectrogram,
+            Self::Spectrogram => Self::Split,
+            Self::Split => Self::Spectrum,
+        }
+    }
+}
+//
+impl FromStr for CliView {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "spectrum" => Ok(Self::Spectrum),
+            "spectrogram" => Ok(Self::Spectrogram),
+            "split" => Ok(Self::Split),
+            _ => Err(anyhow::format_err!("Terminal view {s} is not supported")),
+        }
+    }
+}
+
+/// How the live spectrum is drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CliPlot {
+    /// Filled bars with 8 levels per char and one frequency bin per column
+    Bars,
+
+    /// Line plot of Braille dots, with 4 levels per char and two frequency
+    /// bins per column
+    Braille,
+}
+//
+impl CliPlot {
+    /// Number of frequency bins displayed by each terminal column
+    fn bins_per_column(self) -> usize {
+        match self {
+            Self::Bars => 1,
+            Self::Braille => BRAILLE_DOTS.len(),
+        }
+    }
+}
+//
+impl FromStr for CliPlot {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "bars" => Ok(Self::Bars),
+            "braille" => Ok(Self::Braille),
+            _ => Err(anyhow::format_err!(
+                "Terminal plot style {s} is not supported"
+            )),
+        }
+    }
+}
+
+/// Set up the spectrogram palette in the best colors that the terminal
+/// supports
+///
+/// Terminals that advertise 24-bit color support through the `COLORTERM`
+/// environment variable get the exact colormap, others get the closest
+/// colors of the 6x6x6 color cube of the 256-color palette.
+///
+fn spectrogram_palette() -> Box<[Color]> {
+    let truecolor = matches!(
+        std::env::var("COLORTERM").as_deref(),
+        Ok("truecolor" | "24bit")
+    );
+    (0..PALETTE_LEN)
+        .map(|idx| {
+            let color = INFERNO.eval_rational(idx, PALETTE_LEN);
+            if truecolor {
+                let colorous::Color { r, g, b } = color;
+                Color::Rgb { r, g, b }
+            } else {
+                Color::AnsiValue(cube_color(color))
+            }
+        })
+        .collect()
+}
+
+/// Index of the closest color of the 6x6x6 color cube of the 256-color
+/// palette, whose levels are 0, 95, 135, 175, 215 and 255
+fn cube_color(colorous::Color { r, g, b }: colorous::Color) -> u8 {
+    let cube_level = |c: u8| match c {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (c - 35) / 40,
+    };
+    16 + 36 * cube_level(r) + 6 * cube_level(g) + cube_level(b)
+}
+
+/// Raise the Braille dots of a line plot of a spectrum
+///
+/// `braille` receives one byte of Braille dots per terminal char, line by
+/// line, for a plot of `width` x `height` chars covering `amp_scale` dB
+/// below 0 dBFS. Each bin is drawn as a vertical run of dots joining its
+/// amplitude to that of the previous bin, so that the line stays connected
+/// across steep slopes. Amplitudes outside of the displayed range lie on the
+/// top or bottom row of dots.
+///
+fn braille_dots(data: &[f32], amp_scale: f32, width: usize, height: usize, braille: &mut Vec<u8>) {
+    // Clear the Braille dots from the previous frame
+    let dot_cols = BRAILLE_DOTS.len();
+    let dot_rows = BRAILLE_DOTS[0].len();
+    braille.clear();
+    braille.resize(width * height, 0);
+
+    // Raise the dots of the line plot
+    let num_dot_rows = height * dot_rows;
+    if num_dot_rows == 0 {
+        return;
+    }
+    let dot_amp_norm = num_dot_rows as f32 / amp_scale;
+    let dot_row = |bin: f32| {
+        let row = (-bin * dot_amp_norm).max(0.0);
+        (row as usize).min(num_dot_rows - 1)
+    };
+    let mut prev_row = data.first().map_or(0, |&bin| dot_row(bin));
+    for (dot_col, &bin) in data.iter().enumerate() {
+        let row = dot_row(bin);
+        for dot_row in row.min(prev_row)..=row.max(prev_row) {
+            let cell = (dot_row / dot_rows) * width + dot_col / dot_cols;
+            braille[cell] |= BRAILLE_DOTS[dot_col % dot_cols][dot_row % dot_rows];
+        }
+        prev_row = row;
+    }
+}
 
 /// In-terminal spectrum display
 pub struct CliDisplay {
@@ -21,9 +201,39 @@ pub struct CliDisplay {
     /// Terminal height
     height: u16,
 
+    /// Range of amplitudes that is displayed, in dB
+    amp_scale: f32,
+
     /// Terminal char height in dBFS
     char_amp_scale: f32,
 
+    /// Truth that the key bindings are displayed at the bottom
+    show_help: bool,
+
+    /// What is currently displayed
+    view: CliView,
+
+    /// How the live spectrum is drawn
+    plot: CliPlot,
+
+    /// Braille dots of the live spectrum, one byte per terminal char
+    braille: Vec<u8>,
+
+    /// Spectrogram colors, from the lowest to the highest amplitude
+    palette: Box<[Color]>,
+
+    /// Recently displayed spectra, from the most recent to the oldest
+    history: VecDeque<Box<[f32]>>,
+
+    /// Spectrogram refresh rate in rows per second
+    spectrogram_rate: f32,
+
+    /// Fraction of a spectrogram row that has not been recorded yet
+    pending_rows: f32,
+
+    /// Last time spectrogram rows were recorded
+    last_history: Instant,
+
     /// Spectrum display buffer
     spectrum: String,
 
@@ -33,9 +243,23 @@ pub struct CliDisplay {
 //
 impl CliDisplay {
     /// Set up the terminal display
-    pub fn new(amp_scale: f32) -> Result<Self> {
+    ///
+    /// The terminal is switched to raw mode, so that key presses (including
+    /// Ctrl+C) are received as commands rather than handled by the terminal.
+    ///
+    /// The spectrogram refresh rate is given in spectrogram rows per second,
+    /// each terminal char displaying two rows.
+    ///
+    pub fn new(
+        amp_scale: f32,
+        view: CliView,
+        plot: CliPlot,
+        spectrogram_rate: f32,
+    ) -> Result<Self> {
         assert!(amp_scale > 0.0);
+        assert!(spectrogram_rate > 0.0);
         let (width, height) = terminal::size().unwrap_or((80, 25));
+        terminal::enable_raw_mode()?;
         let stdout = std::io::stdout();
         let mut stdout = stdout.lock();
         stdout.queue(cursor::Hide)?;
@@ -51,77 +275,303 @@ impl CliDisplay {
                     .max()
                     .expect("There has to be sparkline chars"),
         );
-        Ok(Self {
+        let mut result = Self {
             width,
             height,
-            char_amp_scale: amp_scale / (height - 1) as f32,
+            amp_scale,
+            char_amp_scale: 0.0,
+            show_help: true,
+            view,
+            plot,
+            braille: Vec::new(),
+            palette: spectrogram_palette(),
+            history: VecDeque::new(),
+            spectrogram_rate,
+            pending_rows: 0.0,
+            last_history: Instant::now(),
             spectrum,
             last_display: Instant::now(),
-        })
+        };
+        result.update_char_amp_scale();
+        Ok(result)
+    }
+
+    /// Handle the terminal events that occurred since the last frame
+    ///
+    /// Returns None if the user asked to quit.
+    ///
+    fn poll_events(&mut self) -> Result<Option<FrameInput>> {
+        let mut frame_input = FrameInput {
+            new_spectrum_len: None,
+            commands: Vec::new(),
+        };
+        while event::poll(Duration::ZERO)? {
+            match event::read()? {
+                Event::Resize(width, height) => {
+                    self.width = width;
+                    self.height = height;
+                    self.update_char_amp_scale();
+                    self.history.clear();
+                    self.clear()?;
+                    frame_input.new_spectrum_len = Some(self.spectrum_len());
+                }
+                Event::Key(KeyEvent { code, modifiers }) => {
+                    let command = match code {
+                        KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                            return Ok(None)
+                        }
+                        KeyCode::Char(' ') => UserCommand::ToggleFreeze,
+                        KeyCode::Char('+' | '=') => UserCommand::ZoomFrequencies {
+                            center: 0.5,
+                            factor: ZOOM_STEP,
+                        },
+                        KeyCode::Char('-') => UserCommand::ZoomFrequencies {
+                            center: 0.5,
+                            factor: 1.0 / ZOOM_STEP,
+                        },
+                        KeyCode::Left => UserCommand::PanFrequencies { delta: -PAN_STEP },
+                        KeyCode::Right => UserCommand::PanFrequencies { delta: PAN_STEP },
+                        KeyCode::Home | KeyCode::Char('0') => UserCommand::ResetFrequencies,
+                        KeyCode::Up | KeyCode::PageUp => UserCommand::AdjustAmpRange {
+                            delta: AMP_RANGE_STEP,
+                        },
+                        KeyCode::Down | KeyCode::PageDown => UserCommand::AdjustAmpRange {
+                            delta: -AMP_RANGE_STEP,
+                        },
+                        KeyCode::Char(']') => UserCommand::ScaleAverages {
+                            factor: AVERAGES_FACTOR,
+                        },
+                        KeyCode::Char('[') => UserCommand::ScaleAverages {
+                            factor: 1.0 / AVERAGES_FACTOR,
+                        },
+                        KeyCode::Char('w') => UserCommand::CycleWeighting,
+                        KeyCode::Char('h' | '?') => {
+                            self.show_help = !self.show_help;
+                            self.update_char_amp_scale();
+                            self.clear()?;
+                            continue;
+                        }
+                        KeyCode::Char('b') => {
+                            self.plot = match self.plot {
+                                CliPlot::Bars => CliPlot::Braille,
+                                CliPlot::Braille => CliPlot::Bars,
+                            };
+                            frame_input.new_spectrum_len = Some(self.spectrum_len());
+                            continue;
+                        }
+                        KeyCode::Char('v') => {
+                            self.view = self.view.next();
+                            self.update_char_amp_scale();
+                            self.clear()?;
+                            continue;
+                        }
+                        _ => continue,
+                    };
+                    frame_input.commands.push(command);
+                }
+                Event::Mouse(_) => {}
+            }
+        }
+        Ok(Some(frame_input))
+    }
+
+    /// Update the amplitude scale of terminal chars after a change of the
+    /// amplitude range or of the spectrum height
+    fn update_char_amp_scale(&mut self) {
+        self.char_amp_scale = self.amp_scale / self.spectrum_height().max(1) as f32;
+    }
+
+    /// Clear the terminal, e.g. after a resize
+    fn clear(&mut self) -> Result<()> {
+        let mut stdout = std::io::stdout();
+        stdout.queue(terminal::Clear(terminal::ClearType::All))?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    /// Report the height of the plots in chars, leaving room for the status
+    /// line and the key bindings if they are displayed
+    fn plot_height(&self) -> u16 {
+        let reserved = 1 + u16::from(self.show_help);
+        self.height.saturating_sub(reserved).max(1)
+    }
+
+    /// Report live spectrum height in chars
+    fn spectrum_height(&self) -> u16 {
+        match self.view {
+            CliView::Spectrum => self.plot_height(),
+            CliView::Spectrogram => 0,
+            CliView::Split => (self.plot_height() + 1) / 2,
+        }
+    }
+
+    /// Report spectrogram height in chars
+    fn spectrogram_height(&self) -> u16 {
+        self.plot_height() - self.spectrum_height()
+    }
+
+    /// Record a spectrum into the spectrogram history, as many times as the
+    /// spectrogram refresh rate requires
+    ///
+    /// The spectrogram has one frequency bin per column, so when the live
+    /// spectrum has more, the loudest bin of each column is recorded.
+    ///
+    fn record_history(&mut self, data: &[f32]) {
+        let now = Instant::now();
+        let elapsed = (now - self.last_history).min(MAX_SPECTROGRAM_GAP);
+        self.last_history = now;
+        self.pending_rows += elapsed.as_secs_f32() * self.spectrogram_rate;
+        let max_rows = 2 * usize::from(self.plot_height());
+        let new_rows = (self.pending_rows as usize).min(max_rows);
+        self.pending_rows = self.pending_rows.fract();
+        if new_rows == 0 {
+            return;
+        }
+        let row = data
+            .chunks(self.plot.bins_per_column())
+            .map(|bins| bins.iter().copied().fold(f32::NEG_INFINITY, f32::max))
+            .collect::<Box<[f32]>>();
+        for _ in 0..new_rows {
+            self.history.push_front(row.clone());
+        }
+        self.history.truncate(max_rows);
+    }
+
+    /// Draw the live spectrum as filled bars
+    fn render_bars(&mut self, data: &[f32]) {
+        let char_amp_norm = 1. / self.char_amp_scale;
+        for row in 0..self.spectrum_height() {
+            let max_val = -(row as f32) * self.char_amp_scale;
+            let min_val = -(row as f32 + 1.0) * self.char_amp_scale;
+            for &bin in data {
+                let spark = if bin < min_val {
+                    SPARKLINE[0]
+                } else if bin >= max_val {
+                    SPARKLINE.last().expect("There has to be sparkline chars")
+                } else {
+                    let normalized = (bin - min_val) * char_amp_norm;
+                    let idx = (normalized * (SPARKLINE.len() - 2) as f32) as usize + 1;
+                    SPARKLINE[idx]
+                };
+                self.spectrum.push_str(spark);
+            }
+            // Raw mode does not turn line feeds into carriage returns
+            self.spectrum.push_str("\r\n");
+        }
+    }
+
+    /// Draw the live spectrum as a line plot of Braille dots
+    fn render_braille(&mut self, data: &[f32]) {
+        let width = usize::from(self.width);
+        braille_dots(
+            data,
+            self.amp_scale,
+            width,
+            usize::from(self.spectrum_height()),
+            &mut self.braille,
+        );
+        for line in self.braille.chunks(width.max(1)) {
+            for &dots in line {
+                self.spectrum.push(
+                    char::from_u32(BRAILLE_BLANK + u32::from(dots))
+                        .expect("Braille patterns should be valid chars"),
+                );
+            }
+            self.spectrum.push_str("\r\n");
+        }
+    }
+
+    /// Pick the spectrogram color of an amplitude
+    fn color(&self, amplitude: f32) -> Color {
+        let normalized = (1.0 + amplitude / self.amp_scale).clamp(0.0, 1.0);
+        let idx = (normalized * (self.palette.len() - 1) as f32).round() as usize;
+        self.palette[idx.min(self.palette.len() - 1)]
     }
 
+    /// Wait for the previous submitted spectrum to be displayed
+    fn wait_for_frame(&mut self) {
+        // CLI APIs don't do VSync, but we assume a max display rate of 144Hz
+        const MIN_REFRESH_PERIOD: Duration = Duration::from_millis(7);
+        let now = Instant::now();
+        let next_frame = self.last_display + MIN_REFRESH_PERIOD;
+        if now < next_frame {
+            std::thread::sleep(next_frame - now)
+        }
+        self.last_display = Instant::now();
+    }
+}
+//
+impl Display for CliDisplay {
     /// Report desired spectrum length in bins
-    pub fn spectrum_len(&self) -> usize {
-        self.width.into()
+    fn spectrum_len(&self) -> usize {
+        usize::from(self.width) * self.plot.bins_per_column()
+    }
+
+    /// Change the range of amplitudes that is displayed, in dB
+    fn set_amp_scale(&mut self, amp_scale: f32) -> Result<()> {
+        assert!(amp_scale > 0.0);
+        self.amp_scale = amp_scale;
+        self.update_char_amp_scale();
+        Ok(())
     }
 
     /// Start the event loop, run a user-provided callback on every frame
-    pub fn run_event_loop(
-        mut self,
-        mut frame_callback: impl FnMut(&mut Self, FrameInput) -> Result<FrameResult> + 'static,
-    ) -> ! {
+    fn run_event_loop(mut self: Box<Self>, mut frame_callback: Box<FrameCallback>) -> ! {
         let result = loop {
-            // FIXME: Support resizes. This requires polling terminal event,
-            //        which in turn requires enabling raw mode and handling
-            //        events like Ctrl+C ourselves. We'll need to do that anyway
-            //        in order to support keyboard commands.
-            match frame_callback(
-                &mut self,
-                FrameInput {
-                    new_display_width: None,
-                },
-            ) {
+            let frame_input = match self.poll_events() {
+                Ok(Some(frame_input)) => frame_input,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            match frame_callback(self.as_mut(), frame_input) {
                 Ok(FrameResult::Continue) => {}
                 Ok(FrameResult::Stop) => break Ok(()),
                 Err(e) => break Err(e),
             }
             self.wait_for_frame();
         };
-        self.reset_terminal().expect("Failed to reset the terminal");
-        result.expect("A failure occurred during event loop processing");
+        let result = self.reset_terminal().and(result);
         std::mem::drop(frame_callback);
-        std::process::exit(0)
+        crate::exit(result)
     }
 
     /// Display a spectrum
-    pub fn render(&mut self, data: &[f32]) -> Result<()> {
+    fn render(&mut self, data: &[f32]) -> Result<()> {
         // Validate input
-        assert_eq!(data.len(), self.width as usize);
-
-        // Cache some useful quantities
-        let char_amp_norm = 1. / self.char_amp_scale;
+        assert_eq!(data.len(), self.spectrum_len());
 
         // Prepare spectrum display
+        self.record_history(data);
         self.spectrum.clear();
-        for row in 0..self.spectrum_height() {
-            let max_val = -(row as f32) * self.char_amp_scale;
-            let min_val = -(row as f32 + 1.0) * self.char_amp_scale;
-            for &bin in data {
-                let spark = if bin < min_val {
-                    SPARKLINE[0]
-                } else if bin >= max_val {
-                    SPARKLINE
-                        .last()
-                        .expect("There has to be sparkline chars")
-                        .clone()
-                } else {
-                    let normalized = (bin - min_val) * char_amp_norm;
-                    let idx = (normalized * (SPARKLINE.len() - 2) as f32) as usize + 1;
-                    SPARKLINE[idx]
+        match self.plot {
+            CliPlot::Bars => self.render_bars(data),
+            CliPlot::Braille => self.render_braille(data),
+        }
+
+        // Prepare spectrogram display, rows that were not recorded yet are
+        // drawn in the color of the lowest amplitude
+        let mut colors = None;
+        for row in 0..usize::from(self.spectrogram_height()) {
+            for col in 0..usize::from(self.width) {
+                let amplitude = |idx: usize| {
+                    self.history
+                        .get(idx)
+                        .map_or(f32::NEG_INFINITY, |spectrum| spectrum[col])
                 };
-                self.spectrum.push_str(spark);
+                let new_colors = Colors::new(
+                    self.color(amplitude(2 * row)),
+                    self.color(amplitude(2 * row + 1)),
+                );
+                // Only emit escape sequences when the colors change
+                if colors != Some(new_colors) {
+                    write!(self.spectrum, "{}", SetColors(new_colors))?;
+                    colors = Some(new_colors);
+                }
+                self.spectrum.push(HALF_BLOCK);
             }
-            self.spectrum.push('\n');
+            write!(self.spectrum, "{ResetColor}\r\n")?;
+            colors = None;
         }
 
         // Display the rendered spectrum and clear status line
@@ -130,17 +580,61 @@ impl CliDisplay {
         stdout.queue(cursor::MoveTo(0, 0))?;
         write!(stdout, "{}", self.spectrum)?;
         stdout.queue(terminal::Clear(terminal::ClearType::CurrentLine))?;
+
+        // Display the key bindings, if enabled and if there is room for them
+        if self.show_help && self.height > 1 {
+            stdout.queue(cursor::MoveTo(0, self.height - 1))?;
+            stdout.queue(terminal::Clear(terminal::ClearType::CurrentLine))?;
+            let help = HELP.chars().take(self.width.into()).collect::<String>();
+            write!(stdout, "{help}")?;
+        }
         stdout.flush()?;
 
         // We're done
         Ok(())
     }
 
+    /// Rescale previously displayed data after a change of the visible
+    /// frequency range, given the new range in relative coordinates of the
+    /// old one
+    fn reframe_frequencies(&mut self, new_range: (f32, f32)) -> Result<()> {
+        // Resample the spectrogram history with nearest-neighbor interpolation,
+        // frequencies that were not displayed before get the lowest amplitude
+        let width = usize::from(self.width);
+        let (start, end) = new_range;
+        for spectrum in &mut self.history {
+            let old_spectrum = std::mem::replace(spectrum, vec![f32::NEG_INFINITY; width].into());
+            for (col, amplitude) in spectrum.iter_mut().enumerate() {
+                let relative = start + (col as f32 + 0.5) / width as f32 * (end - start);
+                let old_col = (relative * old_spectrum.len() as f32).floor();
+                if old_col >= 0.0 && old_col < old_spectrum.len() as f32 {
+                    *amplitude = old_spectrum[old_col as usize];
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Display a status message (e.g. measurement results) below the spectrum
+    ///
+    /// Since rendering a spectrum clears the status line, this should be
+    /// called again after every call to `render()`.
+    ///
+    fn report_status(&mut self, status: &str) -> Result<()> {
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        stdout.queue(cursor::MoveTo(0, self.plot_height()))?;
+        stdout.queue(terminal::Clear(terminal::ClearType::CurrentLine))?;
+        write!(stdout, "{status}")?;
+        stdout.flush()?;
+        Ok(())
+    }
+
     /// Report a buffer underrun (audio thread provided no new data)
-    pub fn report_underrun(&mut self) -> Result<()> {
+    fn report_underrun(&mut self) -> Result<()> {
         let stdout = std::io::stdout();
         let mut stdout = stdout.lock();
-        stdout.queue(cursor::MoveTo(0, self.spectrum_height()))?;
+        stdout.queue(cursor::MoveTo(0, self.plot_height()))?;
         stdout.queue(terminal::Clear(terminal::ClearType::CurrentLine))?;
         write!(stdout, "No new audio data since last refresh!")?;
         stdout.flush()?;
@@ -148,10 +642,10 @@ impl CliDisplay {
     }
 
     /// Report a buffer overrun (audio thread overwrote some data we were reading)
-    pub fn report_overrun(&mut self, excess_samples: usize) -> Result<()> {
+    fn report_overrun(&mut self, excess_samples: usize) -> Result<()> {
         let stdout = std::io::stdout();
         let mut stdout = stdout.lock();
-        stdout.queue(cursor::MoveTo(0, self.spectrum_height()))?;
+        stdout.queue(cursor::MoveTo(0, self.plot_height()))?;
         stdout.queue(terminal::Clear(terminal::ClearType::CurrentLine))?;
         write!(
             stdout,
@@ -166,31 +660,15 @@ impl CliDisplay {
     /// It is safe to call this function multiple times, but no other function
     /// should be called after it, or terminal corruption will occur.
     ///
-    pub fn reset_terminal(&mut self) -> Result<()> {
+    fn reset_terminal(&mut self) -> Result<()> {
         let mut stdout = std::io::stdout();
         stdout.queue(cursor::Show)?;
         stdout.queue(terminal::LeaveAlternateScreen)?;
         stdout.queue(terminal::EnableLineWrap)?;
         stdout.flush()?;
+        terminal::disable_raw_mode()?;
         Ok(())
     }
-
-    /// Report spectrum height in chars
-    fn spectrum_height(&self) -> u16 {
-        self.height - 1
-    }
-
-    /// Wait for the previous submitted spectrum to be displayed
-    fn wait_for_frame(&mut self) {
-        // CLI APIs don't do VSync, but we assume a max display rate of 144Hz
-        const MIN_REFRESH_PERIOD: Duration = Duration::from_millis(7);
-        let now = Instant::now();
-        let next_frame = self.last_display + MIN_REFRESH_PERIOD;
-        if now < next_frame {
-            std::thread::sleep(next_frame - now)
-        }
-        self.last_display = Instant::now();
-    }
 }
 //
 impl Drop for CliDisplay {
@@ -198,3 +676,62 @@ impl Drop for CliDisplay {
         self.reset_terminal().expect("Failed to reset the terminal");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_colors() {
+        // Colors map to the nearest level of the color cube on each axis
+        let cube = |r, g, b| cube_color(colorous::Color { r, g, b });
+        assert_eq!(cube(0, 0, 0), 16);
+        assert_eq!(cube(255, 255, 255), 231);
+        assert_eq!(cube(255, 0, 0), 196);
+        assert_eq!(cube(95, 135, 175), 67);
+        for (c, level) in [
+            (47, 0),
+            (48, 1),
+            (114, 1),
+            (115, 2),
+            (154, 2),
+            (155, 3),
+            (194, 3),
+            (195, 4),
+            (234, 4),
+            (235, 5),
+        ] {
+            assert_eq!(cube(0, 0, c), 16 + level);
+            assert_eq!(cube(0, c, 0), 16 + 6 * level);
+            assert_eq!(cube(c, 0, 0), 16 + 36 * level);
+        }
+    }
+
+    #[test]
+    fn braille_plot() {
+        // Two chars of 4x2 dots covering 40 dB, i.e. 10 dB per row of dots.
+        // Bins are joined by vertical runs, out-of-range values are clamped.
+        let mut braille = Vec::new();
+        braille_dots(&[0.0, -15.0, 5.0, -100.0], 40.0, 2, 1, &mut braille);
+        assert_eq!(
+            braille,
+            [
+                BRAILLE_DOTS[0][0] | BRAILLE_DOTS[1][0] | BRAILLE_DOTS[1][1],
+                BRAILLE_DOTS[0][0]
+                    | BRAILLE_DOTS[0][1]
+                    | BRAILLE_DOTS[1][0]
+                    | BRAILLE_DOTS[1][1]
+                    | BRAILLE_DOTS[1][2]
+                    | BRAILLE_DOTS[1][3],
+            ]
+        );
+
+        // Dots below the first line of chars go to the next one
+        braille_dots(&[-55.0, -55.0], 80.0, 1, 2, &mut braille);
+        assert_eq!(braille, [0, BRAILLE_DOTS[0][1] | BRAILLE_DOTS[1][1]]);
+
+        // Empty plots have no dots
+        braille_dots(&[-10.0], 40.0, 1, 0, &mut braille);
+        assert!(braille.is_empty());
+    }
+}
@@ -1,7 +1,10 @@
 //! Core context that you would find in pretty much any WGPU-based application
 
 use crate::{
-    display::gui::{Event, EventLoop},
+    display::{
+        gui::{Event, EventLoop},
+        UserCommand, AMP_RANGE_STEP, AVERAGES_FACTOR, PAN_STEP, ZOOM_STEP,
+    },
     Result,
 };
 use log::{debug, error, info, trace};
@@ -12,16 +15,24 @@ use wgpu::{
 };
 use winit::{
     dpi::PhysicalSize,
-    event::{ElementState, ModifiersState, VirtualKeyCode, WindowEvent},
+    event::{
+        ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+    },
     event_loop::ControlFlow,
     window::{Window, WindowBuilder},
 };
 
+/// Number of pixels of touchpad scrolling that count as one mouse wheel notch
+const PIXELS_PER_ZOOM_STEP: f64 = 50.0;
+
 /// Consequences of an event that was handled by the core context
 pub enum HighLevelEvent {
     /// A resize event occurred, possibly accompanied by a DPI change
     Resized { scale_factor_ratio: Option<f32> },
 
+    /// The user has sent a command
+    UserCommand(UserCommand),
+
     /// It is time to redraw the display
     Redraw,
 
@@ -51,6 +62,12 @@ pub struct CoreContext {
 
     /// Keyboard modifier state
     keyboard_modifiers: ModifiersState,
+
+    /// Last known mouse cursor vertical position, if inside of the window
+    cursor_y: Option<f64>,
+
+    /// Truth that the user is panning the frequency axis with the mouse
+    dragging: bool,
 }
 //
 impl CoreContext {
@@ -63,7 +80,7 @@ impl CoreContext {
             .with_visible(false)
             .with_transparent(false)
             // TODO: with_window_icon
-            .build(&event_loop)?;
+            .build(event_loop)?;
         let inner_size = window.inner_size();
         let scale_factor = window.scale_factor() as f32;
         info!(
@@ -181,6 +198,8 @@ impl CoreContext {
             device,
             queue,
             keyboard_modifiers: ModifiersState::default(),
+            cursor_y: None,
+            dragging: false,
         })
     }
 
@@ -214,6 +233,11 @@ impl CoreContext {
         self.window.set_visible(true);
     }
 
+    /// Change the window title
+    pub fn set_title(&mut self, title: &str) {
+        self.window.set_title(title);
+    }
+
     /// Process a winit event, tell the caller about events of particular interest
     pub fn handle_event(
         &mut self,
@@ -244,11 +268,110 @@ impl CoreContext {
                         match input.virtual_keycode {
                             Some(VirtualKeyCode::F4) if self.keyboard_modifiers.alt() => {
                                 *control_flow = ControlFlow::Exit;
+                                None
+                            }
+                            Some(VirtualKeyCode::W) => {
+                                Some(HighLevelEvent::UserCommand(UserCommand::CycleWeighting))
+                            }
+                            Some(
+                                VirtualKeyCode::Plus
+                                | VirtualKeyCode::Equals
+                                | VirtualKeyCode::NumpadAdd,
+                            ) => Some(HighLevelEvent::UserCommand(UserCommand::ZoomFrequencies {
+                                center: 0.5,
+                                factor: ZOOM_STEP,
+                            })),
+                            Some(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract) => {
+                                Some(HighLevelEvent::UserCommand(UserCommand::ZoomFrequencies {
+                                    center: 0.5,
+                                    factor: 1.0 / ZOOM_STEP,
+                                }))
+                            }
+                            Some(VirtualKeyCode::Up) => {
+                                Some(HighLevelEvent::UserCommand(UserCommand::PanFrequencies {
+                                    delta: PAN_STEP,
+                                }))
+                            }
+                            Some(VirtualKeyCode::Down) => {
+                                Some(HighLevelEvent::UserCommand(UserCommand::PanFrequencies {
+                                    delta: -PAN_STEP,
+                                }))
+                            }
+                            Some(VirtualKeyCode::Home) => {
+                                Some(HighLevelEvent::UserCommand(UserCommand::ResetFrequencies))
+                            }
+                            Some(VirtualKeyCode::Space) => {
+                                Some(HighLevelEvent::UserCommand(UserCommand::ToggleFreeze))
+                            }
+                            Some(VirtualKeyCode::PageUp) => {
+                                Some(HighLevelEvent::UserCommand(UserCommand::AdjustAmpRange {
+                                    delta: AMP_RANGE_STEP,
+                                }))
+                            }
+                            Some(VirtualKeyCode::PageDown) => {
+                                Some(HighLevelEvent::UserCommand(UserCommand::AdjustAmpRange {
+                                    delta: -AMP_RANGE_STEP,
+                                }))
+                            }
+                            Some(VirtualKeyCode::RBracket) => {
+                                Some(HighLevelEvent::UserCommand(UserCommand::ScaleAverages {
+                                    factor: AVERAGES_FACTOR,
+                                }))
+                            }
+                            Some(VirtualKeyCode::LBracket) => {
+                                Some(HighLevelEvent::UserCommand(UserCommand::ScaleAverages {
+                                    factor: 1.0 / AVERAGES_FACTOR,
+                                }))
                             }
                             _ => {
                                 trace!("Unhandled key-press event : {event:?}");
+                                None
                             }
                         }
+                    }
+
+                    // Handle mouse input: the wheel zooms the frequency axis
+                    // around the cursor, left click + drag pans it
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        let notches = match delta {
+                            MouseScrollDelta::LineDelta(_, y) => y,
+                            MouseScrollDelta::PixelDelta(position) => {
+                                (position.y / PIXELS_PER_ZOOM_STEP) as f32
+                            }
+                        };
+                        let center = self.cursor_y.map_or(0.5, |y| self.axis_position(y));
+                        Some(HighLevelEvent::UserCommand(UserCommand::ZoomFrequencies {
+                            center,
+                            factor: ZOOM_STEP.powf(notches),
+                        }))
+                    }
+                    //
+                    WindowEvent::MouseInput {
+                        state,
+                        button: MouseButton::Left,
+                        ..
+                    } => {
+                        self.dragging = state == ElementState::Pressed;
+                        None
+                    }
+                    //
+                    WindowEvent::CursorMoved { position, .. } => {
+                        let old_y = self.cursor_y.replace(position.y);
+                        match old_y {
+                            Some(old_y) if self.dragging && position.y != old_y => {
+                                let delta =
+                                    self.axis_position(old_y) - self.axis_position(position.y);
+                                Some(HighLevelEvent::UserCommand(UserCommand::PanFrequencies {
+                                    delta,
+                                }))
+                            }
+                            _ => None,
+                        }
+                    }
+                    //
+                    WindowEvent::CursorLeft { .. } => {
+                        self.cursor_y = None;
+                        self.dragging = false;
                         None
                     }
 
@@ -276,9 +399,7 @@ impl CoreContext {
 
                     // Ignore chatty events we don't care about
                     WindowEvent::AxisMotion { .. }
-                    | WindowEvent::CursorMoved { .. }
                     | WindowEvent::CursorEntered { .. }
-                    | WindowEvent::CursorLeft { .. }
                     | WindowEvent::Moved(_) => None,
 
                     // Log other events we don't handle yet
@@ -317,4 +438,11 @@ impl CoreContext {
     pub fn recreate_surface(&mut self) {
         self.surface.configure(&self.device, &self.surface_config);
     }
+
+    /// Translate a vertical window coordinate into a relative position along
+    /// the frequency axis, which goes from 0.0 at the bottom to 1.0 at the top
+    fn axis_position(&self, y: f64) -> f32 {
+        let max_y = (self.surface_config.height.max(2) - 1) as f64;
+        (1.0 - y / max_y) as f32
+    }
 }
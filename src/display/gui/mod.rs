@@ -7,7 +7,7 @@ mod spectrum;
 
 use self::{core::HighLevelEvent, spectrogram::Spectrogram, spectrum::Spectrum};
 use crate::{
-    display::{FrameInput, FrameResult},
+    display::{Display, FrameCallback, FrameInput, FrameResult, UserCommand},
     Result,
 };
 use crevice::std140::AsStd140;
@@ -15,8 +15,8 @@ use wgpu::{ShaderStages, SurfaceError, TextureViewDescriptor};
 use winit::event_loop::ControlFlow;
 
 /// Re-export some utility types for child modules
-pub(self) use self::core::CoreContext;
-pub(self) use self::settings::SettingsUniform;
+use self::core::CoreContext;
+use self::settings::SettingsUniform;
 
 /// Custom winit event type
 type CustomEvent = ();
@@ -55,6 +55,9 @@ pub struct GuiDisplay {
 
     /// Spectrum renderer
     spectrum: Spectrum,
+
+    /// Last status message, which is displayed in the window title
+    status: String,
 }
 //
 impl GuiDisplay {
@@ -62,6 +65,16 @@ impl GuiDisplay {
     pub fn new(amp_scale: f32, spectrogram_refresh_rate: f32) -> Result<Self> {
         assert!(amp_scale > 0.0);
 
+        // Without a window system, winit would panic instead of erroring out
+        #[cfg(all(
+            unix,
+            not(any(target_os = "macos", target_os = "ios", target_os = "android"))
+        ))]
+        anyhow::ensure!(
+            std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some(),
+            "No window system is available (neither DISPLAY nor WAYLAND_DISPLAY is set)"
+        );
+
         // Set up the event loop
         let event_loop = EventLoop::new();
 
@@ -104,36 +117,77 @@ impl GuiDisplay {
             settings,
             spectrogram,
             spectrum,
+            status: String::new(),
         })
     }
 
+    /// Reallocate structures that depend on the window size after a resize
+    fn handle_resize(&mut self) {
+        // Reallocate window surface
+        self.core_context.recreate_surface();
+
+        // Resize spectrogram texture
+        let mut encoder =
+            self.core_context
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Spectrum rescaling encoder"),
+                });
+        let spectrogram_texture_view = self
+            .spectrogram
+            .handle_resize(&self.core_context, &mut encoder);
+
+        // Resize live spectrum texture
+        self.spectrum
+            .handle_resize(&self.core_context, spectrogram_texture_view);
+
+        // Submit rescaling commands
+        self.core_context.queue().submit(Some(encoder.finish()));
+    }
+}
+//
+impl Display for GuiDisplay {
+    /// Change the range of amplitudes that is displayed, in dB
+    ///
+    /// Previously drawn spectrogram lines are not affected.
+    ///
+    fn set_amp_scale(&mut self, amp_scale: f32) -> Result<()> {
+        assert!(amp_scale > 0.0);
+        self.settings.replace(Settings {
+            spectrum_width: DEFAULT_SPECTRUM_WIDTH,
+            amp_scale,
+        });
+        Ok(())
+    }
+
     /// Report desired spectrum length in bins
-    pub fn spectrum_len(&self) -> usize {
+    fn spectrum_len(&self) -> usize {
         self.core_context.surface_config().height as _
     }
 
     /// Start the event loop, run a user-provided callback on every frame
-    pub fn run_event_loop(
-        mut self,
-        mut frame_callback: impl FnMut(&mut Self, FrameInput) -> Result<FrameResult> + 'static,
-    ) -> ! {
+    fn run_event_loop(mut self: Box<Self>, mut frame_callback: Box<FrameCallback>) -> ! {
         // Display the first frame
         let first_result = frame_callback(
-            &mut self,
+            self.as_mut(),
             FrameInput {
                 new_spectrum_len: None,
+                commands: Vec::new(),
             },
-        )
-        .expect("Failed to render first frame");
-        if first_result == FrameResult::Stop {
+        );
+        if first_result
+            .as_ref()
+            .map_or(true, |r| *r == FrameResult::Stop)
+        {
             std::mem::drop(frame_callback);
-            std::process::exit(0);
+            crate::exit(first_result.map(|_| ()));
         }
         self.core_context.show_window();
 
         // Start the event loop
         let mut frame_callback = Some(frame_callback);
         let mut resized = false;
+        let mut commands = Vec::<UserCommand>::new();
         self.event_loop
             .take()
             .expect("Event loop should be present")
@@ -149,10 +203,14 @@ impl GuiDisplay {
                         }
                     }
 
+                    // The user sent a command, forward it on the next frame
+                    Some(HighLevelEvent::UserCommand(command)) => commands.push(command),
+
                     // It is time to draw a new frame
                     Some(HighLevelEvent::Redraw) => {
                         let mut frame_input = FrameInput {
                             new_spectrum_len: None,
+                            commands: std::mem::take(&mut commands),
                         };
                         if resized {
                             frame_input.new_spectrum_len =
@@ -161,12 +219,15 @@ impl GuiDisplay {
                             resized = false;
                         }
                         match frame_callback.as_mut().expect("Callback should be present")(
-                            &mut self,
+                            self.as_mut(),
                             frame_input,
                         ) {
                             Ok(FrameResult::Continue) => {}
                             Ok(FrameResult::Stop) => *control_flow = ControlFlow::Exit,
-                            Err(e) => panic!("Frame processing failed: {e}"),
+                            Err(e) => {
+                                std::mem::drop(frame_callback.take());
+                                crate::exit(Err(e))
+                            }
                         }
                     }
 
@@ -174,11 +235,10 @@ impl GuiDisplay {
                     // the things that need dropping for correctness
                     Some(HighLevelEvent::Exit) => std::mem::drop(frame_callback.take()),
 
-                    // TODO: Provide some mouse controls: adjust spectrum width
-                    //       via click-drag around separator, spectrum and
-                    //       spectrogram zoom-in via click drag + zoom out via
-                    //       right click, zoom specific to time, frequency or
-                    //       magnitude scales once we have scale bars for those.
+                    // TODO: Provide more mouse controls: adjust spectrum width
+                    //       via click-drag around separator, zoom specific to
+                    //       time or magnitude scales once we have scale bars
+                    //       for those.
 
                     // This event need not concern us
                     None => {}
@@ -187,7 +247,7 @@ impl GuiDisplay {
     }
 
     /// Display a spectrum
-    pub fn render(&mut self, data: &[f32]) -> Result<()> {
+    fn render(&mut self, data: &[f32]) -> Result<()> {
         // Try to access the next window texture
         let window_texture = match self.core_context.current_surface_texture() {
             // Succeeded
@@ -220,7 +280,7 @@ impl GuiDisplay {
 
         // Send new spectrum data to the device
         let queue = self.core_context.queue();
-        self.spectrum.write_input(&queue, data);
+        self.spectrum.write_input(queue, data);
 
         // Move spectrogram forward if enough time elapsed
         let spectrogram_write_idx = self.spectrogram.write_idx();
@@ -283,33 +343,42 @@ impl GuiDisplay {
         Ok(())
     }
 
-    /// Restore the terminal to its initial state
-    pub fn reset_terminal(&mut self) -> Result<()> {
-        // The GUI backend does not alter the terminal state, so this is easy
-        Ok(())
-    }
-
-    /// Reallocate structures that depend on the window size after a resize
-    fn handle_resize(&mut self) {
-        // Reallocate window surface
-        self.core_context.recreate_surface();
-
-        // Resize spectrogram texture
+    /// Rescale previously displayed data after a change of the visible
+    /// frequency range, given the new range in relative coordinates of the
+    /// old one (i.e. 0.0 is the old minimum frequency and 1.0 is the old
+    /// maximum frequency)
+    fn reframe_frequencies(&mut self, new_range: (f32, f32)) -> Result<()> {
         let mut encoder =
             self.core_context
                 .device()
                 .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Spectrum rescaling encoder"),
+                    label: Some("Spectrogram reframing encoder"),
                 });
-        let spectrogram_texture_view = self
-            .spectrogram
-            .handle_resize(&self.core_context, &mut encoder);
-
-        // Resize live spectrum texture
+        let spectrogram_texture_view =
+            self.spectrogram
+                .handle_reframe(&self.core_context, &mut encoder, new_range);
         self.spectrum
             .handle_resize(&self.core_context, spectrogram_texture_view);
-
-        // Submit rescaling commands
         self.core_context.queue().submit(Some(encoder.finish()));
+        Ok(())
+    }
+
+    /// Display a status message (e.g. measurement results)
+    fn report_status(&mut self, status: &str) -> Result<()> {
+        // Changing the window title can be expensive, so only do it when the
+        // status has actually changed
+        if status != self.status {
+            self.status.clear();
+            self.status.push_str(status);
+            self.core_context
+                .set_title(&format!("Spectre - {}", self.status));
+        }
+        Ok(())
+    }
+
+    /// Restore the terminal to its initial state
+    fn reset_terminal(&mut self) -> Result<()> {
+        // The GUI backend does not alter the terminal state, so this is easy
+        Ok(())
     }
 }
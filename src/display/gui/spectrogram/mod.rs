@@ -138,7 +138,7 @@ impl Spectrogram {
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("Spectrogram pipeline layout"),
             bind_group_layouts: &[
-                &settings_bind_group_layout,
+                settings_bind_group_layout,
                 &sampler_bind_group_layout,
                 &texture_bind_group_layout,
             ],
@@ -246,6 +246,36 @@ impl Spectrogram {
         new_texture_view
     }
 
+    /// Handle a change of the visible frequency range, given the new range in
+    /// relative coordinates of the old one, return texture view to update
+    /// spectrogram writer
+    pub fn handle_reframe(
+        &mut self,
+        core_context: &CoreContext,
+        encoder: &mut CommandEncoder,
+        new_range: (f32, f32),
+    ) -> TextureView {
+        // Allocate a new spectrogram texture of the same size
+        let device = core_context.device();
+        let (new_texture, new_texture_view, new_texture_bind_group) =
+            Self::configure_texture(device, &self.texture_desc, &self.texture_bind_group_layout);
+
+        // Resample old spectrogram data into the new frequency range
+        self.resampler.encode_reframe(
+            core_context,
+            encoder,
+            &self.sampler_bind_group,
+            &self.texture_bind_group,
+            &new_texture,
+            new_range,
+        );
+
+        // Switch to the new texture
+        self.texture = new_texture;
+        self.texture_bind_group = new_texture_bind_group;
+        new_texture_view
+    }
+
     /// Handle DPI scale factor change
     pub fn handle_scale_factor_change(&mut self, scale_factor_ratio: f32) {
         self.refresh_period =
@@ -9,9 +9,9 @@ use wgpu::{
     BufferBindingType, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites,
     CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
     Extent3d, FragmentState, FrontFace, ImageCopyBuffer, ImageDataLayout, MultisampleState,
-    PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline,
-    RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, Texture,
-    TextureFormat, TextureViewDescriptor, VertexState,
+    PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, Texture, TextureFormat, TextureViewDescriptor, VertexState,
 };
 
 /// Downscaling pipeline settings
@@ -28,13 +28,26 @@ struct DownscaleSettings {
     new_spectrogram_stride: u32,
 }
 
+/// Reframing pipeline settings
+///
+/// Must be kept in sync with the reframing shader
+///
+#[derive(AsStd140)]
+struct ReframeSettings {
+    // Start and end of the new frequency range, in relative coordinates of
+    // the old frequency range
+    new_start: f32,
+    new_end: f32,
+}
+
 /// Downscaling workgroup length
 ///
 /// Must be ket in sync with the downscaling shader
 ///
 const DOWNSCALE_WORKGROUP_LEN: u32 = 256;
 
-/// Mechanism to resampler the spectrogram when the window is resized
+/// Mechanism to resample the spectrogram when the window is resized or the
+/// visible frequency range changes
 pub struct SpectrogramResampler {
     /// Upscaling pipeline
     upscale_pipeline: RenderPipeline,
@@ -50,6 +63,12 @@ pub struct SpectrogramResampler {
 
     /// Downscaling pipeline
     downscale_pipeline: ComputePipeline,
+
+    /// Reframing settings
+    reframe_settings: SettingsUniform<ReframeSettings>,
+
+    /// Reframing pipeline
+    reframe_pipeline: RenderPipeline,
 }
 //
 impl SpectrogramResampler {
@@ -74,40 +93,13 @@ impl SpectrogramResampler {
         });
 
         // Set up upscaling render pipeline
-        let upscale_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("Spectrogram upscaling pipeline"),
-            layout: Some(&upscale_pipeline_layout),
-            vertex: VertexState {
-                module: &upscale_shader,
-                entry_point: "vertex",
-                buffers: &[],
-            },
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleStrip,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: None,
-                unclipped_depth: false,
-                polygon_mode: PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            fragment: Some(FragmentState {
-                module: &upscale_shader,
-                entry_point: "fragment",
-                targets: &[ColorTargetState {
-                    format: spectrogram_format,
-                    blend: Some(BlendState::REPLACE),
-                    write_mask: ColorWrites::ALL,
-                }],
-            }),
-            multiview: None,
-        });
+        let upscale_pipeline = Self::create_render_pipeline(
+            device,
+            "Spectrogram upscaling pipeline",
+            &upscale_pipeline_layout,
+            &upscale_shader,
+            spectrogram_format,
+        );
 
         // Set up downscaling settings
         let (downscale_settings, downscale_settings_bind_group_layout) = SettingsUniform::new(
@@ -151,7 +143,7 @@ impl SpectrogramResampler {
             label: Some("Spectrogram downscaling pipeline layout"),
             bind_group_layouts: &[
                 &downscale_settings_bind_group_layout,
-                &texture_bind_group_layout,
+                texture_bind_group_layout,
                 &downscale_buffer_bind_group_layout,
             ],
             push_constant_ranges: &[],
@@ -165,6 +157,43 @@ impl SpectrogramResampler {
             entry_point: "downscale",
         });
 
+        // Set up reframing settings
+        let (reframe_settings, reframe_settings_bind_group_layout) = SettingsUniform::new(
+            device,
+            ReframeSettings {
+                new_start: 0.0,
+                new_end: 1.0,
+            },
+            ShaderStages::FRAGMENT,
+            "Spectrogram reframing",
+        );
+
+        // Load reframing shader
+        let reframe_shader = device.create_shader_module(&ShaderModuleDescriptor {
+            label: Some("Spectrogram reframing shaders"),
+            source: ShaderSource::Wgsl(include_str!("reframe.wgsl").into()),
+        });
+
+        // Set up reframing pipeline layout
+        let reframe_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Spectrogram reframing pipeline layout"),
+            bind_group_layouts: &[
+                &reframe_settings_bind_group_layout,
+                sampler_bind_group_layout,
+                texture_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        // Set up reframing render pipeline
+        let reframe_pipeline = Self::create_render_pipeline(
+            device,
+            "Spectrogram reframing pipeline",
+            &reframe_pipeline_layout,
+            &reframe_shader,
+            spectrogram_format,
+        );
+
         // ...and we're ready!
         Self {
             upscale_pipeline,
@@ -172,10 +201,60 @@ impl SpectrogramResampler {
             downscale_buffer_desc,
             downscale_buffer_bind_group_layout,
             downscale_pipeline,
+            reframe_settings,
+            reframe_pipeline,
         }
     }
 
+    /// Resample a spectrogram along the frequency axis after a change of the
+    /// visible frequency range, given the new range in relative coordinates
+    /// of the old one
+    ///
+    /// Frequencies that were not visible before end up transparent, and the
+    /// write index is unchanged since the time axis is left alone.
+    ///
+    pub fn encode_reframe(
+        &mut self,
+        core_context: &CoreContext,
+        encoder: &mut CommandEncoder,
+        sampler_bind_group: &BindGroup,
+        old_texture_bind_group: &BindGroup,
+        new_texture: &Texture,
+        (new_start, new_end): (f32, f32),
+    ) {
+        self.reframe_settings
+            .replace(ReframeSettings { new_start, new_end });
+        let settings_bind_group = self.reframe_settings.updated(core_context.queue());
+        let new_texture_view = new_texture.create_view(&TextureViewDescriptor {
+            label: Some("Spectrogram reframing texture view"),
+            ..Default::default()
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Spectrogram reframing render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &new_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.0,
+                        g: 0.0,
+                        b: 0.0,
+                        a: 0.0,
+                    }),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_bind_group(0, settings_bind_group, &[]);
+        render_pass.set_bind_group(1, sampler_bind_group, &[]);
+        render_pass.set_bind_group(2, old_texture_bind_group, &[]);
+        render_pass.set_pipeline(&self.reframe_pipeline);
+        render_pass.draw(0..4, 0..1);
+    }
+
     /// Rescale a spectrogram to a different height, return the new write index
+    #[allow(clippy::too_many_arguments)]
     pub fn encode_rescale(
         &mut self,
         core_context: &CoreContext,
@@ -258,6 +337,7 @@ impl SpectrogramResampler {
     }
 
     /// Downscale a spectrogram to a smaller height
+    #[allow(clippy::too_many_arguments)]
     fn encode_downscale(
         &mut self,
         core_context: &CoreContext,
@@ -333,4 +413,49 @@ impl SpectrogramResampler {
             },
         );
     }
+
+    /// Set up a render pipeline that draws a full-screen quad into a
+    /// spectrogram texture
+    fn create_render_pipeline(
+        device: &Device,
+        label: &str,
+        layout: &PipelineLayout,
+        shader: &ShaderModule,
+        spectrogram_format: TextureFormat,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "vertex",
+                buffers: &[],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: "fragment",
+                targets: &[ColorTargetState {
+                    format: spectrogram_format,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                }],
+            }),
+            multiview: None,
+        })
+    }
 }
@@ -0,0 +1,58 @@
+// Must be kept in sync with the main program
+struct SettingsUniform {
+    // Start and end of the new frequency range, in relative coordinates of
+    // the old frequency range (0.0 is the old first row, 1.0 the old last row)
+    new_start: f32;
+    new_end: f32;
+};
+//
+[[ group(0), binding(0) ]]
+var<uniform> settings: SettingsUniform;
+
+struct VertexOutput {
+    // Beware that position is given in [-1, 1] world coordinates
+    // by the vertex shader, but translated into absolute screen
+    // coordinates in pixels upon fragment shader invocation.
+    [[ builtin(position) ]] abs_pos: vec4<f32>;
+};
+
+[[ stage(vertex) ]]
+fn vertex([[ builtin(vertex_index) ]] vertex_idx: u32) -> VertexOutput {
+    // Emit a quad that covers the full screen
+    let rel_x = f32(vertex_idx % 2u);
+    let rel_y = f32(vertex_idx / 2u);
+    let x = 2.0 * rel_x - 1.0;
+    let y = 2.0 * rel_y - 1.0;
+    return VertexOutput(vec4<f32>(x, y, 0.5, 1.0));
+}
+
+// Spectrogram sampler with wrapping x axis
+[[ group(1), binding(0) ]]
+var spectrogram_sampler: sampler;
+
+// Old spectrogram texture
+[[ group(2), binding(0) ]]
+var old_spectrogram_texture: texture_2d<f32>;
+
+[[ stage(fragment) ]]
+fn fragment(in: VertexOutput) -> [[ location(0) ]] vec4<f32> {
+    // Rows of the spectrogram are evenly spaced in frequency scale units, so
+    // the position of a new row in the old frequency range is an affine
+    // function of its index. Columns are kept as-is.
+    let dims = vec2<f32>(textureDimensions(old_spectrogram_texture));
+    let new_rel_y = (in.abs_pos.y - 0.5) / (dims.y - 1.0);
+    let old_rel_y = mix(settings.new_start, settings.new_end, new_rel_y);
+    let old_abs_y = old_rel_y * (dims.y - 1.0) + 0.5;
+    let old_spectrogram_color = textureSample(
+        old_spectrogram_texture,
+        spectrogram_sampler,
+        vec2<f32>(in.abs_pos.x / dims.x, old_abs_y / dims.y)
+    );
+
+    // Frequencies that were not visible before are left transparent
+    if (old_rel_y >= 0.0 && old_rel_y <= 1.0) {
+        return old_spectrogram_color;
+    } else {
+        discard;
+    }
+}
@@ -183,7 +183,7 @@ impl Spectrum {
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("Spectrum pipeline layout"),
             bind_group_layouts: &[
-                &settings_bind_group_layout,
+                settings_bind_group_layout,
                 &static_bind_group_layout,
                 &sized_bind_group_layout,
             ],
@@ -228,7 +228,7 @@ impl Spectrum {
 
         // Set up size-dependent entities
         let (f16_input, input_texture, sized_bind_group) = Self::configure_sized_data(
-            &device,
+            device,
             &input_texture_desc,
             spectrogram_texture_view,
             &sized_bind_group_layout,
@@ -309,14 +309,14 @@ impl Spectrum {
             .collect();
 
         // Set up input texture and associated bind group
-        let input_texture = device.create_texture(&input_texture_desc);
+        let input_texture = device.create_texture(input_texture_desc);
         let input_texture_view = input_texture.create_view(&TextureViewDescriptor {
             label: Some("Spectrum input texture view"),
             ..Default::default()
         });
         let sized_bind_group = device.create_bind_group(&BindGroupDescriptor {
             label: Some("Spectrum size-sensitive bind group"),
-            layout: &sized_bind_group_layout,
+            layout: sized_bind_group_layout,
             entries: &[
                 BindGroupEntry {
                     binding: 0,
@@ -1,19 +1,135 @@
 //! Mechanisms for displaying the computed spectra
+//!
+//! Several display backends can be compiled in at once, all of which
+//! implement the [`Display`] trait so that the choice can be made at runtime.
+
+use crate::Result;
+
+#[cfg(not(any(feature = "cli", feature = "gui", feature = "stream")))]
+compile_error!("At least one display backend (cli, gui or stream) must be enabled");
 
 #[cfg(feature = "cli")]
 mod cli;
 #[cfg(feature = "gui")]
 mod gui;
+#[cfg(feature = "stream")]
+mod stream;
 
 #[cfg(feature = "cli")]
-pub use cli::CliDisplay;
+pub use cli::{CliDisplay, CliPlot, CliView};
 #[cfg(feature = "gui")]
 pub use gui::GuiDisplay;
+#[cfg(feature = "stream")]
+pub use stream::{StreamDisplay, StreamFormat};
+
+/// Zoom factor applied by one mouse wheel notch or zoom key press
+pub const ZOOM_STEP: f32 = 1.25;
+
+/// Fraction of the visible frequency range that a pan key press shifts by
+pub const PAN_STEP: f32 = 0.1;
+
+/// Amplitude range change applied by one amplitude key press, in dB
+pub const AMP_RANGE_STEP: f32 = 6.0;
+
+/// Factor by which one averaging key press changes the number of averages
+#[cfg(any(feature = "cli", feature = "gui"))]
+pub const AVERAGES_FACTOR: f32 = 2.0;
+
+/// Spectrum display
+pub trait Display {
+    /// Report desired spectrum length in bins
+    fn spectrum_len(&self) -> usize;
+
+    /// Change the range of amplitudes that is displayed, in dB
+    fn set_amp_scale(&mut self, amp_scale: f32) -> Result<()>;
+
+    /// Start the event loop, run a user-provided callback on every frame
+    ///
+    /// Once the callback asks to stop or fails, the process exits through
+    /// `crate::exit()`, so that errors get the same exit codes as those that
+    /// occur before the event loop starts.
+    ///
+    fn run_event_loop(self: Box<Self>, frame_callback: Box<FrameCallback>) -> !;
+
+    /// Display a spectrum
+    fn render(&mut self, data: &[f32]) -> Result<()>;
+
+    /// Rescale previously displayed data after a change of the visible
+    /// frequency range, given the new range in relative coordinates of the
+    /// old one (i.e. 0.0 is the old minimum frequency and 1.0 is the old
+    /// maximum frequency)
+    fn reframe_frequencies(&mut self, new_range: (f32, f32)) -> Result<()>;
+
+    /// Display a status message (e.g. measurement results)
+    fn report_status(&mut self, status: &str) -> Result<()>;
+
+    /// Report a buffer underrun (audio thread provided no new data)
+    ///
+    /// By default, buffer underruns and overruns are not reported.
+    ///
+    fn report_underrun(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Report a buffer overrun (audio thread overwrote some data we were
+    /// reading)
+    fn report_overrun(&mut self, _excess_samples: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Restore the terminal to its initial state
+    ///
+    /// It is safe to call this function multiple times, but no other function
+    /// should be called after it.
+    ///
+    fn reset_terminal(&mut self) -> Result<()>;
+}
+
+/// Callback that is run by the display's event loop on every frame
+pub type FrameCallback = dyn FnMut(&mut dyn Display, FrameInput) -> Result<FrameResult>;
 
 /// Input of the frame display hook
 pub struct FrameInput {
     /// New spectrum length (if any)
     pub new_spectrum_len: Option<usize>,
+
+    /// Commands that the user has sent since the previous frame
+    pub commands: Vec<UserCommand>,
+}
+
+/// Command sent by the user through the display's input devices
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UserCommand {
+    /// Switch to the next frequency weighting curve
+    CycleWeighting,
+
+    /// Zoom the frequency axis by a certain factor (> 1 to zoom in), keeping
+    /// a certain relative position of the axis in place (0.0 being the
+    /// minimum visible frequency and 1.0 the maximum visible frequency)
+    ZoomFrequencies { center: f32, factor: f32 },
+
+    /// Shift the frequency axis by a certain fraction of its visible width
+    /// (> 0 to move towards higher frequencies)
+    PanFrequencies { delta: f32 },
+
+    /// Go back to the initial frequency range
+    ResetFrequencies,
+
+    /// Freeze or unfreeze the display
+    ToggleFreeze,
+
+    /// Widen the displayed amplitude range by a certain amount in dB (< 0 to
+    /// narrow it down)
+    ///
+    /// This and the following commands are only sent by interactive displays.
+    ///
+    #[cfg_attr(not(any(feature = "cli", feature = "gui")), allow(dead_code))]
+    AdjustAmpRange { delta: f32 },
+
+    /// Multiply the number of averaged spectra by a certain factor (< 1 to
+    /// average less), where averaging is supported
+    #[cfg_attr(not(any(feature = "cli", feature = "gui")), allow(dead_code))]
+    ScaleAverages { factor: f32 },
 }
 
 /// Output of the frame display hook
@@ -0,0 +1,482 @@
+//! Headless spectrum streaming, for consumption by other programs
+
+use crate::{
+    display::{Display, FrameCallback, FrameInput, FrameResult},
+    Result,
+};
+use spectre::scale::FrequencyScale;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Record type tag of frequency axis headers in the binary format
+const BINARY_FREQS_TAG: u8 = 0;
+
+/// Record type tag of spectra in the binary format
+const BINARY_SPECTRUM_TAG: u8 = 1;
+
+/// Record type tag of status messages in the binary format
+const BINARY_STATUS_TAG: u8 = 2;
+
+/// Output format of the spectrum stream
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamFormat {
+    /// One JSON object per line
+    ///
+    /// Frequency axis headers look like `{"freqs":[...]}`, spectra look like
+    /// `{"time":...,"underrun":false,"overrun":0,"levels":[...]}` and status
+    /// messages look like `{"time":...,"status":"..."}`.
+    ///
+    JsonLines,
+
+    /// Comma-separated values
+    ///
+    /// Frequency axis headers are written as a `time_s,underrun,overrun,...`
+    /// header row followed by bin frequencies in Hz, and each spectrum is a
+    /// row of the table. Status messages are not written.
+    ///
+    Csv,
+
+    /// Compact little-endian binary records
+    ///
+    /// Every record starts with a one-byte tag. Frequency axis headers
+    /// (tag 0) are followed by a u32 bin count and the bin frequencies as
+    /// f32. Spectra (tag 1) are followed by an f64 timestamp, a u8 underrun
+    /// flag, a u32 overrun sample count, a u32 bin count and the levels as
+    /// f32. Status messages (tag 2) are followed by an f64 timestamp, a u32
+    /// byte count and UTF-8 text.
+    ///
+    Binary,
+}
+//
+impl FromStr for StreamFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" => Ok(Self::JsonLines),
+            "csv" => Ok(Self::Csv),
+            "binary" => Ok(Self::Binary),
+            _ => Err(anyhow::format_err!("Stream format {s} is not supported")),
+        }
+    }
+}
+
+/// Headless spectrum display, which writes spectra to stdout or a file
+///
+/// Timestamps are in seconds since the UNIX epoch. A frequency axis header
+/// is written before the first spectrum, and again whenever the visible
+/// frequency range changes. Xruns are reported through flags on the next
+/// spectrum that gets written.
+///
+pub struct StreamDisplay {
+    /// Output format
+    format: StreamFormat,
+
+    /// Output destination
+    output: Box<dyn Write>,
+
+    /// Number of bins in each spectrum
+    spectrum_len: usize,
+
+    /// Frequency scale of the spectra
+    scale: FrequencyScale,
+
+    /// Currently visible range of positions on the frequency scale
+    positions: (f32, f32),
+
+    /// Truth that the frequency axis header must be (re)written
+    header_pending: bool,
+
+    /// Truth that an underrun occurred since the last spectrum was written
+    underrun: bool,
+
+    /// Number of samples overwritten since the last spectrum was written
+    overrun: usize,
+
+    /// Last status message that was written
+    status: String,
+
+    /// Minimal time between two frames
+    frame_period: Duration,
+
+    /// Last frame timestamp
+    last_frame: Instant,
+}
+//
+impl StreamDisplay {
+    /// Set up the spectrum stream, given the output format and path (stdout
+    /// if unspecified), the number of bins per spectrum, the initial
+    /// frequency range and the maximal number of spectra per second
+    pub fn new(
+        format: StreamFormat,
+        output: Option<&Path>,
+        spectrum_len: usize,
+        scale: FrequencyScale,
+        (min_freq, max_freq): (f32, f32),
+        rate: f32,
+    ) -> Result<Self> {
+        assert!(spectrum_len > 0);
+        assert!(rate.is_finite() && rate > 0.0);
+        let output: Box<dyn Write> = match output {
+            Some(path) => Box::new(BufWriter::new(File::create(path).map_err(|e| {
+                anyhow::format_err!("Failed to create {}: {e}", path.display())
+            })?)),
+            None => Box::new(BufWriter::new(std::io::stdout())),
+        };
+        let positions = (scale.position(min_freq), scale.position(max_freq));
+        Ok(Self {
+            format,
+            output,
+            spectrum_len,
+            scale,
+            positions,
+            header_pending: true,
+            underrun: false,
+            overrun: 0,
+            status: String::new(),
+            frame_period: Duration::from_secs_f32(1.0 / rate),
+            last_frame: Instant::now(),
+        })
+    }
+
+    /// Write the frequency axis header
+    fn write_header(&mut self) -> Result<()> {
+        let (start, end) = self.positions;
+        let freqs = (0..self.spectrum_len)
+            .map(|bin| {
+                let rel_pos = (bin as f32 + 0.5) / self.spectrum_len as f32;
+                self.scale.frequency(start + rel_pos * (end - start))
+            })
+            .collect::<Vec<_>>();
+        match self.format {
+            StreamFormat::JsonLines => {
+                write!(self.output, "{{\"freqs\":")?;
+                write_json_array(&mut self.output, &freqs)?;
+                writeln!(self.output, "}}")?;
+            }
+            StreamFormat::Csv => {
+                write!(self.output, "time_s,underrun,overrun")?;
+                for freq in freqs {
+                    write!(self.output, ",{freq}")?;
+                }
+                writeln!(self.output)?;
+            }
+            StreamFormat::Binary => {
+                self.output.write_all(&[BINARY_FREQS_TAG])?;
+                write_binary_array(&mut self.output, &freqs)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until it's time to produce the next spectrum
+    fn wait_for_frame(&mut self) {
+        let now = Instant::now();
+        let next_frame = self.last_frame + self.frame_period;
+        if now < next_frame {
+            std::thread::sleep(next_frame - now)
+        }
+        self.last_frame = Instant::now();
+    }
+}
+//
+impl Display for StreamDisplay {
+    /// Report desired spectrum length in bins
+    fn spectrum_len(&self) -> usize {
+        self.spectrum_len
+    }
+
+    /// Change the range of amplitudes that is displayed, in dB
+    fn set_amp_scale(&mut self, amp_scale: f32) -> Result<()> {
+        // Streamed levels are not clipped to the amplitude range
+        assert!(amp_scale > 0.0);
+        Ok(())
+    }
+
+    /// Start the event loop, run a user-provided callback on every frame
+    fn run_event_loop(mut self: Box<Self>, mut frame_callback: Box<FrameCallback>) -> ! {
+        let result = loop {
+            match frame_callback(
+                self.as_mut(),
+                FrameInput {
+                    new_spectrum_len: None,
+                    commands: Vec::new(),
+                },
+            ) {
+                Ok(FrameResult::Continue) => {}
+                Ok(FrameResult::Stop) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+            self.wait_for_frame();
+        };
+        let result = result.and_then(|()| Ok(self.output.flush()?));
+        std::mem::drop(frame_callback);
+        crate::exit(result.or_else(|e| {
+            // The consumer going away (e.g. `spectre | head`) ends the stream
+            let broken_pipe = e.chain().any(|e| {
+                e.downcast_ref::<std::io::Error>()
+                    .map_or(false, |e| e.kind() == std::io::ErrorKind::BrokenPipe)
+            });
+            if broken_pipe {
+                Ok(())
+            } else {
+                Err(e)
+            }
+        }))
+    }
+
+    /// Write a spectrum
+    fn render(&mut self, data: &[f32]) -> Result<()> {
+        // Validate input
+        assert_eq!(data.len(), self.spectrum_len);
+
+        // Write the frequency axis header if needed
+        if self.header_pending {
+            self.write_header()?;
+            self.header_pending = false;
+        }
+
+        // Write the spectrum, along with xruns that occurred since the last one
+        let time = timestamp();
+        match self.format {
+            StreamFormat::JsonLines => {
+                write!(
+                    self.output,
+                    "{{\"time\":{time},\"underrun\":{},\"overrun\":{},\"levels\":",
+                    self.underrun, self.overrun
+                )?;
+                write_json_array(&mut self.output, data)?;
+                writeln!(self.output, "}}")?;
+            }
+            StreamFormat::Csv => {
+                write!(
+                    self.output,
+                    "{time},{},{}",
+                    self.underrun as u8, self.overrun
+                )?;
+                for level in data {
+                    write!(self.output, ",{level}")?;
+                }
+                writeln!(self.output)?;
+            }
+            StreamFormat::Binary => {
+                self.output.write_all(&[BINARY_SPECTRUM_TAG])?;
+                self.output.write_all(&time.to_le_bytes())?;
+                self.output.write_all(&[self.underrun as u8])?;
+                self.output
+                    .write_all(&(self.overrun as u32).to_le_bytes())?;
+                write_binary_array(&mut self.output, data)?;
+            }
+        }
+        self.underrun = false;
+        self.overrun = 0;
+
+        // Make sure the spectrum reaches the consumer promptly
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Rescale previously displayed data after a change of the visible
+    /// frequency range, given the new range in relative coordinates of the
+    /// old one
+    fn reframe_frequencies(&mut self, new_range: (f32, f32)) -> Result<()> {
+        // Spectra are not kept around, but the frequency axis must be updated
+        let (start, end) = self.positions;
+        let absolute = |rel: f32| start + rel * (end - start);
+        self.positions = (absolute(new_range.0), absolute(new_range.1));
+        self.header_pending = true;
+        Ok(())
+    }
+
+    /// Write a status message (e.g. measurement results)
+    ///
+    /// Unchanged status messages are only written once.
+    ///
+    fn report_status(&mut self, status: &str) -> Result<()> {
+        if status == self.status {
+            return Ok(());
+        }
+        self.status.clear();
+        self.status.push_str(status);
+        let time = timestamp();
+        match self.format {
+            StreamFormat::JsonLines => {
+                write!(self.output, "{{\"time\":{time},\"status\":")?;
+                write_json_string(&mut self.output, status)?;
+                writeln!(self.output, "}}")?;
+            }
+            StreamFormat::Csv => {}
+            StreamFormat::Binary => {
+                self.output.write_all(&[BINARY_STATUS_TAG])?;
+                self.output.write_all(&time.to_le_bytes())?;
+                self.output
+                    .write_all(&(status.len() as u32).to_le_bytes())?;
+                self.output.write_all(status.as_bytes())?;
+            }
+        }
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Report a buffer underrun (audio thread provided no new data)
+    fn report_underrun(&mut self) -> Result<()> {
+        self.underrun = true;
+        Ok(())
+    }
+
+    /// Report a buffer overrun (audio thread overwrote some data we were reading)
+    fn report_overrun(&mut self, excess_samples: usize) -> Result<()> {
+        self.overrun += excess_samples;
+        Ok(())
+    }
+
+    /// Restore the terminal to its initial state
+    fn reset_terminal(&mut self) -> Result<()> {
+        // The stream backend does not alter the terminal state, but this is
+        // the last chance to get buffered output out before exiting
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// Current time in seconds since the UNIX epoch
+fn timestamp() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock should be after the UNIX epoch")
+        .as_secs_f64()
+}
+
+/// Write an array of numbers in JSON format
+///
+/// JSON has no representation for infinities and NaNs (e.g. the level of a
+/// silent bin), so these are written as null.
+///
+fn write_json_array(output: &mut impl Write, data: &[f32]) -> Result<()> {
+    write!(output, "[")?;
+    for (idx, x) in data.iter().enumerate() {
+        if idx > 0 {
+            write!(output, ",")?;
+        }
+        if x.is_finite() {
+            write!(output, "{x}")?;
+        } else {
+            write!(output, "null")?;
+        }
+    }
+    write!(output, "]")?;
+    Ok(())
+}
+
+/// Write a string in JSON format
+fn write_json_string(output: &mut impl Write, s: &str) -> Result<()> {
+    write!(output, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(output, "\\\"")?,
+            '\\' => write!(output, "\\\\")?,
+            '\n' => write!(output, "\\n")?,
+            c if c.is_control() => write!(output, "\\u{:04x}", c as u32)?,
+            c => write!(output, "{c}")?,
+        }
+    }
+    write!(output, "\"")?;
+    Ok(())
+}
+
+/// Write an array of numbers in the binary format, preceded by its length
+fn write_binary_array(output: &mut impl Write, data: &[f32]) -> Result<()> {
+    output.write_all(&(data.len() as u32).to_le_bytes())?;
+    for x in data {
+        output.write_all(&x.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Output buffer that remains accessible after being given to a display
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+    //
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Write a header, a spectrum (after an underrun) and a status message
+    /// in a certain format, return what was written
+    fn stream(format: StreamFormat) -> Vec<u8> {
+        let mut display =
+            StreamDisplay::new(format, None, 2, FrequencyScale::Linear, (0.0, 100.0), 10.0)
+                .unwrap();
+        let buffer = SharedBuffer::default();
+        display.output = Box::new(buffer.clone());
+        display.report_underrun().unwrap();
+        display.render(&[-10.0, f32::NEG_INFINITY]).unwrap();
+        display.report_status("THD: \"1%\"").unwrap();
+        buffer.0.take()
+    }
+
+    #[test]
+    fn framings() {
+        // JSON lines
+        let output = String::from_utf8(stream(StreamFormat::JsonLines)).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "{\"freqs\":[25,75]}");
+        assert!(lines[1].starts_with("{\"time\":"));
+        assert!(lines[1].ends_with(",\"underrun\":true,\"overrun\":0,\"levels\":[-10,null]}"));
+        assert!(lines[2].starts_with("{\"time\":"));
+        assert!(lines[2].ends_with(",\"status\":\"THD: \\\"1%\\\"\"}"));
+
+        // CSV, where status messages are not written
+        let output = String::from_utf8(stream(StreamFormat::Csv)).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines, ["time_s,underrun,overrun,25,75", lines[1]]);
+        let fields = lines[1].split(',').collect::<Vec<_>>();
+        assert!(fields[0].parse::<f64>().unwrap() > 0.0);
+        assert_eq!(fields[1..], ["1", "0", "-10", "-inf"]);
+
+        // Binary records
+        let output = stream(StreamFormat::Binary);
+        let mut input = &output[..];
+        let mut take = |len: usize| {
+            let (head, tail) = input.split_at(len);
+            input = tail;
+            head.to_owned()
+        };
+        let u32_at = |bytes: Vec<u8>| u32::from_le_bytes(bytes.try_into().unwrap());
+        let f32_at = |bytes: Vec<u8>| f32::from_le_bytes(bytes.try_into().unwrap());
+        assert_eq!(take(1), [BINARY_FREQS_TAG]);
+        assert_eq!(u32_at(take(4)), 2);
+        assert_eq!([f32_at(take(4)), f32_at(take(4))], [25.0, 75.0]);
+        assert_eq!(take(1), [BINARY_SPECTRUM_TAG]);
+        assert!(f64::from_le_bytes(take(8).try_into().unwrap()) > 0.0);
+        assert_eq!(take(1), [1]);
+        assert_eq!(u32_at(take(4)), 0);
+        assert_eq!(u32_at(take(4)), 2);
+        assert_eq!(
+            [f32_at(take(4)), f32_at(take(4))],
+            [-10.0, f32::NEG_INFINITY]
+        );
+        assert_eq!(take(1), [BINARY_STATUS_TAG]);
+        assert!(f64::from_le_bytes(take(8).try_into().unwrap()) > 0.0);
+        assert_eq!(u32_at(take(4)), 9);
+        assert_eq!(take(9), b"THD: \"1%\"");
+        assert!(input.is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+//! Harmonic distortion and noise measurements
+
+use crate::fourier::WindowProperties;
+
+/// Distortion analyzer
+///
+/// Locates the fundamental of a test tone in a spectrum, integrates the energy
+/// of its harmonics and of the residual noise within a certain bandwidth, and
+/// deduces the usual distortion and noise figures of merit. For best results,
+/// the spectrum should be computed with a window that has low spectral leakage
+/// and accurate amplitudes, such as the flat-top window.
+///
+pub struct DistortionAnalyzer {
+    /// Width of spectrum bins in Hz
+    bin_width: f32,
+
+    /// Factor that converts the sum of squared magnitudes across bins into a
+    /// mean square amplitude, relative to that of a full-scale sinusoid
+    power_norm: f32,
+
+    /// Half-width of a sinusoid's spectral peak in bins
+    peak_half_width: f32,
+
+    /// Range of bins where the fundamental and noise are looked for
+    bin_range: (usize, usize),
+
+    /// Highest harmonic order to be taken into account
+    max_order: usize,
+
+    /// Squared magnitude of each bin
+    bin_powers: Box<[f32]>,
+}
+//
+impl DistortionAnalyzer {
+    /// Prepare to analyze spectra with uniformly spaced bins, such as the
+    /// finest FFT of a `SteadyQTransform`, given the spectrum length, the
+    /// properties of the window function, the audio sampling rate, the
+    /// frequency range of interest in Hz and the highest harmonic order to be
+    /// taken into account.
+    pub fn new(
+        spectrum_len: usize,
+        window: WindowProperties,
+        sample_rate: usize,
+        min_freq: f32,
+        max_freq: f32,
+        max_order: usize,
+    ) -> Self {
+        assert!(spectrum_len >= 2);
+        assert!(min_freq >= 0.0 && max_freq > min_freq);
+        assert!(max_freq <= (sample_rate / 2) as f32);
+        assert!(max_order >= 2);
+        assert!(
+            Self::resolves_range(spectrum_len, sample_rate, min_freq, max_freq),
+            "Frequency range is narrower than the spectrum resolution"
+        );
+        let bin_width = bin_width(spectrum_len, sample_rate);
+        Self {
+            bin_width,
+            power_norm: 1.0 / window.noise_bandwidth,
+            peak_half_width: window.main_lobe_half_width,
+            bin_range: bin_range(spectrum_len, sample_rate, min_freq, max_freq),
+            max_order,
+            bin_powers: vec![0.0; spectrum_len].into_boxed_slice(),
+        }
+    }
+
+    /// Truth that a frequency range spans enough bins of a spectrum for
+    /// `new()` to accept it, given the same parameters as `new()`
+    pub fn resolves_range(
+        spectrum_len: usize,
+        sample_rate: usize,
+        min_freq: f32,
+        max_freq: f32,
+    ) -> bool {
+        let (start, end) = bin_range(spectrum_len, sample_rate, min_freq, max_freq);
+        end > start
+    }
+
+    /// Analyze a spectrum, given as magnitudes in dBFS
+    pub fn analyze(&mut self, spectrum: &[f32]) -> DistortionMeasurement {
+        // Convert the spectrum to squared magnitudes
+        assert_eq!(spectrum.len(), self.bin_powers.len());
+        for (power, &magnitude) in self.bin_powers.iter_mut().zip(spectrum) {
+            *power = 10.0f32.powf(magnitude / 10.0);
+        }
+
+        // Locate the fundamental, and refine its frequency estimate using the
+        // power-weighted centroid of its spectral peak
+        let (start, end) = self.bin_range;
+        let peak_bin = (start..=end)
+            .max_by(|&bin1, &bin2| {
+                self.bin_powers[bin1]
+                    .partial_cmp(&self.bin_powers[bin2])
+                    .expect("Spectrum should not contain NaNs")
+            })
+            .expect("Frequency range can't be empty");
+        let fundamental_bin = {
+            let peak_bins = self.peak_bins(peak_bin as f32);
+            let start = *peak_bins.start();
+            let powers = &self.bin_powers[peak_bins];
+            let weighted_sum = (start..)
+                .zip(powers)
+                .map(|(bin, &power)| bin as f32 * power)
+                .sum::<f32>();
+            weighted_sum / powers.iter().sum::<f32>()
+        };
+
+        // Integrate the power of the fundamental, of its harmonics, and of
+        // everything within the frequency range of interest
+        let fundamental = self.peak_power(fundamental_bin);
+        let harmonics = (2..=self.max_order)
+            .map(|order| order as f32 * fundamental_bin)
+            .take_while(|&bin| bin <= end as f32)
+            .map(|bin| self.peak_power(bin))
+            .sum::<f32>();
+        let total = self.power_norm * self.bin_powers[start..=end].iter().sum::<f32>();
+
+        // Deduce the figures of merit
+        let distortion_and_noise = (total - fundamental).max(f32::MIN_POSITIVE);
+        let noise = (distortion_and_noise - harmonics).max(f32::MIN_POSITIVE);
+        DistortionMeasurement {
+            fundamental_freq: fundamental_bin * self.bin_width,
+            fundamental_level: 10.0 * fundamental.log10(),
+            thd: (harmonics / fundamental).sqrt(),
+            thd_n: (distortion_and_noise / total).sqrt(),
+            sinad: 10.0 * (total / distortion_and_noise).log10(),
+            snr: 10.0 * (fundamental / noise).log10(),
+        }
+    }
+
+    /// Range of bins covered by the spectral peak of a sinusoid, given the
+    /// fractional bin position of its frequency
+    fn peak_bins(&self, center_bin: f32) -> std::ops::RangeInclusive<usize> {
+        let start = (center_bin - self.peak_half_width).ceil().max(0.0) as usize;
+        let end =
+            ((center_bin + self.peak_half_width).floor() as usize).min(self.bin_powers.len() - 1);
+        start..=end
+    }
+
+    /// Integrate the power of a sinusoid, given the fractional bin position
+    /// of its frequency
+    fn peak_power(&self, center_bin: f32) -> f32 {
+        self.power_norm
+            * self.bin_powers[self.peak_bins(center_bin)]
+                .iter()
+                .sum::<f32>()
+    }
+}
+
+/// Result of a distortion measurement
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DistortionMeasurement {
+    /// Frequency of the fundamental in Hz
+    pub fundamental_freq: f32,
+
+    /// Level of the fundamental in dBFS
+    pub fundamental_level: f32,
+
+    /// Total harmonic distortion, as a ratio of harmonic amplitude to the
+    /// amplitude of the fundamental
+    pub thd: f32,
+
+    /// Total harmonic distortion plus noise, as a ratio of residual amplitude
+    /// (everything but the fundamental) to the total signal amplitude
+    pub thd_n: f32,
+
+    /// Signal to noise and distortion ratio in dB
+    pub sinad: f32,
+
+    /// Signal to noise ratio in dB, excluding harmonics from the noise
+    pub snr: f32,
+}
+//
+impl std::fmt::Display for DistortionMeasurement {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "F0 {:.1} Hz @ {:.1} dBFS | THD {:.4}% ({:.1} dB) | THD+N {:.4}% ({:.1} dB) | \
+             SINAD {:.1} dB | SNR {:.1} dB",
+            self.fundamental_freq,
+            self.fundamental_level,
+            100.0 * self.thd,
+            20.0 * self.thd.log10(),
+            100.0 * self.thd_n,
+            20.0 * self.thd_n.log10(),
+            self.sinad,
+            self.snr,
+        )
+    }
+}
+
+/// Width of the bins of a spectrum of a certain length in Hz
+fn bin_width(spectrum_len: usize, sample_rate: usize) -> f32 {
+    (sample_rate / 2) as f32 / (spectrum_len - 1) as f32
+}
+
+/// Range of spectrum bins whose center frequency is within a certain range
+fn bin_range(
+    spectrum_len: usize,
+    sample_rate: usize,
+    min_freq: f32,
+    max_freq: f32,
+) -> (usize, usize) {
+    let bin_width = bin_width(spectrum_len, sample_rate);
+    (
+        (min_freq / bin_width).ceil() as usize,
+        ((max_freq / bin_width).floor() as usize).min(spectrum_len - 1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::SteadyQTransform;
+    use more_asserts::*;
+
+    #[test]
+    fn distorted_sine() {
+        // Set up a constant-Q transform and associated distortion analyzer
+        let sample_rate = 48_000;
+        let mut fourier =
+            SteadyQTransform::new(1.0, 20.0, (20.0, 20_000.0), sample_rate, "flattop");
+        let mut analyzer = DistortionAnalyzer::new(
+            fourier.output_len(),
+            fourier.window_properties(),
+            sample_rate,
+            20.0,
+            20_000.0,
+            10,
+        );
+
+        // Analyze a -6dBFS 1kHz sine with 1% of 3rd harmonic distortion
+        let omega = std::f32::consts::TAU * 1000.0 / sample_rate as f32;
+        for (idx, x) in fourier.input().iter_mut().enumerate() {
+            let phase = omega * idx as f32;
+            *x = 0.5 * (phase.sin() + 0.01 * (3.0 * phase).sin());
+        }
+        fourier.compute();
+        let measurement = analyzer.analyze(fourier.finest_magnitudes());
+
+        // Check the measurement
+        assert_lt!((measurement.fundamental_freq - 1000.0).abs(), 1.0);
+        assert_lt!((measurement.fundamental_level + 6.02).abs(), 0.1);
+        assert_lt!((measurement.thd - 0.01).abs(), 0.0005);
+        assert_gt!(measurement.snr, 60.0);
+
+        // Measurement bandwidths must span more than one spectrum bin
+        let len = fourier.output_len();
+        assert!(DistortionAnalyzer::resolves_range(
+            len,
+            sample_rate,
+            1000.0,
+            1002.0
+        ));
+        assert!(!DistortionAnalyzer::resolves_range(
+            len,
+            sample_rate,
+            1000.0,
+            1000.1
+        ));
+    }
+}
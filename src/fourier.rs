@@ -1,12 +1,25 @@
 //! Fourier transform computation and processing
 
-use crate::math;
+use crate::{
+    detrend::Detrending,
+    math::{self, Float},
+};
 use log::{debug, info};
 use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::Arc,
+};
 
-/// Remove DC offset before computing a Fourier transform
-const REMOVE_DC: bool = true;
+/// Names of the supported window functions
+pub const WINDOWS: &[&str] = &[
+    "rectangular",
+    "triangular",
+    "hann",
+    "blackman",
+    "nuttall",
+    "flattop",
+];
 
 /// Fast and sane approximation of a constant-Q transform
 ///
@@ -21,139 +34,256 @@ const REMOVE_DC: bool = true;
 ///   converges to zero at high frequencies (which is useless).
 ///
 /// We address the first problem by approximating the constant-Q transform as
-/// a weighted average of radix-2 FFTs, and the second problem by bounding
-/// the set of radix-2 FFTs that we will use for STFT to a useful amount.
+/// a weighted average of FFTs (radix-2 ones by default), and the second
+/// problem by bounding the set of FFTs that we will use for STFT to a useful
+/// amount.
 ///
-pub struct SteadyQTransform {
-    /// Radix-2 FFTs used to approximate the constant-Q transform, and frequency
-    /// bin of the base (first) FFT on which each one is considered optimal.
-    ffts_and_optimal_bins: Box<[(FourierTransform, f32)]>,
+/// Computations are carried out in single precision by default, but double
+/// precision can be used instead in order to trust lower levels.
+///
+pub struct SteadyQTransform<T: Float = f32> {
+    /// FFTs used to approximate the constant-Q transform, from the longest to
+    /// the shortest, and frequency bin of the base (first) FFT on which each
+    /// one is considered optimal.
+    ffts_and_optimal_bins: Box<[(FourierTransform<T>, f32)]>,
 
-    /// Weights to be used when transitioning from one radix-2 FFT to the next
+    /// Weights to be used when transitioning from one FFT to the next
     transition_weights: Box<[Box<[f32]>]>,
 
+    /// Human-readable description of the FFTs and transitions
+    plan: SteadyQPlan,
+
+    /// Magnitudes of the first (widest) FFT's output
+    finest_magnitude: Box<[f32]>,
+
     /// Buffer to merge all the FFT outputs into one
-    merged_output: Box<[Complex<f32>]>,
+    merged_output: Box<[Complex<T>]>,
 }
 //
 impl SteadyQTransform {
     /// Get ready to compute approximate constant-Q transforms with a certain
-    /// frequency resolution at 20Hz (in Hz) and time resolution at 20kHz
-    /// (in ms), given the audio sampling rate and a choice of window function.
+    /// frequency resolution (in Hz) at a low anchor frequency and time
+    /// resolution (in ms) at a high anchor frequency, given the audio sampling
+    /// rate and a choice of window function.
+    ///
+    /// The anchor frequencies should bound the range of frequencies that the
+    /// user is interested in, since transitions between the FFTs that are
+    /// used to approximate the constant-Q transform are spread across this
+    /// range. For audible sound, 20Hz and 20kHz are a good choice.
+    ///
     pub fn new(
-        freq_res_at_20hz: f32,
-        time_res_at_20khz: f32,
+        freq_res: f32,
+        time_res: f32,
+        anchors: (f32, f32),
         sample_rate: usize,
         window: &str,
     ) -> Self {
-        // Translate the low-frequency resolution into a first FFT length
-        let mut fft_len_at_20hz = FourierTransform::fft_len(freq_res_at_20hz, sample_rate);
-        let inv_bin_width_at_20hz = FourierTransform::inv_bin_width(fft_len_at_20hz, sample_rate);
-
-        // Translate the high-frequency time resolution into a last FFT length
-        let samples_at_20khz = (time_res_at_20khz * sample_rate as f32 / 1000.0) as usize;
-        let fft_len_at_20khz = if samples_at_20khz.is_power_of_two() {
-            samples_at_20khz
+        Self::with_plan_options(
+            freq_res,
+            time_res,
+            anchors,
+            sample_rate,
+            window,
+            PlanOptions::default(),
+        )
+    }
+
+    /// Like `new()`, but with control over how FFTs are picked and combined
+    pub fn with_plan_options(
+        freq_res: f32,
+        time_res: f32,
+        anchors: (f32, f32),
+        sample_rate: usize,
+        window: &str,
+        options: PlanOptions,
+    ) -> Self {
+        Self::with_precision(freq_res, time_res, anchors, sample_rate, window, options)
+    }
+
+    /// Default anchor frequencies for analyzing a certain frequency range at
+    /// a certain sampling rate
+    ///
+    /// This is the 20Hz-20kHz audible range (capped to the Nyquist frequency),
+    /// widened as needed to cover the frequency range. Audible sub-ranges are
+    /// thus analyzed with the same plan as the whole audible range, whereas
+    /// infrasonic and ultrasonic ranges move the FFT transitions.
+    ///
+    pub fn default_anchors(min_freq: f32, max_freq: f32, sample_rate: usize) -> (f32, f32) {
+        assert!(min_freq >= 0.0 && max_freq > min_freq);
+        const AUDIBLE_RANGE: (f32, f32) = (20.0, 20_000.0);
+        let low_anchor = if min_freq > 0.0 {
+            min_freq.min(AUDIBLE_RANGE.0)
         } else {
-            (samples_at_20khz / 4).next_power_of_two()
+            AUDIBLE_RANGE.0
         };
-        info!(
-            "At a sampling rate of {sample_rate} Hz, \
-             achieving a time resolution of {time_res_at_20khz} ms \
-             requires a {fft_len_at_20khz}-points FFT"
-        );
+        let nyquist = (sample_rate / 2) as f32;
+        let high_anchor = max_freq.max(AUDIBLE_RANGE.1.min(nyquist));
+        (low_anchor, high_anchor)
+    }
 
-        // If the time resolution constraint is harsher than the frequency
-        // resolution one, pick the FFT length accordingly.
-        if fft_len_at_20khz > fft_len_at_20hz {
-            info!(
-                "Can achieve desired time-frequency resolution compromise \
-                 with a single {fft_len_at_20khz}-points FFT"
-            );
-            fft_len_at_20hz = fft_len_at_20khz;
-        }
+    /// Check that a constant-Q transform can be set up with certain
+    /// parameters, which have the same meaning as in `with_plan_options()`
+    ///
+    /// Constructors panic with the error reported by this function, so it
+    /// should be used when the parameters come from the user.
+    ///
+    pub fn check_params(
+        freq_res: f32,
+        time_res: f32,
+        anchors: (f32, f32),
+        sample_rate: usize,
+        options: PlanOptions,
+    ) -> Result<(), PlanError> {
+        fft_lens(freq_res, time_res, anchors, sample_rate, options).map(|_| ())
+    }
 
-        // Check that the constant-Q transform can fulfill those constraints
-        // There is a factor of 1000 between the start and the end of the range,
-        // so we cannot cover that range with more than 11 FFTs (base FFT +
-        // decimations 1/2, 1/4, 1/8, ..., 1/1024.
-        debug_assert!(fft_len_at_20hz.is_power_of_two());
-        let fft_len_at_20hz_pow2 = fft_len_at_20hz.trailing_zeros();
-        let fft_len_at_20khz_pow2 = fft_len_at_20khz.trailing_zeros();
-        let num_ffts = (fft_len_at_20hz_pow2 - fft_len_at_20khz_pow2 + 1) as usize;
-        assert!(
-            num_ffts <= 11,
-            "Cannot achieve requested time-frequency resolution compromise \
-             ({freq_res_at_20hz} Hz at 20Hz, {time_res_at_20khz} ms at 20kHz)"
-        );
+    /// Length of the spectrum that a constant-Q transform computes when it
+    /// is set up with certain parameters, which have the same meaning as in
+    /// `with_plan_options()`, or why it cannot be set up
+    pub fn output_len_for(
+        freq_res: f32,
+        time_res: f32,
+        anchors: (f32, f32),
+        sample_rate: usize,
+        options: PlanOptions,
+    ) -> Result<usize, PlanError> {
+        fft_lens(freq_res, time_res, anchors, sample_rate, options)
+            .map(|(fft_len_at_low, _)| fft_len_at_low / 2 + 1)
+    }
+}
+//
+impl<T: Float> SteadyQTransform<T> {
+    /// Like `with_plan_options()`, but computing in an arbitrary
+    /// floating-point precision, e.g. `SteadyQTransform::<f64>::with_precision`
+    pub fn with_precision(
+        freq_res: f32,
+        time_res: f32,
+        (low_anchor, high_anchor): (f32, f32),
+        sample_rate: usize,
+        window: &str,
+        options: PlanOptions,
+    ) -> Self {
+        // Determine the lengths of the first and last FFTs
+        let anchors = (low_anchor, high_anchor);
+        let (fft_len_at_low, fft_len_at_high) =
+            fft_lens(freq_res, time_res, anchors, sample_rate, options)
+                .unwrap_or_else(|e| panic!("{e}"));
+        let inv_bin_width_at_low =
+            FourierTransform::<T>::inv_bin_width(fft_len_at_low, sample_rate);
+        let num_octaves =
+            (fft_len_at_low.trailing_zeros() - fft_len_at_high.trailing_zeros()) as usize;
+        let num_ffts = num_octaves * options.ffts_per_octave + 1;
 
-        // Set up all the radix-2 FFTs required to approximate a constant-Q
-        // transform, and record on which bin of the 20Hz FFT we consider each
-        // of these radix-2 FFTs to be an optimal approximation. Spread the FFTs
-        // around the center of the 20Hz-20kHz log scale.
-        let mut planner = RealFftPlanner::<f32>::new();
-        let mut ffts_and_optimal_bins = VecDeque::new();
-        let center_freq = (20.0f32 * 20_000.0).sqrt() * inv_bin_width_at_20hz;
-        let center_right_len = 2usize.pow((fft_len_at_20hz_pow2 + fft_len_at_20khz_pow2) / 2);
-        let mut pick_fft = |freq, len| {
-            debug!(
-                "Will use a {len}-points FFT at {freq_hz} Hz",
-                freq_hz = freq / inv_bin_width_at_20hz
-            );
-            (
-                FourierTransform::from_fft(planner.plan_fft_forward(len), window),
-                freq,
-            )
-        };
-        let (mut left_freq, mut left_len, mut right_freq, mut right_len);
-        if num_ffts % 2 == 0 {
-            left_freq = center_freq / std::f32::consts::SQRT_2;
-            left_len = center_right_len * 2;
-            right_freq = center_freq * std::f32::consts::SQRT_2;
-            right_len = center_right_len;
-        } else {
-            ffts_and_optimal_bins.push_front(pick_fft(center_freq, center_right_len));
-            left_freq = center_freq / 2.0;
-            left_len = center_right_len * 2;
-            right_freq = center_freq * 2.0;
-            right_len = center_right_len / 2;
-        }
-        while ffts_and_optimal_bins.len() < num_ffts {
-            ffts_and_optimal_bins.push_front(pick_fft(left_freq, left_len));
-            left_freq /= 2.0;
-            left_len *= 2;
-            ffts_and_optimal_bins.push_back(pick_fft(right_freq, right_len));
-            right_freq *= 2.0;
-            right_len /= 2;
-        }
-        debug_assert_eq!(ffts_and_optimal_bins.len(), num_ffts);
-        let ffts_and_optimal_bins: Box<[_]> = ffts_and_optimal_bins.drain(..).collect();
+        // Set up all the FFTs required to approximate a constant-Q transform,
+        // and record on which bin of the first FFT we consider each of these
+        // FFTs to be an optimal approximation. FFT lengths decrease, and
+        // optimal frequencies increase, by a factor of 2 every octave. Spread
+        // the FFTs around the center of the log scale between the anchors.
+        let mut planner = RealFftPlanner::<T>::new();
+        let center_freq = (low_anchor * high_anchor).sqrt() * inv_bin_width_at_low;
+        let octave_fraction = 1.0 / options.ffts_per_octave as f32;
+        let center_idx = (num_ffts - 1) as f32 / 2.0;
+        let ffts_and_optimal_bins: Box<[_]> = (0..num_ffts)
+            .map(|idx| {
+                let exponent = idx as f32 * octave_fraction;
+                let len = if options.ffts_per_octave == 1 {
+                    fft_len_at_low >> idx
+                } else {
+                    2 * (fft_len_at_low as f32 * 2.0f32.powf(-exponent) / 2.0).round() as usize
+                };
+                let freq = center_freq * 2.0f32.powf((idx as f32 - center_idx) * octave_fraction);
+                debug!(
+                    "Will use a {len}-points FFT at {freq_hz} Hz",
+                    freq_hz = freq / inv_bin_width_at_low
+                );
+                (
+                    FourierTransform::from_fft(planner.plan_fft_forward(len), window),
+                    freq,
+                )
+            })
+            .collect();
         let merged_output = ffts_and_optimal_bins[0].0.output.clone();
 
-        // For each consecutive pair of radix-2 FFTs, determine the weights to
-        // use so that the transition from one to the next is smooth when the
-        // transform is rendered on a log frequency scale.
-        let transition_weights = ffts_and_optimal_bins
+        // For each consecutive pair of FFTs, determine the weights to use so
+        // that the transition from one to the next is smooth when the
+        // transform is rendered on a log frequency scale, or abrupt if the
+        // user asked us not to blend FFTs together.
+        let transition_weights: Box<[Box<[f32]>]> = ffts_and_optimal_bins
             .windows(2)
             .map(|pair| {
                 let (_fft1, bin1) = &pair[0];
                 let (_fft2, bin2) = &pair[1];
                 let start_idx = bin1.ceil() as usize;
                 let end_idx = bin2.ceil() as usize;
+                let switch_bin = (bin1 * bin2).sqrt();
                 (start_idx..end_idx)
-                    .map(|idx| ((idx as f32).log2() - bin1.log2()) / (bin2.log2() - bin1.log2()))
+                    .map(|idx| {
+                        let idx = idx as f32;
+                        if options.blend {
+                            (idx.log2() - bin1.log2()) / (bin2.log2() - bin1.log2())
+                        } else if idx < switch_bin {
+                            0.0
+                        } else {
+                            1.0
+                        }
+                    })
                     .collect()
             })
             .collect();
 
+        // Describe the resulting plan
+        let bin_width = 1.0 / inv_bin_width_at_low;
+        let plan = SteadyQPlan {
+            ffts: ffts_and_optimal_bins
+                .iter()
+                .map(|(fft, optimal_bin)| {
+                    let len = fft.input.len();
+                    let fft_bin_width = sample_rate as f32 / len as f32;
+                    PlannedFft {
+                        len,
+                        optimal_freq: optimal_bin * bin_width,
+                        bin_width: fft_bin_width,
+                        freq_res: fft.window_properties.noise_bandwidth * fft_bin_width,
+                        time_res: len as f32 / sample_rate as f32 * 1000.0,
+                    }
+                })
+                .collect(),
+            transitions: ffts_and_optimal_bins
+                .windows(2)
+                .zip(transition_weights.iter())
+                .map(|(pair, weights)| PlannedTransition {
+                    start_freq: pair[0].1.ceil() * bin_width,
+                    end_freq: pair[1].1.ceil() * bin_width,
+                    weights: weights.clone(),
+                })
+                .collect(),
+        };
+        info!("Will approximate the constant-Q transform as follows:\n{plan}");
+
         // Return the resulting constant-Q FFT approximation harness
         Self {
             ffts_and_optimal_bins,
             transition_weights,
+            plan,
+            finest_magnitude: vec![0.0; merged_output.len()].into_boxed_slice(),
             merged_output,
         }
     }
 
+    /// Describe which FFTs are used and how they are combined
+    pub fn plan(&self) -> &SteadyQPlan {
+        &self.plan
+    }
+
+    /// Choose how the input is detrended before computing the transform
+    ///
+    /// Only the first (widest) FFT's input is detrended, the other FFTs
+    /// receive the end of that detrended input.
+    ///
+    pub fn set_detrending(&mut self, detrending: Detrending) {
+        self.first_fft_mut().set_detrending(detrending);
+    }
+
     /// Access the input buffer
     pub fn input(&mut self) -> &mut [f32] {
         self.first_fft_mut().input()
@@ -164,18 +294,34 @@ impl SteadyQTransform {
         self.first_fft().output_len()
     }
 
+    /// Query the properties of the window function
+    pub fn window_properties(&self) -> WindowProperties {
+        self.first_fft().window_properties
+    }
+
     /// Compute the constant-Q transform approximation and return coefficient
     /// magnitudes in dBFS.
     pub fn compute(&mut self) -> &[f32] {
+        self.compute_complex();
+        FourierTransform::compute_magnitudes(
+            &self.merged_output[..],
+            &mut self.ffts_and_optimal_bins[0].0.magnitude[..],
+        )
+    }
+
+    /// Compute the constant-Q transform approximation and return the complex
+    /// coefficients, normalized such that a full-scale sinusoid has unit
+    /// magnitude.
+    pub fn compute_complex(&mut self) -> &[Complex<T>] {
         // Prepare the first FFT's input
         let (first_fft, other_ffts) = self.ffts_and_optimal_bins.split_at_mut(1);
         let (ref mut first_fft, first_optimal_bin) = first_fft[0];
         first_fft.prepare_input();
 
         // Propagate the end of that input to other FFTs'inputs and compute them
-        let first_input = first_fft.input();
+        let first_input = &first_fft.input[..];
         for (fft, _optimal_bin) in other_ffts.iter_mut() {
-            let input = fft.input();
+            let input = &mut fft.input[..];
             input.copy_from_slice(&first_input[first_input.len() - input.len()..]);
             fft.window_and_compute_fft();
         }
@@ -187,15 +333,15 @@ impl SteadyQTransform {
         let low_bins = first_optimal_bin.ceil() as usize;
         self.merged_output[..low_bins].copy_from_slice(&first_fft.output[..low_bins]);
 
-        // After that, combine pairs of consecutive radix-2 FFTs using the
-        // previously determined weights. Bear in mind that those FFTs must be
+        // After that, combine pairs of consecutive FFTs using the previously
+        // determined weights. Bear in mind that those FFTs must be
         // interpolated in order to match the frequency resolution of the
         // final merged FFT.
-        for (idx, (fft_pair, transition_weights)) in self
+        let first_len = first_fft.input.len();
+        for (fft_pair, transition_weights) in self
             .ffts_and_optimal_bins
             .windows(2)
             .zip(self.transition_weights.iter())
-            .enumerate()
         {
             // Extract the pair of FFTs that we're going to work with
             let (ref fft1, optimal_bin1) = fft_pair[0];
@@ -206,13 +352,9 @@ impl SteadyQTransform {
             let end_idx = optimal_bin2.ceil() as usize;
             debug_assert_eq!(end_idx - start_idx, transition_weights.len());
 
-            // Determine how bins of each FFT map into bins of the merged FFT
-            let stride1 = 2usize.pow(idx as u32);
-            let stride2 = 2 * stride1;
-
             // Produce linear interpolants of each FFT on the merged FFT's bins
-            let fft1_interpolant = math::interpolate_c32(&fft1.output[..], stride1);
-            let fft2_interpolant = math::interpolate_c32(&fft2.output[..], stride2);
+            let fft1_interpolant = fft1.interpolant(first_len);
+            let fft2_interpolant = fft2.interpolant(first_len);
 
             // Perform the FFT merging
             for ((dest, (src1, src2)), weight) in self
@@ -222,7 +364,8 @@ impl SteadyQTransform {
                 .skip(start_idx)
                 .zip(transition_weights.iter())
             {
-                *dest = (1.0 - weight) * src1 + weight * src2;
+                let weight = T::widen(*weight);
+                *dest = src1 * (T::one() - weight) + src2 * weight;
             }
         }
 
@@ -232,8 +375,7 @@ impl SteadyQTransform {
             .last()
             .expect("There has to be at least one FFT");
         let high_bins = last_optimal_bin.ceil() as usize;
-        let last_stride = 2usize.pow(self.ffts_and_optimal_bins.len() as u32 - 1);
-        let last_fft_interpolant = math::interpolate_c32(&last_fft.output[..], last_stride);
+        let last_fft_interpolant = last_fft.interpolant(first_len);
         for (dest, src) in self
             .merged_output
             .iter_mut()
@@ -242,40 +384,397 @@ impl SteadyQTransform {
         {
             *dest = src
         }
+        &self.merged_output[..]
+    }
 
-        // Compute the magnitude of the merged FFT
+    /// Magnitudes of the constant-Q transform approximation that was
+    /// produced by the last call to `compute()`, in dBFS
+    pub fn magnitudes(&self) -> &[f32] {
+        &self.first_fft().magnitude[..]
+    }
+
+    /// Magnitudes of the first (widest) radix-2 FFT that was used during the
+    /// last computation, in dBFS
+    ///
+    /// This FFT has the best frequency resolution and covers the full
+    /// spectrum with uniformly spaced bins, so unlike the merged output, its
+    /// coefficients can be integrated to measure the power of the input
+    /// signal in a certain frequency range.
+    ///
+    pub fn finest_magnitudes(&mut self) -> &[f32] {
         FourierTransform::compute_magnitudes(
-            &self.merged_output[..],
-            &mut self.ffts_and_optimal_bins[0].0.magnitude[..],
+            &self.ffts_and_optimal_bins[0].0.output[..],
+            &mut self.finest_magnitude[..],
         )
     }
 
     // Access the first (widest) inner FFT
-    fn first_fft(&self) -> &FourierTransform {
+    fn first_fft(&self) -> &FourierTransform<T> {
         &self.ffts_and_optimal_bins[0].0
     }
     //
-    fn first_fft_mut(&mut self) -> &mut FourierTransform {
+    fn first_fft_mut(&mut self) -> &mut FourierTransform<T> {
         &mut self.ffts_and_optimal_bins[0].0
     }
 }
 
+/// Determine the lengths of the first (longest) and last (shortest) FFT of a
+/// constant-Q transform, or why it cannot be set up
+fn fft_lens(
+    freq_res: f32,
+    time_res: f32,
+    (low_anchor, high_anchor): (f32, f32),
+    sample_rate: usize,
+    options: PlanOptions,
+) -> Result<(usize, usize), PlanError> {
+    // Validate the parameters
+    if !(freq_res.is_finite() && freq_res > 0.0) {
+        return Err(PlanError::FreqResolution(freq_res));
+    }
+    if !(time_res.is_finite() && time_res > 0.0) {
+        return Err(PlanError::TimeResolution(time_res));
+    }
+    let nyquist = (sample_rate / 2) as f32;
+    if !(low_anchor.is_finite()
+        && low_anchor > 0.0
+        && high_anchor > low_anchor
+        && high_anchor <= nyquist)
+    {
+        return Err(PlanError::Anchors {
+            anchors: (low_anchor, high_anchor),
+            nyquist,
+        });
+    }
+    if options.ffts_per_octave == 0 {
+        return Err(PlanError::FftsPerOctave);
+    }
+
+    // Translate the low-frequency resolution into a first FFT length
+    let fft_len_for_freq_res = FourierTransform::<f32>::fft_len(freq_res, sample_rate);
+
+    // Translate the high-frequency time resolution into a last FFT length
+    let fft_len_for_time_res = |time_res: f32| {
+        let samples_at_high = (time_res * sample_rate as f32 / 1000.0) as usize;
+        if samples_at_high.is_power_of_two() {
+            samples_at_high
+        } else {
+            (samples_at_high / 4).next_power_of_two()
+        }
+    };
+    let fft_len_at_high = fft_len_for_time_res(time_res);
+    info!(
+        "At a sampling rate of {sample_rate} Hz, \
+         achieving a time resolution of {time_res} ms \
+         requires a {fft_len_at_high}-points FFT"
+    );
+
+    // If the time resolution constraint is harsher than the frequency
+    // resolution one, pick the FFT length accordingly.
+    if fft_len_at_high >= fft_len_for_freq_res {
+        info!(
+            "Can achieve desired time-frequency resolution compromise \
+             with a single {fft_len_at_high}-points FFT"
+        );
+        return Ok((fft_len_at_high, fft_len_at_high));
+    }
+
+    // Check that the constant-Q transform can fulfill those constraints
+    // Each FFT covers a fraction of an octave, so we cannot cover the range
+    // between the anchor frequencies with more FFTs than there are octave
+    // fractions in this range, plus one (e.g. 11 FFTs for 20Hz-20kHz with
+    // one FFT per octave: base FFT + decimations 1/2, 1/4, ..., 1/1024).
+    debug_assert!(fft_len_for_freq_res.is_power_of_two());
+    let num_octaves =
+        (fft_len_for_freq_res.trailing_zeros() - fft_len_at_high.trailing_zeros()) as usize;
+    let max_fractions =
+        (options.ffts_per_octave as f32 * (high_anchor / low_anchor).log2()).ceil() as usize;
+    let max_octaves = max_fractions / options.ffts_per_octave;
+    if num_octaves > max_octaves {
+        // Suggest the finest resolutions that would work, keeping the other
+        // resolution constant. The time resolution must yield a power of two
+        // number of samples, otherwise a 4x shorter FFT would be used, so
+        // coarser time resolutions only work again from a bit over twice
+        // that number of samples.
+        let min_len_at_high = fft_len_for_freq_res >> max_octaves;
+        let max_len_at_low = fft_len_at_high << max_octaves;
+        let works = |time_res| fft_len_for_time_res(time_res) >= min_len_at_high;
+        let best_time_res =
+            smallest_valid(min_len_at_high as f32 * 1000.0 / sample_rate as f32, works);
+        let coarse_time_res = smallest_valid(
+            (4 * (min_len_at_high / 2 + 1)) as f32 * 1000.0 / sample_rate as f32,
+            works,
+        );
+        let best_freq_res = smallest_valid(sample_rate as f32 / max_len_at_low as f32, |res| {
+            FourierTransform::<f32>::fft_len(res, sample_rate) <= max_len_at_low
+        });
+        return Err(PlanError::Compromise {
+            freq_res,
+            time_res,
+            anchors: (low_anchor, high_anchor),
+            best_time_res,
+            coarse_time_res,
+            best_freq_res,
+        });
+    }
+    Ok((fft_len_for_freq_res, fft_len_at_high))
+}
+
+/// Nudge a positive approximate solution upwards until it passes a check
+/// that may fail due to rounding errors
+fn smallest_valid(mut x: f32, check: impl Fn(f32) -> bool) -> f32 {
+    while !check(x) {
+        x = f32::from_bits(x.to_bits() + 1);
+    }
+    x
+}
+
+/// Reason why a [`SteadyQTransform`] cannot be set up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlanError {
+    /// The frequency resolution is not a positive number of Hz
+    FreqResolution(f32),
+
+    /// The time resolution is not a positive number of ms
+    TimeResolution(f32),
+
+    /// The anchor frequencies are not increasing and between 0Hz (excluded)
+    /// and the Nyquist frequency (included)
+    Anchors {
+        /// Requested anchor frequencies in Hz
+        anchors: (f32, f32),
+
+        /// Nyquist frequency in Hz
+        nyquist: f32,
+    },
+
+    /// There must be at least one FFT per octave
+    FftsPerOctave,
+
+    /// The requested time and frequency resolutions cannot both be achieved
+    /// between the anchor frequencies
+    Compromise {
+        /// Requested frequency resolution in Hz
+        freq_res: f32,
+
+        /// Requested time resolution in ms
+        time_res: f32,
+
+        /// Anchor frequencies in Hz
+        anchors: (f32, f32),
+
+        /// Finest time resolution that works with this frequency resolution
+        best_time_res: f32,
+
+        /// Time resolution from which every coarser time resolution works
+        /// with this frequency resolution
+        coarse_time_res: f32,
+
+        /// Finest frequency resolution that works with this time resolution
+        best_freq_res: f32,
+    },
+}
+//
+impl Display for PlanError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::FreqResolution(freq_res) => write!(
+                f,
+                "Frequency resolution {freq_res} Hz is invalid, it should be a positive number"
+            ),
+            Self::TimeResolution(time_res) => write!(
+                f,
+                "Time resolution {time_res} ms is invalid, it should be a positive number"
+            ),
+            Self::Anchors {
+                anchors: (low, high),
+                nyquist,
+            } => write!(
+                f,
+                "Anchor frequencies {low} Hz and {high} Hz are invalid, they should be \
+                 increasing and between 0 Hz (excluded) and {nyquist} Hz"
+            ),
+            Self::FftsPerOctave => write!(f, "There should be at least one FFT per octave"),
+            Self::Compromise {
+                freq_res,
+                time_res,
+                anchors: (low, high),
+                best_time_res,
+                coarse_time_res,
+                best_freq_res,
+            } => write!(
+                f,
+                "Cannot achieve requested time-frequency resolution compromise \
+                 ({freq_res} Hz at {low} Hz, {time_res} ms at {high} Hz). With this \
+                 frequency resolution, the finest achievable time resolution is \
+                 {best_time_res} ms, and any time resolution of {coarse_time_res} ms \
+                 or more works too. With this time resolution, the finest achievable \
+                 frequency resolution is {best_freq_res} Hz. Anchor frequencies that \
+                 are further apart would also help."
+            ),
+        }
+    }
+}
+//
+impl std::error::Error for PlanError {}
+
+/// Options controlling how a [`SteadyQTransform`] picks and combines FFTs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlanOptions {
+    /// Number of FFTs per octave of FFT length
+    ///
+    /// With the default of 1, all FFTs have power-of-two lengths. Using more
+    /// FFTs per octave makes transitions narrower and better approximates the
+    /// constant-Q transform, at the expense of extra computations.
+    ///
+    pub ffts_per_octave: usize,
+
+    /// Blend consecutive FFTs together across each transition range
+    ///
+    /// If disabled, the output abruptly switches from one FFT to the next at
+    /// the center of each transition range.
+    ///
+    pub blend: bool,
+}
+//
+impl Default for PlanOptions {
+    fn default() -> Self {
+        Self {
+            ffts_per_octave: 1,
+            blend: true,
+        }
+    }
+}
+
+/// Description of the FFTs that a [`SteadyQTransform`] uses, and of how
+/// their outputs are combined
+#[derive(Clone, Debug, PartialEq)]
+pub struct SteadyQPlan {
+    /// FFTs, from the longest to the shortest
+    pub ffts: Box<[PlannedFft]>,
+
+    /// Transitions between consecutive FFTs
+    pub transitions: Box<[PlannedTransition]>,
+}
+//
+impl Display for SteadyQPlan {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "FFT  Length  Optimal freq  Bin width  Freq res  Time res"
+        )?;
+        for (idx, fft) in self.ffts.iter().enumerate() {
+            writeln!(
+                f,
+                "{idx:>3}  {len:>6}  {optimal_freq:>9.1} Hz  {bin_width:>6.2} Hz  \
+                 {freq_res:>5.2} Hz  {time_res:>5.1} ms",
+                len = fft.len,
+                optimal_freq = fft.optimal_freq,
+                bin_width = fft.bin_width,
+                freq_res = fft.freq_res,
+                time_res = fft.time_res,
+            )?;
+        }
+        for (idx, transition) in self.transitions.iter().enumerate() {
+            write!(
+                f,
+                "Transition {idx}->{next}: {start:.1} Hz to {end:.1} Hz, ",
+                next = idx + 1,
+                start = transition.start_freq,
+                end = transition.end_freq,
+            )?;
+            match (transition.weights.first(), transition.weights.last()) {
+                (Some(first), Some(last)) => writeln!(
+                    f,
+                    "weight {first:.2} to {last:.2} over {len} bins",
+                    len = transition.weights.len()
+                )?,
+                _ => writeln!(f, "empty")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One of the FFTs of a [`SteadyQPlan`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlannedFft {
+    /// Number of input points
+    pub len: usize,
+
+    /// Frequency at which this FFT is considered optimal, in Hz
+    pub optimal_freq: f32,
+
+    /// Spacing between output bins, in Hz
+    pub bin_width: f32,
+
+    /// Frequency resolution, i.e. equivalent noise bandwidth of the window
+    /// function, in Hz
+    pub freq_res: f32,
+
+    /// Time resolution, i.e. duration of the input, in ms
+    pub time_res: f32,
+}
+
+/// Transition between two consecutive FFTs of a [`SteadyQPlan`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedTransition {
+    /// Start of the transition range in Hz, where the longer FFT is used
+    pub start_freq: f32,
+
+    /// End of the transition range in Hz, where the shorter FFT is used
+    pub end_freq: f32,
+
+    /// Weight of the shorter FFT at each bin of the transition range, using
+    /// the bin spacing of the first (longest) FFT
+    pub weights: Box<[f32]>,
+}
+
+/// Spectral properties of a window function
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowProperties {
+    /// Equivalent noise bandwidth in bins
+    ///
+    /// Since the output of the Fourier transform is normalized such that a
+    /// full-scale sinusoid has unit magnitude, noise is boosted by this factor
+    /// with respect to sinusoids. Integrating the squared magnitude of the
+    /// output across bins, then dividing by this factor, yields the mean
+    /// square amplitude of the input, relative to that of a full-scale sine.
+    ///
+    pub noise_bandwidth: f32,
+
+    /// Half-width of the central peak in bins, i.e. distance from the peak of
+    /// a pure sinusoid to the first zero of its spectrum
+    pub main_lobe_half_width: f32,
+}
+
 /// Short-term Fourier transform
-pub struct FourierTransform {
+///
+/// Like [`SteadyQTransform`], this computes in single precision by default.
+///
+pub struct FourierTransform<T: Float = f32> {
     /// FFT implementation
-    fft: Arc<dyn RealToComplex<f32>>,
+    fft: Arc<dyn RealToComplex<T>>,
 
-    /// Time series input
-    input: Box<[f32]>,
+    /// Time series input, as provided by the user
+    samples: Box<[f32]>,
+
+    /// Time series input, in the precision of the FFT
+    input: Box<[T]>,
 
     /// Window to be applied to input data
-    window: Box<[f32]>,
+    window: Box<[T]>,
+
+    /// Properties of that window
+    window_properties: WindowProperties,
+
+    /// Detrending applied to the input before windowing
+    detrending: Detrending,
 
     /// Scratch space
-    scratch: Box<[Complex<f32>]>,
+    scratch: Box<[Complex<T>]>,
 
     /// Complex FFT output
-    output: Box<[Complex<f32>]>,
+    output: Box<[Complex<T>]>,
 
     /// Complex FFT magnitude in dB
     magnitude: Box<[f32]>,
@@ -285,16 +784,30 @@ impl FourierTransform {
     /// Get ready to compute Fourier transforms with a certain frequency
     /// resolution (in Hz), given the audio sample rate and a choice of
     /// window function.
-    #[allow(unused)]
     pub fn new(resolution: f32, sample_rate: usize, window: &str) -> Self {
+        Self::with_precision(resolution, sample_rate, window)
+    }
+}
+//
+impl<T: Float> FourierTransform<T> {
+    /// Like `new()`, but computing in an arbitrary floating-point precision
+    pub fn with_precision(resolution: f32, sample_rate: usize, window: &str) -> Self {
         let fft_len = Self::fft_len(resolution, sample_rate);
-        let mut planner = RealFftPlanner::<f32>::new();
+        let mut planner = RealFftPlanner::<T>::new();
         Self::from_fft(planner.plan_fft_forward(fft_len), window)
     }
 
     /// Access the input buffer
     pub fn input(&mut self) -> &mut [f32] {
-        &mut self.input[..]
+        &mut self.samples[..]
+    }
+
+    /// Choose how the input is detrended before computing the transform
+    ///
+    /// By default, the mean of the input is removed.
+    ///
+    pub fn set_detrending(&mut self, detrending: Detrending) {
+        self.detrending = detrending;
     }
 
     /// Query the output length
@@ -303,7 +816,6 @@ impl FourierTransform {
     }
 
     /// Compute the Fourier transform and return coefficient magnitudes in dBFS
-    #[allow(unused)]
     pub fn compute(&mut self) -> &[f32] {
         self.prepare_input();
         self.window_and_compute_fft();
@@ -344,76 +856,124 @@ impl FourierTransform {
     }
 
     /// Subset of the constructor that happens after an FFT has been planned
-    fn from_fft(fft: Arc<dyn RealToComplex<f32>>, window: &str) -> Self {
+    fn from_fft(fft: Arc<dyn RealToComplex<T>>, window: &str) -> Self {
         // Prepare for the FFT computation
         let input = fft.make_input_vec().into_boxed_slice();
+        let samples = vec![0.0; input.len()].into_boxed_slice();
         let scratch = fft.make_scratch_vec().into_boxed_slice();
         let output = fft.make_output_vec().into_boxed_slice();
         let magnitude = vec![0.0; output.len()].into_boxed_slice();
 
-        // Prepare for input windowing
-        let mut window: Box<[_]> = match window {
-            "rectangular" => std::iter::repeat(1.0).take(input.len()).collect(),
-            "triangular" => (0..input.len() / 2)
-                .chain((0..input.len() / 2).rev())
-                .map(|x| x as f32 / ((input.len() - 1) / 2) as f32)
-                .collect(),
-            "hann" => (0..input.len())
-                .map(|n| {
-                    (std::f32::consts::PI * n as f32 / (input.len() - 1) as f32)
-                        .sin()
-                        .powi(2)
-                })
-                .collect(),
-            "blackman" => (0..input.len())
-                .map(|n| {
-                    use std::f32::consts::TAU;
-                    let alpha = 0.16;
-                    let a0 = 0.5 * (1.0 - alpha);
-                    let a1 = 0.5;
-                    let a2 = 0.5 * alpha;
-                    let phase = TAU * n as f32 / input.len() as f32;
-                    a0 - a1 * (phase).cos() + a2 * (2.0 * phase).cos()
-                })
-                .collect(),
-            "nuttall" => (0..input.len())
-                .map(|n| {
-                    use std::f32::consts::TAU;
-                    let a0 = 0.355768;
-                    let a1 = 0.487396;
-                    let a2 = 0.144232;
-                    let a3 = 0.012604;
-                    let phase = TAU * n as f32 / input.len() as f32;
-                    a0 - a1 * (phase).cos() + a2 * (2.0 * phase).cos() - a3 * (3.0 * phase).cos()
-                })
-                .collect(),
+        // Prepare for input windowing, computing the window in double
+        // precision so that it does not limit the accuracy of the FFT
+        let (window, main_lobe_half_width): (Box<[_]>, _) = match window {
+            "rectangular" => (std::iter::repeat(1.0).take(input.len()).collect(), 1.0),
+            "triangular" => (
+                (0..input.len() / 2)
+                    .chain((0..input.len() / 2).rev())
+                    .map(|x| x as f64 / ((input.len() - 1) / 2) as f64)
+                    .collect(),
+                2.0,
+            ),
+            "hann" => (
+                (0..input.len())
+                    .map(|n| {
+                        (std::f64::consts::PI * n as f64 / (input.len() - 1) as f64)
+                            .sin()
+                            .powi(2)
+                    })
+                    .collect(),
+                2.0,
+            ),
+            "blackman" => (
+                (0..input.len())
+                    .map(|n| {
+                        use std::f64::consts::TAU;
+                        let alpha = 0.16;
+                        let a0 = 0.5 * (1.0 - alpha);
+                        let a1 = 0.5;
+                        let a2 = 0.5 * alpha;
+                        let phase = TAU * n as f64 / input.len() as f64;
+                        a0 - a1 * (phase).cos() + a2 * (2.0 * phase).cos()
+                    })
+                    .collect(),
+                3.0,
+            ),
+            "nuttall" => (
+                (0..input.len())
+                    .map(|n| {
+                        use std::f64::consts::TAU;
+                        let a0 = 0.355768;
+                        let a1 = 0.487396;
+                        let a2 = 0.144232;
+                        let a3 = 0.012604;
+                        let phase = TAU * n as f64 / input.len() as f64;
+                        a0 - a1 * (phase).cos() + a2 * (2.0 * phase).cos()
+                            - a3 * (3.0 * phase).cos()
+                    })
+                    .collect(),
+                4.0,
+            ),
+            "flattop" => (
+                (0..input.len())
+                    .map(|n| {
+                        use std::f64::consts::TAU;
+                        let a0 = 0.21557895;
+                        let a1 = 0.41663158;
+                        let a2 = 0.27726316;
+                        let a3 = 0.083578947;
+                        let a4 = 0.006947368;
+                        let phase = TAU * n as f64 / input.len() as f64;
+                        a0 - a1 * (phase).cos() + a2 * (2.0 * phase).cos()
+                            - a3 * (3.0 * phase).cos()
+                            + a4 * (4.0 * phase).cos()
+                    })
+                    .collect(),
+                5.0,
+            ),
             _ => panic!("Window type {window} is not supported"),
         };
 
+        // Compute the window's equivalent noise bandwidth
+        let window_sum = window.iter().sum::<f64>();
+        let window_square_sum = window.iter().map(|w| w * w).sum::<f64>();
+        let window_properties = WindowProperties {
+            noise_bandwidth: (window.len() as f64 * window_square_sum / window_sum.powi(2)) as f32,
+            main_lobe_half_width,
+        };
+
         // Pre-normalize the window function so that output is normalized
-        let output_norm = 2.0 / math::sum_f32_fast(&window[..]);
-        for x in window.iter_mut() {
-            *x *= output_norm;
-        }
+        let output_norm = 2.0 / window_sum;
+        let window = window
+            .iter()
+            .map(|&x| T::from_f64(x * output_norm).expect("Window should be representable"))
+            .collect();
 
         // Return the state to the client
         Self {
             fft,
+            samples,
             input,
             window,
+            window_properties,
+            detrending: Detrending::Mean,
             scratch,
             output,
             magnitude,
         }
     }
 
+    /// Linearly interpolate the output on the bins of a longer FFT
+    fn interpolant(&self, fft_len: usize) -> impl Iterator<Item = Complex<T>> + '_ {
+        math::resample_complex(&self.output[..], fft_len / 2 + 1)
+    }
+
     /// Prepare the input data for the FFT computation
     fn prepare_input(&mut self) {
-        // Remove DC offset if configured to do so
-        if REMOVE_DC {
-            let average = math::sum_f32_fast(&self.input[..]) / self.input.len() as f32;
-            self.input.iter_mut().for_each(|elem| *elem -= average);
+        for (dest, &src) in self.input.iter_mut().zip(self.samples.iter()) {
+            *dest = T::widen(src);
         }
+        self.detrending.apply(&mut self.input[..]);
     }
 
     /// Window the input data and compute the FFT
@@ -434,10 +994,7 @@ impl FourierTransform {
     }
 
     /// Compute FFT magnitudes in dBFS and return them
-    fn compute_magnitudes<'mag>(
-        output: &[Complex<f32>],
-        magnitude: &'mag mut [f32],
-    ) -> &'mag [f32] {
+    fn compute_magnitudes<'mag>(output: &[Complex<T>], magnitude: &'mag mut [f32]) -> &'mag [f32] {
         // Normalize magnitudes, convert to dBFS, and send the result out
         for (coeff, mag) in output.iter().zip(magnitude.iter_mut()) {
             // NOTE: dBFS formula is 20*log10(|coeff|) but we avoid a
@@ -451,8 +1008,235 @@ impl FourierTransform {
             //       408833fc89895cb4231d1c62b21cedce88f87b83 . Revisit this
             //       later with a manually vectorized loop (using SIMD
             //       deinterleave for the initial norm_sqr computations).
-            *mag = 10.0 * coeff.norm_sqr().log10();
+            *mag = 10.0 * coeff.norm_sqr().log10().as_();
         }
         magnitude
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use more_asserts::*;
+
+    #[test]
+    fn ultrasonic_anchors() {
+        // Set up a steady-Q transform that spans 20kHz-80kHz at 192kHz, which
+        // requires 3 FFTs (4096, 2048 and 1024 points).
+        let sample_rate = 192_000;
+        let mut fourier =
+            SteadyQTransform::new(50.0, 16.0, (20_000.0, 80_000.0), sample_rate, "hann");
+        assert_eq!(fourier.ffts_and_optimal_bins.len(), 3);
+
+        // Transitions should be spread across the anchor frequencies
+        let bin_width = (sample_rate / 2) as f32 / (fourier.output_len() - 1) as f32;
+        for (_fft, optimal_bin) in fourier.ffts_and_optimal_bins.iter() {
+            let optimal_freq = optimal_bin * bin_width;
+            assert_ge!(optimal_freq, 20_000.0);
+            assert_le!(optimal_freq, 80_000.0);
+        }
+
+        // A 40kHz sine should show up at the right place
+        let omega = std::f32::consts::TAU * 40_000.0 / sample_rate as f32;
+        for (idx, x) in fourier.input().iter_mut().enumerate() {
+            *x = 0.5 * (omega * idx as f32).sin();
+        }
+        let magnitudes = fourier.compute();
+        let peak_bin = (0..magnitudes.len())
+            .max_by(|&a, &b| {
+                magnitudes[a]
+                    .partial_cmp(&magnitudes[b])
+                    .expect("Magnitudes should not be NaN")
+            })
+            .expect("There has to be output bins");
+        assert_lt!((peak_bin as f32 * bin_width / 40_000.0 - 1.0).abs(), 0.01);
+    }
+    #[test]
+    fn plan_options() {
+        // By default, 20Hz-20kHz with 1Hz and 7ms resolution at 48kHz requires
+        // 10 radix-2 FFTs (65536 to 128 points), with smooth transitions between them
+        let sample_rate = 48_000;
+        let new_fourier = |options| {
+            SteadyQTransform::with_plan_options(
+                1.0,
+                7.0,
+                (20.0, 20_000.0),
+                sample_rate,
+                "hann",
+                options,
+            )
+        };
+        let default = new_fourier(PlanOptions::default());
+        let plan = default.plan();
+        assert_eq!(plan.ffts.len(), 10);
+        assert_eq!(plan.transitions.len(), 9);
+        for (fft, next) in plan.ffts.iter().zip(plan.ffts.iter().skip(1)) {
+            assert!(fft.len.is_power_of_two());
+            assert_eq!(fft.len, 2 * next.len);
+            assert_lt!(fft.optimal_freq, next.optimal_freq);
+            assert_lt!(fft.freq_res, next.freq_res);
+            assert_gt!(fft.time_res, next.time_res);
+        }
+        for transition in plan.transitions.iter() {
+            assert_lt!(transition.start_freq, transition.end_freq);
+            for pair in transition.weights.windows(2) {
+                assert_lt!(pair[0], pair[1]);
+            }
+        }
+
+        // Two FFTs per octave double the number of transitions
+        let finer = new_fourier(PlanOptions {
+            ffts_per_octave: 2,
+            blend: true,
+        });
+        let plan = finer.plan();
+        assert_eq!(plan.ffts.len(), 19);
+        assert_eq!(plan.ffts[0].len, default.plan().ffts[0].len);
+        assert_eq!(plan.ffts[18].len, default.plan().ffts[9].len);
+
+        // Without blending, transitions are abrupt
+        let abrupt = new_fourier(PlanOptions {
+            ffts_per_octave: 1,
+            blend: false,
+        });
+        for transition in abrupt.plan().transitions.iter() {
+            assert!(transition.weights.iter().all(|&w| w == 0.0 || w == 1.0));
+            assert_eq!(transition.weights.first(), Some(&0.0));
+            assert_eq!(transition.weights.last(), Some(&1.0));
+        }
+
+        // Whatever the plan, a sine should show up at the right place
+        for mut fourier in [default, finer, abrupt] {
+            let bin_width = (sample_rate / 2) as f32 / (fourier.output_len() - 1) as f32;
+            let omega = std::f32::consts::TAU * 3_000.0 / sample_rate as f32;
+            for (idx, x) in fourier.input().iter_mut().enumerate() {
+                *x = 0.5 * (omega * idx as f32).sin();
+            }
+            let magnitudes = fourier.compute();
+            let peak_bin = (0..magnitudes.len())
+                .max_by(|&a, &b| {
+                    magnitudes[a]
+                        .partial_cmp(&magnitudes[b])
+                        .expect("Magnitudes should not be NaN")
+                })
+                .expect("There has to be output bins");
+            assert_lt!((peak_bin as f32 * bin_width / 3_000.0 - 1.0).abs(), 0.01);
+        }
+    }
+
+    #[test]
+    fn double_precision() {
+        // Compute the spectrum of a -6dBFS sine, whose samples are rounded to
+        // single precision, at a certain floating-point precision. Return the
+        // highest level away from the peak.
+        fn noise_floor<T: Float>() -> f32 {
+            let mut fourier = FourierTransform::<T>::with_precision(1.0, 48_000, "blackman");
+            fourier.set_detrending(Detrending::None);
+            let len = fourier.input().len();
+            let cycles = 1000;
+            let omega = std::f64::consts::TAU * cycles as f64 / len as f64;
+            for (idx, x) in fourier.input().iter_mut().enumerate() {
+                *x = (0.5 * (omega * idx as f64).sin()) as f32;
+            }
+            let magnitudes = fourier.compute();
+            assert_lt!((magnitudes[cycles] + 6.02).abs(), 0.01);
+            magnitudes
+                .iter()
+                .enumerate()
+                .filter(|(bin, _)| (*bin as isize - cycles as isize).abs() > 10)
+                .map(|(_, &level)| level)
+                .fold(f32::NEG_INFINITY, f32::max)
+        }
+
+        // In double precision, the noise floor should be much lower
+        let single_floor = noise_floor::<f32>();
+        let double_floor = noise_floor::<f64>();
+        assert_lt!(double_floor, -170.0);
+        assert_lt!(double_floor, single_floor - 10.0);
+    }
+
+    #[test]
+    fn plan_errors() {
+        // Invalid parameters are reported as such
+        let check = |freq_res, time_res, anchors| {
+            SteadyQTransform::check_params(
+                freq_res,
+                time_res,
+                anchors,
+                48_000,
+                PlanOptions::default(),
+            )
+        };
+        assert_eq!(
+            check(0.0, 7.0, (20.0, 20_000.0)),
+            Err(PlanError::FreqResolution(0.0))
+        );
+        assert!(matches!(
+            check(1.0, 7.0, (20.0, 30_000.0)),
+            Err(PlanError::Anchors { .. })
+        ));
+        assert!(check(1.0, 7.0, (20.0, 20_000.0)).is_ok());
+
+        // When the time-frequency compromise cannot be achieved, the suggested
+        // resolutions are achievable and as fine as possible
+        let (best_time_res, coarse_time_res, best_freq_res) = match check(0.1, 1.0, (100.0, 200.0))
+        {
+            Err(PlanError::Compromise {
+                best_time_res,
+                coarse_time_res,
+                best_freq_res,
+                ..
+            }) => (best_time_res, coarse_time_res, best_freq_res),
+            other => panic!("Unexpected plan check result {other:?}"),
+        };
+        assert!(check(0.1, best_time_res, (100.0, 200.0)).is_ok());
+        assert!(check(0.1, best_time_res * 0.99, (100.0, 200.0)).is_err());
+        assert!(check(0.1, best_time_res * 1.05, (100.0, 200.0)).is_err());
+        assert!(check(0.1, coarse_time_res, (100.0, 200.0)).is_ok());
+        assert!(check(0.1, coarse_time_res * 0.99, (100.0, 200.0)).is_err());
+        assert!(check(0.1, coarse_time_res * 1.05, (100.0, 200.0)).is_ok());
+        assert!(check(best_freq_res, 1.0, (100.0, 200.0)).is_ok());
+        assert!(check(best_freq_res * 0.99, 1.0, (100.0, 200.0)).is_err());
+        assert!(check(best_freq_res * 1.05, 1.0, (100.0, 200.0)).is_ok());
+
+        // Any time resolution coarser than the suggested threshold works too
+        let coarse_time_res = match check(1.0, 7.0, (50.0, 8_000.0)) {
+            Err(PlanError::Compromise {
+                coarse_time_res, ..
+            }) => coarse_time_res,
+            other => panic!("Unexpected plan check result {other:?}"),
+        };
+        for time_res in [1.0, 1.05, 1.2, 1.5, 1.8, 2.0, 3.0] {
+            assert!(check(1.0, coarse_time_res * time_res, (50.0, 8_000.0)).is_ok());
+        }
+    }
+
+    #[test]
+    fn default_anchors() {
+        // Audible sub-ranges are analyzed with the default resolutions
+        for sample_rate in [44_100, 48_000] {
+            for (min_freq, max_freq) in [(20.0, 1_000.0), (50.0, 8_000.0), (0.0, 500.0)] {
+                let anchors = SteadyQTransform::default_anchors(min_freq, max_freq, sample_rate);
+                assert_eq!(anchors, (20.0, 20_000.0));
+                assert!(SteadyQTransform::check_params(
+                    1.0,
+                    7.0,
+                    anchors,
+                    sample_rate,
+                    PlanOptions::default()
+                )
+                .is_ok());
+            }
+        }
+
+        // Other ranges widen the anchors, which stay below Nyquist
+        assert_eq!(
+            SteadyQTransform::default_anchors(2.0, 80_000.0, 192_000),
+            (2.0, 80_000.0)
+        );
+        assert_eq!(
+            SteadyQTransform::default_anchors(20.0, 4_000.0, 16_000),
+            (20.0, 8_000.0)
+        );
+    }
+}
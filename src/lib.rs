@@ -0,0 +1,51 @@
+//! Real-time spectrum analysis
+//!
+//! This crate contains the signal processing engine of the `spectre` spectrum
+//! analyzer, so that it can be embedded into other applications. The easiest
+//! way to get started is the [`Analyzer`], which turns a stream of audio
+//! samples into spectra that are ready for display:
+//!
+//! ```
+//! use spectre::Analyzer;
+//!
+//! let mut analyzer = Analyzer::builder(48_000).output_len(256).build();
+//! analyzer.push_samples(&[0.0; 1024]);
+//! let spectrum = analyzer.compute();
+//! assert_eq!(spectrum.len(), analyzer.output_freqs().len());
+//! ```
+//!
+//! Lower-level building blocks are also available for more specialized uses:
+//!
+//! - [`fourier::SteadyQTransform`] is a fast approximation of the constant-Q
+//!   transform, based on a weighted average of FFTs.
+//! - [`resampler::FourierResampler`] maps the output of a Fourier transform
+//!   to an arbitrary number of bins on a [`scale::FrequencyScale`].
+//! - [`batch::Spectrogram`] analyzes whole recordings, e.g. WAV files decoded
+//!   using [`wav`], into time-frequency matrices that can be exported.
+//! - `audio` records and plays audio through JACK. It requires the `audio`
+//!   Cargo feature, which is enabled by default.
+//! - [`math`] contains general-purpose numerical utilities.
+
+pub mod analyzer;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod bands;
+pub mod batch;
+pub mod detrend;
+pub mod distortion;
+pub mod fourier;
+pub mod loudness;
+pub mod math;
+pub mod resampler;
+pub mod scale;
+pub mod smoothing;
+pub mod sweep;
+pub mod transfer;
+pub mod wav;
+pub mod weighting;
+
+pub use analyzer::{Analyzer, AnalyzerBuilder};
+
+/// Default Result type used throughout this crate whenever bubbling errors up
+/// seems to be the only sensible option.
+pub use anyhow::Result;
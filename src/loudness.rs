@@ -0,0 +1,200 @@
+//! Perceived loudness estimation based on ISO 226:2003 equal-loudness contours
+
+/// Frequencies at which the ISO 226:2003 contour parameters are tabulated, in Hz
+const FREQS: [f32; 29] = [
+    20.0, 25.0, 31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0, 400.0,
+    500.0, 630.0, 800.0, 1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0, 5000.0, 6300.0,
+    8000.0, 10000.0, 12500.0,
+];
+
+/// Exponent for loudness perception (alpha_f)
+const EXPONENTS: [f32; 29] = [
+    0.532, 0.506, 0.480, 0.455, 0.432, 0.409, 0.387, 0.367, 0.349, 0.330, 0.315, 0.301, 0.288,
+    0.276, 0.267, 0.259, 0.253, 0.250, 0.246, 0.244, 0.243, 0.243, 0.243, 0.242, 0.242, 0.245,
+    0.254, 0.271, 0.301,
+];
+
+/// Magnitude of the linear transfer function normalized at 1kHz, in dB (L_U)
+const TRANSFER_GAINS: [f32; 29] = [
+    -31.6, -27.2, -23.0, -19.1, -15.9, -13.0, -10.3, -8.1, -6.2, -4.5, -3.1, -2.0, -1.1, -0.4, 0.0,
+    0.3, 0.5, 0.0, -2.7, -4.1, -1.0, 1.7, 2.5, 1.2, -2.1, -7.1, -11.2, -10.7, -3.1,
+];
+
+/// Threshold of hearing in dB SPL (T_f)
+const THRESHOLDS: [f32; 29] = [
+    78.5, 68.7, 59.5, 51.1, 44.0, 37.5, 31.5, 26.5, 22.1, 17.9, 14.4, 11.4, 8.6, 6.2, 4.4, 3.0,
+    2.2, 2.4, 3.5, 1.7, -1.3, -4.2, -6.0, -5.4, -1.5, 6.0, 12.6, 13.9, 12.3,
+];
+
+/// Lowest loudness level that can be reported, in phons
+///
+/// Sounds that are sufficiently far below the threshold of hearing fall
+/// outside of the domain of the ISO 226 model, and are clamped to this value,
+/// which is far below anything that can be displayed.
+///
+const MIN_LOUDNESS: f32 = -200.0;
+
+/// Equal-loudness contour parameters at a given frequency
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ContourParameters {
+    /// Exponent for loudness perception (alpha_f)
+    exponent: f32,
+
+    /// Magnitude of the linear transfer function normalized at 1kHz (L_U)
+    transfer_gain: f32,
+
+    /// Contribution of the threshold of hearing to the loudness function
+    threshold_term: f32,
+}
+//
+impl ContourParameters {
+    /// Interpolate the ISO 226 parameters at a certain frequency in Hz
+    ///
+    /// ISO 226 only defines equal-loudness contours from 20Hz to 12.5kHz.
+    /// Outside of this range, the parameters of the closest tabulated
+    /// frequency are used.
+    ///
+    fn new(freq: f32) -> Self {
+        // Locate the frequency in the table, interpolate in log-frequency
+        let (idx, weight) = if freq <= FREQS[0] {
+            (0, 0.0)
+        } else if freq >= FREQS[FREQS.len() - 1] {
+            (FREQS.len() - 2, 1.0)
+        } else {
+            let idx = FREQS
+                .windows(2)
+                .position(|pair| freq < pair[1])
+                .expect("Frequency should be in the table's range");
+            let weight = (freq / FREQS[idx]).ln() / (FREQS[idx + 1] / FREQS[idx]).ln();
+            (idx, weight)
+        };
+        let interpolate = |table: &[f32; 29]| (1.0 - weight) * table[idx] + weight * table[idx + 1];
+        let exponent = interpolate(&EXPONENTS);
+        let transfer_gain = interpolate(&TRANSFER_GAINS);
+        let threshold = interpolate(&THRESHOLDS);
+        Self {
+            exponent,
+            transfer_gain,
+            threshold_term: Self::excitation(threshold, transfer_gain, exponent),
+        }
+    }
+
+    /// Compressed excitation term of the ISO 226 loudness function, for a
+    /// certain sound pressure level in dB SPL
+    fn excitation(spl: f32, transfer_gain: f32, exponent: f32) -> f32 {
+        (0.4 * 10.0f32.powf((spl + transfer_gain) / 10.0 - 9.0)).powf(exponent)
+    }
+
+    /// Convert a sound pressure level in dB SPL to a loudness level in phons
+    fn loudness(&self, spl: f32) -> f32 {
+        let excitation = Self::excitation(spl, self.transfer_gain, self.exponent);
+        let b = excitation - self.threshold_term + 0.005135;
+        if b > 0.0 {
+            (40.0 * b.log10() + 94.0).max(MIN_LOUDNESS)
+        } else {
+            MIN_LOUDNESS
+        }
+    }
+}
+
+/// Conversion of Fourier transform magnitudes to perceived loudness
+///
+/// Given a calibration that tells which sound pressure level is produced by a
+/// full-scale sinusoid at the listening position, this converts dBFS
+/// magnitudes into loudness levels in phons, then offsets them by the
+/// calibration so that a 1kHz sinusoid keeps the same value. This way, sounds
+/// which are perceived as equally loud are displayed at the same level.
+///
+pub struct LoudnessConverter {
+    /// Sound pressure level of a full-scale sinusoid, in dB SPL
+    calibration: f32,
+
+    /// ISO 226 contour parameters at each Fourier transform bin
+    bin_parameters: Box<[ContourParameters]>,
+
+    /// Loudness levels
+    output: Box<[f32]>,
+}
+//
+impl LoudnessConverter {
+    /// Prepare to convert Fourier transforms of a certain output length, given
+    /// the audio sampling rate and the sound pressure level of a full-scale
+    /// sinusoid in dB SPL
+    pub fn new(calibration: f32, fourier_len: usize, sample_rate: usize) -> Self {
+        assert!(fourier_len >= 2);
+        assert!(calibration.is_finite());
+        let bin_width = (sample_rate / 2) as f32 / (fourier_len - 1) as f32;
+        Self {
+            calibration,
+            bin_parameters: (0..fourier_len)
+                .map(|bin| ContourParameters::new(bin as f32 * bin_width))
+                .collect(),
+            output: vec![0.0; fourier_len].into_boxed_slice(),
+        }
+    }
+
+    /// Convert Fourier transform magnitudes in dBFS to calibration-relative
+    /// loudness levels (in phons minus the calibration)
+    pub fn apply(&mut self, magnitudes: &[f32]) -> &[f32] {
+        assert_eq!(magnitudes.len(), self.output.len());
+        for ((dest, &magnitude), parameters) in self
+            .output
+            .iter_mut()
+            .zip(magnitudes)
+            .zip(self.bin_parameters.iter())
+        {
+            *dest = parameters.loudness(magnitude + self.calibration) - self.calibration;
+        }
+        &self.output[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use more_asserts::*;
+
+    // Forward ISO 226 model, converting loudness levels into SPL
+    fn spl(freq: f32, loudness: f32) -> f32 {
+        let idx = FREQS.iter().position(|&f| f == freq).unwrap();
+        let (exponent, transfer_gain, threshold) =
+            (EXPONENTS[idx], TRANSFER_GAINS[idx], THRESHOLDS[idx]);
+        let a = 4.47e-3 * (10.0f32.powf(0.025 * loudness) - 1.15)
+            + (0.4 * 10.0f32.powf((threshold + transfer_gain) / 10.0 - 9.0)).powf(exponent);
+        10.0 / exponent * a.log10() - transfer_gain + 94.0
+    }
+
+    #[test]
+    fn iso_226_contours() {
+        // At 1kHz, loudness levels in phons match SPL by definition
+        let at_1khz = ContourParameters::new(1000.0);
+        for spl in [20.0, 40.0, 60.0, 80.0, 100.0] {
+            assert_lt!((at_1khz.loudness(spl) - spl).abs(), 0.1);
+        }
+
+        // Elsewhere, the inverse model must match the forward one, and low
+        // frequencies must need more SPL to sound as loud
+        for freq in FREQS {
+            let parameters = ContourParameters::new(freq);
+            for loudness in [20.0, 40.0, 60.0, 80.0] {
+                assert_lt!(
+                    (parameters.loudness(spl(freq, loudness)) - loudness).abs(),
+                    0.1
+                );
+            }
+            if freq < 500.0 {
+                assert_gt!(spl(freq, 40.0), 40.0);
+            }
+        }
+
+        // In between tabulated frequencies, contours are interpolated
+        let interpolated = ContourParameters::new((100.0f32 * 125.0).sqrt());
+        let loudness = interpolated.loudness(60.0);
+        let bounds = (
+            ContourParameters::new(100.0).loudness(60.0),
+            ContourParameters::new(125.0).loudness(60.0),
+        );
+        assert_gt!(loudness, bounds.0);
+        assert_lt!(loudness, bounds.1);
+    }
+}
@@ -1,28 +1,88 @@
-mod audio;
+mod config;
+#[cfg(unix)]
+mod control;
 mod display;
-mod fourier;
-pub mod math;
-mod resampler;
+mod midi;
 
+#[cfg(unix)]
+use crate::control::{ControlCommand, ControlSocket};
 use crate::{
-    audio::AudioSetup, display::FrameResult, fourier::SteadyQTransform, resampler::FourierResampler,
+    config::{Config, ConfigError, DEFAULT_PROFILE},
+    display::{Display, FrameInput, FrameResult, UserCommand, AMP_RANGE_STEP},
+    midi::{MidiAction, MidiMapping},
 };
-use log::{debug, error};
-use rt_history::Overrun;
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use log::{debug, error, info, warn};
+use rt_history::{Clock, Overrun};
+use spectre::{
+    analyzer::{Analyzer, AnalyzerBuilder},
+    audio::{AudioError, AudioRecording, AudioSetup},
+    bands::{BandsError, OctaveBands},
+    batch::Spectrogram,
+    detrend::Detrending,
+    distortion::{DistortionAnalyzer, DistortionMeasurement},
+    fourier::{self, PlanError, PlanOptions, SteadyQTransform},
+    loudness::LoudnessConverter,
+    math::Float,
+    resampler::{FourierResampler, ResamplerError, ResamplingMode},
+    scale::{FrequencyRange, FrequencyScale},
+    smoothing::OctaveSmoothing,
+    sweep::ExponentialSweep,
+    transfer::TransferFunction,
+    wav,
+    weighting::{FrequencyWeighting, Weighting},
+    Result,
 };
-use structopt::StructOpt;
-
-/// Default Result type used throughout this app whenever bubbling errors up
-/// seems to be the only sensible option.
-pub use anyhow::Result;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use structopt::{clap::AppSettings, StructOpt};
 
 // Command-line parameters
+//
+// Options may be specified several times, in which case the last occurrence
+// wins. This is how command-line options override those of the profile.
 #[derive(Debug, StructOpt)]
+#[structopt(
+    global_settings = &[AppSettings::AllArgsOverrideSelf],
+    after_help = "EXIT STATUS:\n    \
+                  0    Success\n    \
+                  1    Runtime failure (e.g. unreadable input file)\n    \
+                  2    Invalid options, configuration file or analysis settings\n    \
+                  3    Audio stack failure (e.g. JACK server not running)"
+)]
 struct CliOpts {
+    /// Configuration profile to be used
+    ///
+    /// Profiles are tables of the configuration file whose keys are long
+    /// option names, e.g. a "tuner" profile could contain 'min-freq = 25',
+    /// 'freq-res = 0.1' and 'window = "blackman"'. Options that are specified
+    /// on the command line take precedence over those of the profile. If no
+    /// profile is specified, the "default" profile is used, if any.
+    ///
+    #[structopt(long)]
+    profile: Option<String>,
+
+    /// Configuration file (default: $XDG_CONFIG_HOME/spectre/config.toml)
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
     /// Minimum displayed frequency in Hz
+    ///
+    /// This is only the initial displayed frequency range. In the GUI, it can
+    /// be zoomed using the mouse wheel or +/- keys, panned by dragging with
+    /// the left mouse button or with the up/down arrow keys, and reset to its
+    /// initial value using the Home key. In the terminal display, it can be
+    /// zoomed using the +/- keys, panned with the left/right arrow keys, and
+    /// reset using the Home key.
+    ///
     #[structopt(long, default_value = "20.0")]
     min_freq: f32,
 
@@ -32,29 +92,108 @@ struct CliOpts {
 
     /// Minimal frequency resolution in Hz
     ///
-    /// This is the minimal FFT bin spacing at 20Hz. Actual frequency resolution
-    /// will be a bit more, depending on the choice of window function.
+    /// This is the minimal FFT bin spacing at the low anchor frequency.
+    /// Actual frequency resolution will be a bit more, depending on the
+    /// choice of window function.
     ///
     #[structopt(long, default_value = "1.0")]
     freq_res: f32,
 
     /// Minimal time resolution in ms
     ///
-    /// This is the time resolution provided by the FFT at 20kHz. It cannot be
-    /// set indefinitely small, at some point the limit of the constant Q
-    /// transform's ability to accomodate both frequency and time resolution
-    /// constraints will be reached.
+    /// This is the time resolution provided by the FFT at the high anchor
+    /// frequency. It cannot be set indefinitely small, at some point the limit
+    /// of the constant Q transform's ability to accomodate both frequency and
+    /// time resolution constraints will be reached.
     ///
     #[structopt(long, default_value = "7.0")]
     time_res: f32,
 
-    /// Use a linear frequency scale
+    /// Low anchor frequency in Hz, where the frequency resolution is specified
+    ///
+    /// The constant Q transform is approximated using FFTs of decreasing
+    /// lengths, with transitions spread between the low and high anchor
+    /// frequencies. By default, these are 20Hz and 20kHz (or the Nyquist
+    /// frequency, if lower), widened as needed to cover the initial displayed
+    /// frequency range.
+    ///
+    #[structopt(long)]
+    low_anchor: Option<f32>,
+
+    /// High anchor frequency in Hz, where the time resolution is specified
+    #[structopt(long)]
+    high_anchor: Option<f32>,
+
+    /// Number of FFTs per octave of FFT length in the constant Q transform
+    ///
+    /// By default, FFT lengths are powers of two. Using more FFTs per octave
+    /// narrows the transitions between FFTs, at the expense of CPU time.
+    ///
+    #[structopt(long, default_value = "1")]
+    ffts_per_octave: usize,
+
+    /// Switch abruptly from one FFT to the next instead of blending them
+    #[structopt(long)]
+    no_blend: bool,
+
+    /// Blend successive FFTs, overriding a --no-blend from the profile
+    //
+    // The argument parser clears no_blend when this flag comes after it, so
+    // this flag never needs to be read.
+    #[allow(dead_code)]
+    #[structopt(long, overrides_with = "no-blend")]
+    blend: bool,
+
+    /// Print the FFTs used by the constant Q transform, along with their
+    /// resolution and the transitions between them, then exit
+    #[structopt(long)]
+    print_plan: bool,
+
+    /// Frequency scale
     ///
-    /// By default, spectre will use a log scale as it better matches human
-    /// audition, but linear scales may be better for specific applications.
+    /// By default, spectre uses a log scale as it roughly matches musical
+    /// pitch perception, but other scales may be better for specific
+    /// applications: "linear" for harmonic analysis, "mel", "bark" or "erb"
+    /// for speech and psychoacoustics work.
+    ///
+    /// Custom scales can also be specified as "table:<f1>,<f2>,...", where
+    /// f1, f2... are strictly increasing frequencies in Hz that should be
+    /// evenly spaced on the display.
+    ///
+    #[structopt(long, default_value = "log")]
+    freq_scale: FrequencyScale,
+
+    /// Resampling mode, i.e. how Fourier transform bins are combined into
+    /// display bins when the display has a coarser frequency resolution
+    ///
+    /// "db" averages the dB magnitudes, which looks smooth but underestimates
+    /// narrow peaks and does not correspond to any physical quantity.
+    ///
+    /// "mean-power" averages the power across each display bin, which is a
+    /// physically meaningful power spectral density estimate.
+    ///
+    /// "sum-power" sums the power within each display bin, which is the
+    /// total energy that the display bin represents.
+    ///
+    /// "max" displays the highest magnitude within each display bin, which
+    /// preserves the amplitude of narrow peaks such as pure tones.
+    ///
+    #[structopt(
+        long,
+        default_value = "db",
+        possible_values = &["db", "mean-power", "sum-power", "max"]
+    )]
+    resampling: ResamplingMode,
+
+    /// Apply 1/N-octave smoothing to the spectrum before display
+    ///
+    /// Each frequency is displayed with the mean power of a window that is
+    /// 1/N octave wide around it, as room acoustics tools do. Typical values
+    /// are 3, 6, 12 or 24. In transfer mode, only the magnitude view can be
+    /// smoothed. Frequency responses measured with sweeps are smoothed too.
     ///
     #[structopt(long)]
-    lin_freqs: bool,
+    smoothing: Option<usize>,
 
     /// Window function to be applied
     ///
@@ -73,9 +212,72 @@ struct CliOpts {
     /// "nuttall" has a central peak width of 2.0 bins, first sidelobes at
     /// -95dB, down to -130dB when 40 bins away.
     ///
-    #[structopt(long, default_value = "hann")]
+    /// "flattop" has a very wide central peak (3.8 bins), first sidelobes at
+    /// -90dB, but its peak amplitude is almost independent of the position of
+    /// a sinusoid with respect to the bins. This makes it the window of choice
+    /// for accurate amplitude measurements, such as distortion measurements.
+    ///
+    #[structopt(long, default_value = "hann", possible_values = fourier::WINDOWS)]
     window: String,
 
+    /// Removal of DC offset and slow trends from the input
+    ///
+    /// "mean" subtracts the mean of each Fourier transform input, which
+    /// removes DC offset but produces frame-to-frame steps in the lowest bins
+    /// when the offset fluctuates.
+    ///
+    /// "linear" subtracts a linear fit of each Fourier transform input,
+    /// which also removes slow drifts.
+    ///
+    /// "highpass" continuously filters the incoming audio with a first-order
+    /// high-pass filter, with a 5Hz cutoff frequency by default. Use
+    /// "highpass:<f>" to set the cutoff frequency to f Hz.
+    ///
+    /// "none" leaves the input untouched, which is what you want for
+    /// infrasound and DC-coupled sensors.
+    ///
+    #[structopt(long, default_value = "mean")]
+    detrend: Detrending,
+
+    /// Floating-point precision of the Fourier transforms
+    ///
+    /// "f32" is enough for most purposes. "f64" is slower, but lets you trust
+    /// lower levels, down to the noise floor of 24-bit sources and below,
+    /// which can matter when testing high-end converters.
+    ///
+    #[structopt(long, default_value = "f32", possible_values = &["f32", "f64"])]
+    precision: Precision,
+
+    /// Frequency weighting curve
+    ///
+    /// "a" and "c" apply the IEC 61672 A- and C-weighting curves to the
+    /// spectrum, so that it matches what sound level meters report, while
+    /// "z" leaves the spectrum unweighted. Weighting affects both the display
+    /// and the distortion measurements, but not transfer functions.
+    ///
+    /// In the GUI and terminal displays, the "W" key switches to the next
+    /// weighting curve.
+    ///
+    #[structopt(long, default_value = "z", possible_values = &["a", "c", "z"])]
+    weighting: Weighting,
+
+    /// Display perceived loudness in phons, given the sound pressure level in
+    /// dB SPL that a full-scale sinusoid produces at the listening position
+    ///
+    /// This calibration can be performed by playing a 0dBFS 1kHz sine, or
+    /// more comfortably a quieter one and adding the attenuation back, and
+    /// measuring the resulting sound level with a sound level meter.
+    ///
+    /// Spectrum levels are then converted into loudness levels using the
+    /// ISO 226:2003 equal-loudness contours, so that sounds which are
+    /// perceived as equally loud are displayed with the same height and
+    /// color. To keep the amplitude range meaningful, the calibration is
+    /// subtracted from the loudness levels, so that a 1kHz sine is displayed
+    /// at the same level as in dBFS.
+    ///
+    #[structopt(long)]
+    phon_calibration: Option<f32>,
+
     /// Amplitude range in dBFS
     ///
     /// Signal amplitudes lower than this amount below 0dBFS will not be
@@ -84,165 +286,1785 @@ struct CliOpts {
     #[structopt(long, default_value = "96")]
     amp_range: f32,
 
+    /// Display on which spectra are shown
+    ///
+    /// "gui" opens a window with the live spectrum and a spectrogram, "cli"
+    /// draws the live spectrum and/or a spectrogram in the terminal (see
+    /// --cli-view), and "headless" streams
+    /// spectra to stdout or a file for consumption by other programs.
+    ///
+    /// By default, the GUI is used, falling back to the terminal if no window
+    /// system is available. Only the displays that were enabled at build time
+    /// are available.
+    ///
+    #[structopt(long, possible_values = DISPLAYS)]
+    display: Option<DisplayKind>,
+
     /// Spectrogram refresh rate in logical pixels per second
     ///
     /// If this is set above the screen refresh rate, it will be capped to it.
     ///
-    #[cfg(all(feature = "gui", not(feature = "cli")))]
+    #[cfg(feature = "gui")]
     #[structopt(long, default_value = "200")]
     spectrogram_refresh: f32,
+
+    /// What the terminal display shows ("spectrum", "spectrogram" or "split")
+    ///
+    /// "spectrum" draws the live spectrum as a bar chart, "spectrogram" draws
+    /// a scrolling spectrogram with the most recent spectrum at the top, and
+    /// "split" draws the live spectrum above the spectrogram. The "V" key
+    /// switches to the next view.
+    ///
+    /// The spectrogram is drawn in 24-bit color if the COLORTERM environment
+    /// variable is set to "truecolor" or "24bit", and in the 256-color
+    /// palette otherwise.
+    ///
+    #[cfg(feature = "cli")]
+    #[structopt(
+        long,
+        default_value = "spectrum",
+        possible_values = &["spectrum", "spectrogram", "split"]
+    )]
+    cli_view: crate::display::CliView,
+
+    /// How the terminal display draws the live spectrum ("bars" or "braille")
+    ///
+    /// "bars" draws filled bars with 8 amplitude levels per char and one
+    /// frequency bin per column. "braille" draws a line plot of Braille dots
+    /// with 4 amplitude levels per char and two frequency bins per column,
+    /// which makes narrow peaks easier to read in a narrow terminal. The "B"
+    /// key switches between both styles.
+    ///
+    #[cfg(feature = "cli")]
+    #[structopt(long, default_value = "bars", possible_values = &["bars", "braille"])]
+    cli_plot: crate::display::CliPlot,
+
+    /// Terminal spectrogram refresh rate in rows per second
+    ///
+    /// Each terminal char displays two spectrogram rows. The rate is capped
+    /// to the terminal refresh rate.
+    ///
+    #[cfg(feature = "cli")]
+    #[structopt(long, default_value = "20")]
+    cli_spectrogram_rate: f32,
+
+    /// Format of the spectrum stream ("jsonl", "csv" or "binary")
+    #[cfg(feature = "stream")]
+    #[structopt(long, default_value = "jsonl", possible_values = &["jsonl", "csv", "binary"])]
+    stream_format: crate::display::StreamFormat,
+
+    /// File to which spectra should be streamed (default: stdout)
+    #[cfg(feature = "stream")]
+    #[structopt(long, parse(from_os_str))]
+    stream_output: Option<PathBuf>,
+
+    /// Number of frequency bins in each streamed spectrum
+    #[cfg(feature = "stream")]
+    #[structopt(long, default_value = "256")]
+    stream_bins: usize,
+
+    /// Maximal number of spectra streamed per second
+    #[cfg(feature = "stream")]
+    #[structopt(long, default_value = "10")]
+    stream_rate: f32,
+
+    /// Kind of analysis to be performed
+    ///
+    /// "spectrum" displays the spectrum of the signal on the "input" port.
+    ///
+    /// "transfer" measures the transfer function of a system by comparing the
+    /// signal sent to it (which must be sent to the "reference" port) with the
+    /// signal coming out of it (which must be sent to the "input" port). Any
+    /// excitation with enough spectral content, including music, can be used.
+    ///
+    /// "distortion" displays the spectrum of the "input" port, and measures
+    /// the harmonic distortion and noise of the sinusoidal test tone that it
+    /// is expected to contain. Use of the "flattop" window is recommended.
+    ///
+    /// "bands" displays the power of the "input" port within standard
+    /// fractional-octave bands, as a bar graph.
+    ///
+    #[structopt(
+        long,
+        default_value = "spectrum",
+        possible_values = &["spectrum", "transfer", "distortion", "bands"]
+    )]
+    mode: Mode,
+
+    /// Transfer function quantity to be displayed in transfer mode
+    ///
+    /// "magnitude" displays the transfer function gain in dB. 0dB is shown at
+    /// half the amplitude range, so that gains and losses are both visible.
+    ///
+    /// "phase" displays the transfer function phase, from -180° at the
+    /// bottom of the amplitude range to +180° at the top.
+    ///
+    /// "coherence" displays the magnitude-squared coherence, from 0 at the
+    /// bottom of the amplitude range to 1 at the top.
+    ///
+    #[structopt(
+        long,
+        default_value = "magnitude",
+        possible_values = &["magnitude", "phase", "coherence"]
+    )]
+    transfer_view: TransferView,
+
+    /// Number of spectra averaged together in transfer mode
+    ///
+    /// More averaging gives a more stable estimate of the transfer function,
+    /// at the expense of reacting more slowly to changes.
+    ///
+    #[structopt(long, default_value = "16")]
+    transfer_averages: usize,
+
+    /// Delay of the measurement with respect to the reference in ms
+    ///
+    /// In transfer mode, the reference is delayed by this amount before being
+    /// compared with the measurement, to compensate for the latency of the
+    /// system under test (e.g. acoustic propagation time).
+    ///
+    #[structopt(long, default_value = "0.0")]
+    transfer_delay: f32,
+
+    /// Minimal coherence for the transfer function to be displayed
+    ///
+    /// In transfer mode, the magnitude and phase of frequencies where the
+    /// coherence is below this threshold are not displayed, since they are
+    /// likely to be dominated by noise.
+    ///
+    #[structopt(long, default_value = "0.0")]
+    coherence_threshold: f32,
+
+    /// Highest harmonic order taken into account by distortion measurements
+    #[structopt(long, default_value = "10")]
+    thd_orders: usize,
+
+    /// Minimum frequency of the distortion measurement bandwidth in Hz
+    ///
+    /// In distortion mode, the test tone is looked for in this bandwidth,
+    /// and any other signal in there is considered to be noise or distortion.
+    ///
+    #[structopt(long, default_value = "20.0")]
+    thd_min_freq: f32,
+
+    /// Maximum frequency of the distortion measurement bandwidth in Hz
+    ///
+    /// Harmonics beyond this frequency are not taken into account.
+    ///
+    #[structopt(long, default_value = "20000.0")]
+    thd_max_freq: f32,
+
+    /// Number of bands per octave in bands mode
+    ///
+    /// Band center frequencies follow IEC 61260-1, and only bands whose
+    /// nominal center frequency lies within the displayed frequency range are
+    /// analyzed.
+    ///
+    #[structopt(long, default_value = "3", possible_values = &["1", "3", "6", "12"])]
+    octave_fraction: usize,
+
+    /// Unix domain socket on which live analysis accepts control commands
+    ///
+    /// Scripts can connect to this socket in order to change the amplitude
+    /// range, frequency range, window function or number of transfer
+    /// function averages, pause and resume the display, and query the latest
+    /// spectrum or its peaks. See the documentation of the control module
+    /// for the protocol.
+    ///
+    #[cfg(unix)]
+    #[structopt(long, parse(from_os_str))]
+    control_socket: Option<PathBuf>,
+
+    /// Register a "midi" JACK MIDI input port for remote control
+    ///
+    /// Notes and control changes received on this port can freeze the
+    /// display, cycle through windows and frequency weightings, adjust the
+    /// amplitude range, and zoom or pan the frequency axis.
+    ///
+    /// By default, notes 60, 62, 64, 65, 67, 69, 71 and 72 respectively
+    /// freeze the display, cycle windows, cycle weightings, zoom in, zoom
+    /// out, pan left, pan right and reset the frequency range, while CC 7
+    /// sets the amplitude range from 20 to 160 dB, on any channel.
+    ///
+    #[structopt(long)]
+    midi: bool,
+
+    /// File that maps MIDI messages to actions (implies --midi)
+    ///
+    /// Each line maps a note or CC to an action, e.g. "note 1 60 freeze" or
+    /// "cc * 7 amp-range 20 160". See the documentation of the midi module
+    /// for the full syntax.
+    ///
+    #[structopt(long, parse(from_os_str))]
+    midi_mapping: Option<PathBuf>,
+
+    /// Disable MIDI remote control, overriding a --midi or --midi-mapping
+    /// from the profile
+    //
+    // The argument parser clears midi and midi_mapping when this flag comes
+    // after them, so this flag never needs to be read.
+    #[allow(dead_code)]
+    #[structopt(long, overrides_with_all = &["midi", "midi-mapping"])]
+    no_midi: bool,
+
+    /// Measurement or offline analysis to be performed instead of live
+    /// analysis
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+/// Measurements and offline analyses that can be performed instead of live
+/// analysis
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Measure an impulse response using an exponential sine sweep
+    ///
+    /// A sweep from the minimum to the maximum displayed frequency is played
+    /// on the "output" port, while the system response is recorded on the
+    /// "input" port. The recording is then deconvolved into an impulse
+    /// response, from which the frequency response of the system and the
+    /// level of each harmonic distortion order are computed.
+    ///
+    Sweep(SweepOpts),
+
+    /// Compute the spectrogram of a WAV file and save it to disk
+    ///
+    /// The analysis settings (frequency range and resolution, window,
+    /// detrending, precision...) are the same as for live analysis. The
+    /// output format is chosen from the output file extension: CSV files
+    /// contain the time axis in the first column and the frequency axis in
+    /// the first row, whereas for NPY files the level matrix is saved as is
+    /// and the axes are saved next to it as <name>_times.npy and
+    /// <name>_freqs.npy.
+    ///
+    /// Only plain spectra are computed, so --mode, --weighting, --smoothing
+    /// and --phon-calibration are ignored. This way, profiles that are meant
+    /// for live analysis can also be used for batch analysis.
+    ///
+    Batch(BatchOpts),
+}
+
+/// Exponential sine sweep measurement parameters
+#[derive(Debug, StructOpt)]
+struct SweepOpts {
+    /// Sweep duration in s
+    ///
+    /// Longer sweeps improve the signal-to-noise ratio of the measurement.
+    ///
+    #[structopt(long, default_value = "5.0")]
+    duration: f32,
+
+    /// Sweep level in dBFS
+    #[structopt(long, default_value = "-6.0")]
+    level: f32,
+
+    /// Recording duration after the end of the sweep in s
+    ///
+    /// This must cover the latency and the reverberation time of the system
+    /// under test, otherwise the end of the impulse response will be lost.
+    ///
+    #[structopt(long, default_value = "1.0")]
+    tail: f32,
+
+    /// Maximal impulse response length in ms
+    ///
+    /// This sets the frequency resolution of the frequency responses, and the
+    /// amount of room reflections that they account for.
+    ///
+    #[structopt(long, default_value = "500")]
+    ir_len: f32,
+
+    /// Number of harmonic orders to be analyzed (including the linear one)
+    #[structopt(long, default_value = "5")]
+    orders: usize,
+
+    /// Harmonic order whose frequency response should be displayed
+    ///
+    /// Order 1 is the linear frequency response, whose 0dB level is shown at
+    /// half the amplitude range. Order N > 1 is the level of the N-th harmonic
+    /// relative to the linear response, as a function of excitation frequency.
+    ///
+    #[structopt(long, default_value = "1")]
+    show_order: usize,
+
+    /// JACK port to which the sweep should be sent (e.g. system:playback_1)
+    ///
+    /// If unspecified, the "output" port must be connected manually.
+    ///
+    #[structopt(long)]
+    playback_port: Option<String>,
+
+    /// JACK port from which the response should be recorded
+    /// (e.g. system:capture_1)
+    ///
+    /// If unspecified, the "input" port must be connected manually.
+    ///
+    #[structopt(long)]
+    capture_port: Option<String>,
+}
+
+/// Offline spectrogram computation parameters
+#[derive(Debug, StructOpt)]
+struct BatchOpts {
+    /// WAV file to be analyzed
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Output file (.csv or .npy)
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+
+    /// Time between consecutive spectra in ms
+    #[structopt(long, default_value = "10.0")]
+    hop: f32,
+
+    /// Number of frequency bins in each spectrum
+    #[structopt(long, default_value = "512")]
+    bins: usize,
+
+    /// Channel of the WAV file to be analyzed, starting from 0
+    #[structopt(long, default_value = "0")]
+    channel: usize,
+}
+
+/// Kind of analysis to be performed
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    /// Spectrum of the input signal
+    Spectrum,
+
+    /// Transfer function from the reference signal to the input signal
+    Transfer,
+
+    /// Harmonic distortion and noise of a test tone on the input signal
+    Distortion,
+
+    /// Power of the input signal in fractional-octave bands
+    Bands,
+}
+//
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "spectrum" => Ok(Self::Spectrum),
+            "transfer" => Ok(Self::Transfer),
+            "distortion" => Ok(Self::Distortion),
+            "bands" => Ok(Self::Bands),
+            _ => Err(anyhow::format_err!("Analysis mode {s} is not supported")),
+        }
+    }
+}
+
+/// Transfer function quantity to be displayed
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TransferView {
+    /// Gain in dB
+    Magnitude,
+
+    /// Phase in degrees
+    Phase,
+
+    /// Magnitude-squared coherence
+    Coherence,
+}
+//
+impl FromStr for TransferView {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "magnitude" => Ok(Self::Magnitude),
+            "phase" => Ok(Self::Phase),
+            "coherence" => Ok(Self::Coherence),
+            _ => Err(anyhow::format_err!("Transfer view {s} is not supported")),
+        }
+    }
+}
+
+/// Names of the displays that were enabled at build time
+const DISPLAYS: &[&str] = &[
+    #[cfg(feature = "gui")]
+    "gui",
+    #[cfg(feature = "cli")]
+    "cli",
+    #[cfg(feature = "stream")]
+    "headless",
+];
+
+/// Display on which spectra are shown
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DisplayKind {
+    /// Window with the live spectrum and a spectrogram
+    #[cfg(feature = "gui")]
+    Gui,
+
+    /// Live spectrum in the terminal
+    #[cfg(feature = "cli")]
+    Cli,
+
+    /// Spectra streamed to stdout or a file
+    #[cfg(feature = "stream")]
+    Headless,
+}
+//
+impl FromStr for DisplayKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            #[cfg(feature = "gui")]
+            "gui" => Ok(Self::Gui),
+            #[cfg(feature = "cli")]
+            "cli" => Ok(Self::Cli),
+            #[cfg(feature = "stream")]
+            "headless" => Ok(Self::Headless),
+            _ => Err(anyhow::format_err!(
+                "Display {s} is not supported by this build of spectre"
+            )),
+        }
+    }
 }
 
-fn main() -> Result<()> {
+/// Floating-point precision of the Fourier transforms
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Precision {
+    /// Single precision (f32)
+    Single,
+
+    /// Double precision (f64)
+    Double,
+}
+//
+impl FromStr for Precision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "f32" => Ok(Self::Single),
+            "f64" => Ok(Self::Double),
+            _ => Err(anyhow::format_err!("Precision {s} is not supported")),
+        }
+    }
+}
+
+/// Analysis being performed on the audio input, with Fourier transforms
+/// computed in a certain floating-point precision
+enum Analysis<T: Float> {
+    /// Spectrum of the "input" port
+    Spectrum(SteadyQTransform<T>),
+
+    /// Transfer function from the "reference" port to the "input" port, with
+    /// a buffer for mapping the quantity of interest to the display range
+    Transfer(TransferFunction<T>, Box<[f32]>),
+
+    /// Spectrum of the "input" port, with harmonic distortion and noise
+    /// measurements of the test tone that it contains
+    Distortion(
+        SteadyQTransform<T>,
+        DistortionAnalyzer,
+        Option<DistortionMeasurement>,
+    ),
+
+    /// Power of the "input" port in fractional-octave bands
+    Bands(SteadyQTransform<T>, OctaveBands),
+}
+//
+impl<T: Float> Analysis<T> {
+    /// Names of the JACK ports that this analysis records from
+    fn port_names(&self) -> &'static [&'static str] {
+        match self {
+            Self::Spectrum(_) | Self::Distortion(..) | Self::Bands(..) => &["input"],
+            Self::Transfer(..) => &["input", "reference"],
+        }
+    }
+
+    /// Length of the longest audio history that is read on every frame
+    fn input_len(&mut self) -> usize {
+        match self {
+            Self::Spectrum(fourier) | Self::Distortion(fourier, ..) | Self::Bands(fourier, _) => {
+                fourier.input().len()
+            }
+            Self::Transfer(transfer, _) => transfer.reference_input().len(),
+        }
+    }
+
+    /// Length of the analysis output
+    fn output_len(&self) -> usize {
+        match self {
+            Self::Spectrum(fourier) | Self::Distortion(fourier, ..) | Self::Bands(fourier, _) => {
+                fourier.output_len()
+            }
+            Self::Transfer(transfer, _) => transfer.output_len(),
+        }
+    }
+
+    /// Read the latest audio history into the analysis inputs
+    fn read_history(
+        &mut self,
+        recording: &mut AudioRecording,
+    ) -> std::result::Result<std::result::Result<Clock, Overrun>, AudioError> {
+        match self {
+            Self::Spectrum(fourier) | Self::Distortion(fourier, ..) | Self::Bands(fourier, _) => {
+                recording.read_history(0, fourier.input())
+            }
+            Self::Transfer(transfer, _) => {
+                let measurement = recording.read_history(0, transfer.measurement_input())?;
+                let reference = recording.read_history(1, transfer.reference_input())?;
+                match (measurement, reference) {
+                    // If the audio thread wrote new data between the two
+                    // readouts, the inputs are out of sync, and we must treat
+                    // this like an overrun of the first readout.
+                    (Ok(clock1), Ok(clock2)) if clock1 != clock2 => Ok(Err(Overrun {
+                        clock: clock2,
+                        excess_entries: clock2.wrapping_sub(clock1),
+                    })),
+                    (Ok(_), reference) => Ok(reference),
+                    (measurement @ Err(_), _) => Ok(measurement),
+                }
+            }
+        }
+    }
+
+    /// Perform the analysis, return its output in display units
+    fn compute<'out>(
+        &'out mut self,
+        opts: &CliOpts,
+        weighting: &'out mut FrequencyWeighting,
+    ) -> &'out [f32] {
+        match self {
+            Self::Spectrum(fourier) => weighting.apply(fourier.compute()),
+            Self::Distortion(fourier, analyzer, measurement) => {
+                fourier.compute();
+                *measurement = Some(analyzer.analyze(weighting.apply(fourier.finest_magnitudes())));
+                weighting.apply(fourier.magnitudes())
+            }
+            Self::Bands(fourier, bands) => {
+                fourier.compute();
+                bands.analyze(weighting.apply(fourier.finest_magnitudes()))
+            }
+            Self::Transfer(transfer, output) => {
+                transfer.compute();
+                let amp_range = opts.amp_range;
+                let below_display = -2.0 * amp_range;
+                let coherent = transfer
+                    .coherence()
+                    .iter()
+                    .map(|&coherence| coherence >= opts.coherence_threshold);
+                match opts.transfer_view {
+                    TransferView::Magnitude => {
+                        for ((dest, &mag), coherent) in
+                            output.iter_mut().zip(transfer.magnitude()).zip(coherent)
+                        {
+                            *dest = if coherent {
+                                mag - 0.5 * amp_range
+                            } else {
+                                below_display
+                            };
+                        }
+                    }
+                    TransferView::Phase => {
+                        for ((dest, &phase), coherent) in
+                            output.iter_mut().zip(transfer.phase()).zip(coherent)
+                        {
+                            *dest = if coherent {
+                                (phase / 360.0 - 0.5) * amp_range
+                            } else {
+                                below_display
+                            };
+                        }
+                    }
+                    TransferView::Coherence => {
+                        for (dest, &coherence) in output.iter_mut().zip(transfer.coherence()) {
+                            *dest = (coherence - 1.0) * amp_range;
+                        }
+                    }
+                }
+                &output[..]
+            }
+        }
+    }
+
+    /// Textual summary of the last analysis, if any
+    fn status(&self) -> Option<String> {
+        match self {
+            Self::Distortion(_, _, measurement) => measurement.map(|m| m.to_string()),
+            Self::Spectrum(_) | Self::Transfer(..) | Self::Bands(..) => None,
+        }
+    }
+}
+
+/// Exit code for invalid options, configuration files or analysis settings
+const EXIT_CONFIG: i32 = 2;
+
+/// Exit code for audio stack failures (e.g. JACK server not running)
+const EXIT_AUDIO: i32 = 3;
+
+fn main() {
     // Set up logging
     env_logger::init();
 
-    // Decode and validate CLI arguments
-    let mut opts = CliOpts::from_args();
+    // Run spectre, report errors
+    exit(run())
+}
+
+/// Terminate the process, reporting errors with an exit code that tells what
+/// went wrong (other errors get the customary exit code of 1)
+///
+/// Displays also use this to report errors from their event loop, which never
+/// returns to `main()`.
+///
+fn exit(result: Result<()>) -> ! {
+    if let Err(e) = result {
+        eprintln!("Error: {e:?}");
+        std::process::exit(exit_code(&e));
+    }
+    std::process::exit(0)
+}
+
+/// Exit code that reports a certain error
+fn exit_code(e: &anyhow::Error) -> i32 {
+    let is_config_error = |e: &(dyn std::error::Error + 'static)| {
+        e.is::<ConfigError>()
+            || e.is::<PlanError>()
+            || e.is::<ResamplerError>()
+            || e.is::<BandsError>()
+    };
+    if e.chain().any(is_config_error) {
+        EXIT_CONFIG
+    } else if e.chain().any(|e| e.is::<jack::Error>()) {
+        EXIT_AUDIO
+    } else {
+        1
+    }
+}
+
+/// Decode and validate the configuration, then do what was requested
+fn run() -> Result<()> {
+    // Decode and validate CLI arguments, applying the configuration profile
+    let mut opts = apply_profile(parse_args(std::env::args_os()))?;
     debug!("Got CLI options {opts:?}");
-    assert!(
+    ConfigError::check_value(
         opts.min_freq.is_finite() && opts.min_freq >= 0.0,
-        "Please specify a sensible minimum frequency"
-    );
-    assert!(
+        "min-freq",
+        opts.min_freq,
+        "a frequency of 0 Hz or more",
+    )?;
+    ConfigError::check_value(
         opts.max_freq.is_finite() && opts.max_freq > opts.min_freq,
-        "Please specify a sensible maximum frequency"
-    );
-    assert!(
+        "max-freq",
+        opts.max_freq,
+        format!(
+            "a frequency above the minimum frequency ({} Hz)",
+            opts.min_freq
+        ),
+    )?;
+    ConfigError::check_compatible(
+        opts.freq_scale != FrequencyScale::Logarithmic || opts.min_freq > 0.0,
+        "The log frequency scale cannot display 0 Hz, please specify a nonzero --min-freq",
+    )?;
+    ConfigError::check_value(
         opts.freq_res.is_finite() && opts.freq_res > 0.0,
-        "Please specify a sensible frequency resolution"
-    );
-    assert!(
+        "freq-res",
+        opts.freq_res,
+        "a positive frequency resolution",
+    )?;
+    ConfigError::check_value(
         opts.time_res.is_finite() && opts.time_res > 0.0,
-        "Please specify a sensible time resolution"
-    );
-    assert!(
-        opts.amp_range.is_finite(),
-        "Please specify a sensible amplitude scale"
-    );
+        "time-res",
+        opts.time_res,
+        "a positive time resolution",
+    )?;
+    ConfigError::check_value(
+        opts.amp_range.is_finite() && opts.amp_range != 0.0,
+        "amp-range",
+        opts.amp_range,
+        "a nonzero amplitude range",
+    )?;
     opts.amp_range = opts.amp_range.abs();
-    #[cfg(all(feature = "gui", not(feature = "cli")))]
-    assert!(
+    #[cfg(feature = "gui")]
+    ConfigError::check_value(
         opts.spectrogram_refresh.is_finite() && opts.spectrogram_refresh > 0.0,
-        "Please specify a sensible spectrogram refresh rate"
-    );
+        "spectrogram-refresh",
+        opts.spectrogram_refresh,
+        "a positive refresh rate",
+    )?;
+    #[cfg(feature = "cli")]
+    ConfigError::check_value(
+        opts.cli_spectrogram_rate.is_finite() && opts.cli_spectrogram_rate > 0.0,
+        "cli-spectrogram-rate",
+        opts.cli_spectrogram_rate,
+        "a positive refresh rate",
+    )?;
+    #[cfg(feature = "stream")]
+    ConfigError::check_value(
+        opts.stream_bins > 0,
+        "stream-bins",
+        opts.stream_bins,
+        "at least one frequency bin",
+    )?;
+    #[cfg(feature = "stream")]
+    ConfigError::check_value(
+        opts.stream_rate.is_finite() && opts.stream_rate > 0.0,
+        "stream-rate",
+        opts.stream_rate,
+        "a positive spectrum rate",
+    )?;
+    ConfigError::check_value(
+        opts.transfer_averages > 0,
+        "transfer-averages",
+        opts.transfer_averages,
+        "at least one average",
+    )?;
+    ConfigError::check_value(
+        opts.transfer_delay.is_finite() && opts.transfer_delay >= 0.0,
+        "transfer-delay",
+        opts.transfer_delay,
+        "a delay of 0 ms or more",
+    )?;
+    ConfigError::check_value(
+        (0.0..=1.0).contains(&opts.coherence_threshold),
+        "coherence-threshold",
+        opts.coherence_threshold,
+        "a coherence between 0 and 1",
+    )?;
+    if let Some(calibration) = opts.phon_calibration {
+        ConfigError::check_value(
+            calibration.is_finite(),
+            "phon-calibration",
+            calibration,
+            "a finite sound pressure level",
+        )?;
+    }
+    ConfigError::check_compatible(
+        opts.phon_calibration.is_none() || opts.mode != Mode::Transfer,
+        "Loudness levels can't be computed for transfer functions",
+    )?;
+    ConfigError::check_compatible(
+        opts.phon_calibration.is_none() || opts.mode != Mode::Bands,
+        "Loudness levels can't be computed for fractional-octave bands",
+    )?;
+    ConfigError::check_compatible(
+        opts.mode != Mode::Bands || opts.min_freq > 0.0,
+        "Band analysis cannot start at 0 Hz, please specify a nonzero --min-freq",
+    )?;
+    if let Some(smoothing) = opts.smoothing {
+        ConfigError::check_value(
+            smoothing > 0,
+            "smoothing",
+            smoothing,
+            "a positive octave fraction, e.g. 3, 6, 12 or 24",
+        )?;
+    }
+    ConfigError::check_compatible(
+        opts.smoothing.is_none()
+            || (opts.mode != Mode::Bands
+                && (opts.mode != Mode::Transfer || opts.transfer_view == TransferView::Magnitude)),
+        "Smoothing is only supported for spectra and transfer function magnitudes",
+    )?;
+    ConfigError::check_value(
+        opts.thd_orders >= 2,
+        "thd-orders",
+        opts.thd_orders,
+        "a harmonic order of 2 or more",
+    )?;
+    ConfigError::check_value(
+        opts.thd_min_freq.is_finite() && opts.thd_min_freq >= 0.0,
+        "thd-min-freq",
+        opts.thd_min_freq,
+        "a frequency of 0 Hz or more",
+    )?;
+    ConfigError::check_value(
+        opts.thd_max_freq.is_finite() && opts.thd_max_freq > opts.thd_min_freq,
+        "thd-max-freq",
+        opts.thd_max_freq,
+        format!(
+            "a frequency above the distortion measurement's minimum frequency ({} Hz)",
+            opts.thd_min_freq
+        ),
+    )?;
+
+    // Offline analysis does not need the audio stack
+    if let Some(Command::Batch(batch_opts)) = opts.command.take() {
+        return analyze_file(opts, batch_opts);
+    }
 
     // Set up the audio stack
     let audio = AudioSetup::new()?;
     let sample_rate = audio.sample_rate();
-    assert!(
-        opts.max_freq <= (sample_rate / 2) as f32,
-        "Requested max frequency can't be probed at current sampling rate"
+    ConfigError::check_nyquist("max-freq", opts.max_freq, sample_rate)?;
+
+    // Perform the requested measurement instead of live analysis, if any
+    if let Some(Command::Sweep(sweep_opts)) = opts.command.take() {
+        return measure_sweep(audio, opts, sweep_opts);
+    }
+
+    // Perform live analysis in the requested floating-point precision
+    match opts.precision {
+        Precision::Single => analyze_live::<f32>(audio, opts),
+        Precision::Double => analyze_live::<f64>(audio, opts),
+    }
+}
+
+/// Parse command-line arguments, exiting on errors and help requests
+fn parse_args<I>(args: I) -> CliOpts
+where
+    I: IntoIterator,
+    I::Item: Into<std::ffi::OsString> + Clone,
+{
+    CliOpts::from_iter_safe(args).unwrap_or_else(|e| {
+        // Help and version requests are printed to stdout and are not errors
+        if !e.use_stderr() {
+            e.exit()
+        }
+        eprintln!("{}", e.message);
+        std::process::exit(EXIT_CONFIG)
+    })
+}
+
+/// Apply the configuration profile that was selected on the command line,
+/// if any, giving precedence to options that were specified there
+fn apply_profile(opts: CliOpts) -> Result<CliOpts> {
+    // Load the configuration file, which is optional unless it or a profile
+    // were explicitly requested
+    let path = match opts.config.clone().or_else(Config::default_path) {
+        Some(path) => path,
+        None if opts.profile.is_none() => return Ok(opts),
+        None => {
+            return Err(ConfigError::File(
+                "Failed to locate the configuration file, please specify it".to_owned(),
+            )
+            .into())
+        }
+    };
+    if opts.config.is_none() && opts.profile.is_none() && !path.exists() {
+        return Ok(opts);
+    }
+    let config = Config::load(&path).map_err(|e| ConfigError::File(e.to_string()))?;
+
+    // Translate the profile into command-line arguments
+    let name = opts.profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+    let profile_args = config.profile_args(name).map_err(|e| {
+        ConfigError::File(format!(
+            "Invalid configuration file {}: {e}",
+            path.display()
+        ))
+    })?;
+    let profile_args = match profile_args {
+        Some(args) => args,
+        None if opts.profile.is_none() => return Ok(opts),
+        None => {
+            return Err(ConfigError::File(format!(
+                "Profile {name} was not found in {}",
+                path.display()
+            ))
+            .into())
+        }
+    };
+    debug!(
+        "Applying profile {name} from {}: {profile_args:?}",
+        path.display()
     );
 
-    // Set up the Fourier transform
-    let mut fourier =
-        SteadyQTransform::new(opts.freq_res, opts.time_res, sample_rate, &opts.window);
+    // Check the profile on its own, so that errors point to it...
+    let program = std::env::args_os()
+        .next()
+        .unwrap_or_else(|| "spectre".into());
+    CliOpts::from_iter_safe(
+        std::iter::once(program.clone()).chain(profile_args.iter().map(Into::into)),
+    )
+    .map_err(|e| {
+        // Strip the error prefix and usage, which are about the command line
+        let message = e.message.split("\n\n").next().unwrap_or_default();
+        ConfigError::File(format!(
+            "Invalid profile {name} in {}: {}",
+            path.display(),
+            message.trim_start_matches("error: ")
+        ))
+    })?;
+
+    // ...then put it before the actual command-line arguments, which will
+    // override it since the last occurrence of an option wins.
+    Ok(parse_args(
+        std::iter::once(program)
+            .chain(profile_args.into_iter().map(Into::into))
+            .chain(std::env::args_os().skip(1)),
+    ))
+}
+
+/// Analyze the audio input live, computing Fourier transforms in a certain
+/// floating-point precision
+fn analyze_live<T: Float>(audio: AudioSetup, mut opts: CliOpts) -> Result<()> {
+    let sample_rate = audio.sample_rate();
+
+    // Set up the Fourier transform(s)
+    let (anchors, plan_options) = check_live(&opts, sample_rate)?;
+    // (Analyses are rebuilt from the CLI options when these are changed
+    // through the control socket, hence the closures)
+    let new_fourier = move |opts: &CliOpts| {
+        let mut fourier = SteadyQTransform::<T>::with_precision(
+            opts.freq_res,
+            opts.time_res,
+            anchors,
+            sample_rate,
+            &opts.window,
+            plan_options,
+        );
+        fourier.set_detrending(opts.detrend);
+        fourier
+    };
+    if opts.print_plan {
+        print!("{}", new_fourier(&opts).plan());
+        return Ok(());
+    }
+    let setup_analysis = move |opts: &CliOpts| match opts.mode {
+        Mode::Spectrum => Analysis::Spectrum(new_fourier(opts)),
+        Mode::Transfer => {
+            let reference_delay = (opts.transfer_delay * sample_rate as f32 / 1000.0) as usize;
+            let transfer = TransferFunction::new(
+                new_fourier(opts),
+                new_fourier(opts),
+                reference_delay,
+                opts.transfer_averages,
+            );
+            let output = vec![0.0; transfer.output_len()].into_boxed_slice();
+            Analysis::Transfer(transfer, output)
+        }
+        Mode::Distortion => {
+            let fourier = new_fourier(opts);
+            let analyzer = DistortionAnalyzer::new(
+                fourier.output_len(),
+                fourier.window_properties(),
+                sample_rate,
+                opts.thd_min_freq,
+                thd_max_freq(opts, sample_rate),
+                opts.thd_orders,
+            );
+            Analysis::Distortion(fourier, analyzer, None)
+        }
+        Mode::Bands => {
+            let fourier = new_fourier(opts);
+            let bands = OctaveBands::new(
+                opts.octave_fraction,
+                fourier.output_len(),
+                fourier.window_properties(),
+                sample_rate,
+                opts.min_freq,
+                opts.max_freq,
+            );
+            Analysis::Bands(fourier, bands)
+        }
+    };
+    let mut analysis = setup_analysis(&opts);
 
     // Start recording audio, keeping enough history that the audio thread can
     // write two full periods before triggering an FFT input readout overrun.
-    let history_len = if audio.buffer_size() <= fourier.input().len() / 2 {
-        2 * fourier.input().len()
+    let input_len = analysis.input_len();
+    let history_len = if audio.buffer_size() <= input_len / 2 {
+        2 * input_len
     } else {
         4 * audio.buffer_size()
     };
-    let mut recording = audio.start_recording(history_len)?;
+    let mut midi_mapping = match (&opts.midi_mapping, opts.midi) {
+        (Some(path), _) => Some(MidiMapping::load(path)?),
+        (None, true) => Some(MidiMapping::default()),
+        (None, false) => None,
+    };
+    let mut recording = audio.start_recording(
+        history_len,
+        analysis.port_names(),
+        opts.detrend.high_pass_filter(sample_rate),
+        midi_mapping.as_ref().map(|_| "midi"),
+    )?;
+    let mut midi_messages = Vec::new();
 
-    // Initialize the GUI display
-    #[cfg(feature = "cli")]
-    let spectrum_display = crate::display::CliDisplay::new(opts.amp_range)?;
-    #[cfg(all(feature = "gui", not(feature = "cli")))]
-    let spectrum_display =
-        crate::display::GuiDisplay::new(opts.amp_range, opts.spectrogram_refresh)?;
-
-    // Prepare to resample the Fourier transform for display purposes
-    let fourier_len = fourier.output_len();
-    let setup_resampler = move |display_len| {
-        FourierResampler::new(
+    // Initialize the display
+    let spectrum_display = setup_display(&opts)?;
+
+    // Prepare to apply frequency weighting
+    let fourier_len = analysis.output_len();
+    let mut weighting = FrequencyWeighting::new(opts.weighting, fourier_len, sample_rate);
+
+    // Prepare to smooth the spectrum, if requested
+    let mut smoothing = opts
+        .smoothing
+        .map(|bands_per_octave| OctaveSmoothing::new(bands_per_octave, fourier_len));
+
+    // Prepare to convert magnitudes to perceived loudness, if requested
+    let mut loudness = opts
+        .phon_calibration
+        .map(|calibration| LoudnessConverter::new(calibration, fourier_len, sample_rate));
+
+    // Prepare to resample the Fourier transform for display purposes, over a
+    // frequency range that the user can adjust at runtime
+    let mut freq_range = setup_freq_range(&opts, sample_rate);
+    let setup_resampler = move |display_len, freq_range: &FrequencyRange| -> Result<_> {
+        FourierResampler::check_params(
             fourier_len,
             sample_rate,
             display_len,
-            opts.min_freq,
-            opts.max_freq,
-            !opts.lin_freqs,
-        )
+            freq_range.min_freq(),
+            freq_range.max_freq(),
+        )?;
+        Ok(FourierResampler::new(
+            fourier_len,
+            sample_rate,
+            display_len,
+            freq_range.min_freq(),
+            freq_range.max_freq(),
+            freq_range.scale(),
+            opts.resampling,
+        ))
     };
-    let mut resampler = setup_resampler(spectrum_display.spectrum_len());
+    let mut display_len = spectrum_display.spectrum_len();
+    let mut resampler = setup_resampler(display_len, &freq_range)?;
+
+    // Fractional-octave bands are drawn at the center frequency of each display bin
+    let mut bin_freqs = freq_range.bin_frequencies(display_len);
 
     // Handle user shutdown requests (Ctrl+C)
-    let shutdown = Arc::new(AtomicBool::new(false));
-    let shutdown_2 = shutdown.clone();
-    ctrlc::set_handler(move || shutdown_2.store(true, Ordering::Relaxed))?;
+    let shutdown = setup_shutdown()?;
+
+    // Accept commands from scripts, if requested
+    #[cfg(unix)]
+    let mut control = opts
+        .control_socket
+        .as_deref()
+        .map(ControlSocket::bind)
+        .transpose()?;
+    let mut last_spectrum = Vec::new();
+
+    // The display can be frozen remotely
+    let mut paused = false;
+
+    // Status messages are only updated a few times per second, otherwise
+    // they would change too quickly to be readable
+    const STATUS_PERIOD: Duration = Duration::from_millis(250);
+    let mut status = String::new();
+    let mut last_status_update = Instant::now() - STATUS_PERIOD;
 
     // Start computing some FFTs
     let mut last_clock = 0;
-    spectrum_display.run_event_loop(move |display, frame_input| {
-        // Check if the user has requested shutdown via Ctrl+C
-        if shutdown.load(Ordering::Relaxed) {
-            return Ok(FrameResult::Stop);
-        }
+    spectrum_display.run_event_loop(Box::new(
+        move |display: &mut dyn Display, frame_input: FrameInput| {
+            // Check if the user has requested shutdown via Ctrl+C
+            if shutdown.load(Ordering::Relaxed) {
+                return Ok(FrameResult::Stop);
+            }
 
-        // Check if the display width has changed, recreate resampler if need be
-        if let Some(new_spectrum_len) = frame_input.new_spectrum_len {
-            resampler = setup_resampler(new_spectrum_len);
-        }
+            // Check if the display width has changed, recreate resampler if need be
+            if let Some(new_spectrum_len) = frame_input.new_spectrum_len {
+                display_len = new_spectrum_len;
+                resampler = setup_resampler(display_len, &freq_range)?;
+                bin_freqs = freq_range.bin_frequencies(display_len);
+            }
 
-        // Read latest audio history, handle xruns and audio thread errors
-        let mut underrun = false;
-        let mut overrun = None;
-        last_clock = match recording.read_history(fourier.input()) {
-            // Successfully read latest FFT history with a certain timestamp
-            Ok(Ok(clock)) => {
-                if clock == last_clock {
-                    underrun = true;
+            // Handle MIDI remote control, which can emulate user commands
+            let mut commands = frame_input.commands;
+            if let Some(midi_mapping) = &mut midi_mapping {
+                recording.read_midi(&mut midi_messages);
+                for message in midi_messages.drain(..) {
+                    match midi_mapping.action(message) {
+                        Some(MidiAction::CycleWindow) => {
+                            let idx = fourier::WINDOWS
+                                .iter()
+                                .position(|&window| window == opts.window)
+                                .map_or(0, |idx| (idx + 1) % fourier::WINDOWS.len());
+                            opts.window = fourier::WINDOWS[idx].to_owned();
+                            info!("Switching to {} window", opts.window);
+                            analysis = setup_analysis(&opts);
+                        }
+                        Some(MidiAction::AmpRange(amp_range)) => {
+                            opts.amp_range = amp_range;
+                            display.set_amp_scale(amp_range)?;
+                        }
+                        Some(MidiAction::Command(command)) => commands.push(command),
+                        None => {}
+                    }
                 }
-                clock
             }
 
-            // Some history was overwritten by the audio thread (overrun)
-            Ok(Err(Overrun {
-                clock,
-                excess_entries,
-            })) => {
-                overrun = Some(excess_entries);
-                clock
+            // Handle user commands
+            for command in commands {
+                match command {
+                    UserCommand::CycleWeighting => {
+                        let new_weighting = weighting.weighting().next();
+                        info!("Switching to {new_weighting:?}-weighting");
+                        weighting.set_weighting(new_weighting);
+                    }
+                    UserCommand::ToggleFreeze => {
+                        paused = !paused;
+                        info!("Display is now {}", if paused { "frozen" } else { "live" });
+                    }
+                    UserCommand::AdjustAmpRange { delta } => {
+                        opts.amp_range = (opts.amp_range + delta).max(AMP_RANGE_STEP);
+                        info!("Switching to a {} dB amplitude range", opts.amp_range);
+                        display.set_amp_scale(opts.amp_range)?;
+                    }
+                    UserCommand::ScaleAverages { factor } => {
+                        if opts.mode == Mode::Transfer {
+                            opts.transfer_averages =
+                                ((opts.transfer_averages as f32 * factor).round() as usize).max(1);
+                            info!("Averaging {} spectra", opts.transfer_averages);
+                            analysis = setup_analysis(&opts);
+                        }
+                    }
+                    _ => adjust_freq_range(&mut freq_range, command),
+                }
             }
 
-            // The audio threads have crashed, report their errors and die
-            mut audio_error @ Err(_) => {
-                let terminal_reset_result = display.reset_terminal();
-                while let Err(error) = audio_error {
-                    error!("Audio thread error: {error:?}");
-                    audio_error = recording.read_history(fourier.input());
+            // Handle commands from the control socket
+            #[cfg(unix)]
+            if let Some(control) = control.as_mut() {
+                for request in control.poll() {
+                    let reply = match request.command {
+                        ControlCommand::AmpRange(amp_range) => {
+                            opts.amp_range = amp_range;
+                            display.set_amp_scale(amp_range).map(|()| String::new())
+                        }
+                        ControlCommand::FreqRange(min_freq, max_freq) => {
+                            // Only switch to ranges that can be displayed
+                            let mut new_range = freq_range.clone();
+                            new_range.set_visible((min_freq, max_freq));
+                            setup_resampler(display_len, &new_range).map(|_| {
+                                let payload = control::range_payload(
+                                    new_range.min_freq(),
+                                    new_range.max_freq(),
+                                );
+                                freq_range = new_range;
+                                payload
+                            })
+                        }
+                        ControlCommand::Window(ref window) => {
+                            opts.window = window.clone();
+                            analysis = setup_analysis(&opts);
+                            Ok(String::new())
+                        }
+                        ControlCommand::Averages(averages) => {
+                            // Like the keyboard shortcut, this only makes
+                            // sense for transfer functions
+                            if opts.mode == Mode::Transfer {
+                                opts.transfer_averages = averages;
+                                analysis = setup_analysis(&opts);
+                                Ok(String::new())
+                            } else {
+                                Err(anyhow::format_err!(
+                                    "Averages only apply to --mode transfer"
+                                ))
+                            }
+                        }
+                        ControlCommand::Pause => {
+                            paused = true;
+                            Ok(String::new())
+                        }
+                        ControlCommand::Resume => {
+                            paused = false;
+                            Ok(String::new())
+                        }
+                        ControlCommand::Spectrum => Ok(control::spectrum_payload(
+                            &freq_range.bin_frequencies(last_spectrum.len()),
+                            &last_spectrum,
+                        )),
+                        ControlCommand::Peaks(num_peaks) => Ok(control::peaks_payload(
+                            &freq_range.bin_frequencies(last_spectrum.len()),
+                            &last_spectrum,
+                            num_peaks,
+                        )),
+                    };
+                    control.reply(&request, reply);
                 }
-                error!("Audio thread exited due to errors, time to die...");
-                return terminal_reset_result.map(|()| FrameResult::Stop);
             }
-        };
 
-        // Display the result of the data acquisition
-        match (underrun, overrun) {
-            // Everything went fine
-            (false, None) => {
-                // Compute the Fourier transform
-                let fft_amps = fourier.compute();
+            // Check if the visible frequency range has changed, recreate resampler
+            // and rescale previously displayed data if need be
+            if let Some(new_range) = freq_range.commit() {
+                resampler = setup_resampler(display_len, &freq_range)?;
+                bin_freqs = freq_range.bin_frequencies(display_len);
+                display.reframe_frequencies(new_range)?;
+            }
+
+            // Leave the display alone while paused
+            if paused {
+                return Ok(FrameResult::Continue);
+            }
 
-                // Resample it to the desired number of output bins
-                let output_bins = resampler.resample(fft_amps);
+            // Read latest audio history, handle xruns and audio thread errors
+            let mut underrun = false;
+            let mut overrun = None;
+            last_clock = match analysis.read_history(&mut recording) {
+                // Successfully read latest FFT history with a certain timestamp
+                Ok(Ok(clock)) => {
+                    if clock == last_clock {
+                        underrun = true;
+                    }
+                    clock
+                }
+
+                // Some history was overwritten by the audio thread (overrun)
+                Ok(Err(Overrun {
+                    clock,
+                    excess_entries,
+                })) => {
+                    overrun = Some(excess_entries);
+                    clock
+                }
+
+                // The audio threads have crashed, report their errors and die
+                mut audio_error @ Err(_) => {
+                    let terminal_reset_result = display.reset_terminal();
+                    while let Err(error) = audio_error {
+                        error!("Audio thread error: {error:?}");
+                        audio_error = analysis.read_history(&mut recording);
+                    }
+                    error!("Audio thread exited due to errors, time to die...");
+                    return terminal_reset_result.map(|()| FrameResult::Stop);
+                }
+            };
+
+            // Display the result of the data acquisition
+            match (underrun, overrun) {
+                // Everything went fine
+                (false, None) => {
+                    // Perform the analysis
+                    let fft_amps = analysis.compute(&opts, &mut weighting);
+
+                    // Smooth it if requested
+                    let fft_amps = match smoothing.as_mut() {
+                        Some(smoothing) => smoothing.smooth(fft_amps),
+                        None => fft_amps,
+                    };
+
+                    // Convert it to perceived loudness if requested
+                    let fft_amps = match loudness.as_mut() {
+                        Some(loudness) => loudness.apply(fft_amps),
+                        None => fft_amps,
+                    };
+
+                    // Resample it to the desired number of output bins, or draw
+                    // fractional-octave bands as a bar graph, which only needs
+                    // the band levels of the analysis
+                    let output_bins = if opts.mode == Mode::Bands {
+                        match &mut analysis {
+                            Analysis::Bands(_, bands) => bands.bar_graph(&bin_freqs),
+                            _ => unreachable!("Bands mode always sets up a bands analysis"),
+                        }
+                    } else {
+                        resampler.resample(fft_amps)
+                    };
+
+                    // Display the resampled FFT bins, keep them around for queries
+                    display.render(output_bins)?;
+                    last_spectrum.clear();
+                    last_spectrum.extend_from_slice(output_bins);
+
+                    // Display the analysis summary, if any
+                    if last_status_update.elapsed() >= STATUS_PERIOD {
+                        if let Some(new_status) = analysis.status() {
+                            status = new_status;
+                            last_status_update = Instant::now();
+                        }
+                    }
+                    if !status.is_empty() {
+                        display.report_status(&status)?;
+                    }
+                }
+
+                // Buffer underrun (no new data)
+                (true, _) => {
+                    // FIXME: Also report xruns on the GUI display
+                    display.report_underrun()?;
+                }
 
-                // Display the resampled FFT bins
-                display.render(output_bins)?;
+                // Buffer overrun (audio thread overwrote buffer while we were reading)
+                (false, Some(excess_samples)) => {
+                    display.report_overrun(excess_samples)?;
+                }
             }
 
-            // Buffer underrun (no new data)
-            (true, _) => { /* FIXME: display.report_underrun()?; */ }
+            // All good and ready for the next frame
+            Ok(FrameResult::Continue)
+        },
+    ))
+}
+
+/// Measure an impulse response with an exponential sweep, display the result
+fn measure_sweep(audio: AudioSetup, opts: CliOpts, sweep_opts: SweepOpts) -> Result<()> {
+    // Validate sweep parameters
+    ConfigError::check_compatible(
+        opts.min_freq > 0.0,
+        "Sweeps cannot start at 0 Hz, please specify a nonzero --min-freq",
+    )?;
+    ConfigError::check_value(
+        sweep_opts.duration.is_finite() && sweep_opts.duration > 0.0,
+        "duration",
+        sweep_opts.duration,
+        "a positive sweep duration",
+    )?;
+    ConfigError::check_value(
+        sweep_opts.level.is_finite() && sweep_opts.level <= 0.0,
+        "level",
+        sweep_opts.level,
+        "a sweep level of 0 dBFS or less",
+    )?;
+    ConfigError::check_value(
+        sweep_opts.tail.is_finite() && sweep_opts.tail >= 0.0,
+        "tail",
+        sweep_opts.tail,
+        "a recording tail duration of 0 s or more",
+    )?;
+    ConfigError::check_value(
+        sweep_opts.ir_len.is_finite() && sweep_opts.ir_len > 0.0,
+        "ir-len",
+        sweep_opts.ir_len,
+        "a positive impulse response length",
+    )?;
+    ConfigError::check_value(
+        (1..=sweep_opts.orders).contains(&sweep_opts.show_order),
+        "show-order",
+        sweep_opts.show_order,
+        format!("an analyzed harmonic order (1 to {})", sweep_opts.orders),
+    )?;
+
+    // Perform the measurement
+    let sample_rate = audio.sample_rate();
+    let sweep = ExponentialSweep::new(
+        opts.min_freq,
+        opts.max_freq,
+        sweep_opts.duration,
+        10.0f32.powf(sweep_opts.level / 20.0),
+        sample_rate,
+    );
+    let record_len = sweep.signal().len() + (sweep_opts.tail * sample_rate as f32) as usize;
+    info!("Playing a {} s exponential sweep...", sweep_opts.duration);
+    let recording = audio.play_and_record(
+        sweep.signal().into(),
+        record_len,
+        sweep_opts.playback_port.as_deref(),
+        sweep_opts.capture_port.as_deref(),
+    )?;
+
+    // Extract the impulse response and harmonic frequency responses
+    let impulse_response = sweep.deconvolve(&recording);
+    let max_ir_len = ((sweep_opts.ir_len * sample_rate as f32 / 1000.0) as usize).max(2);
+    let responses = impulse_response.harmonic_responses(sweep_opts.orders, max_ir_len);
 
-            // Buffer overrun (audio thread overwrote buffer while we were reading)
-            (false, Some(excess_samples)) => { /* FIXME: display.report_overrun(excess_samples)?; */
+    // Report harmonic distortion levels (this is printed before the display
+    // starts, so that it remains visible in the terminal afterwards)
+    if sweep_opts.orders > 1 {
+        println!("Average harmonic distortion, relative to the linear response:");
+        for order in 2..=sweep_opts.orders {
+            match responses.average_distortion(order, opts.min_freq, opts.max_freq, sample_rate) {
+                Some(level) => println!("- Order {order}: {level:.1} dB"),
+                None => println!("- Order {order}: n/a"),
             }
         }
+    }
 
-        // All good and ready for the next frame
-        return Ok(FrameResult::Continue);
-    })
+    // Prepare the frequency response for display, smoothing it if requested
+    let response = responses.order(sweep_opts.show_order);
+    let response = match opts.smoothing {
+        Some(bands_per_octave) => Box::<[f32]>::from(
+            OctaveSmoothing::new(bands_per_octave, response.len()).smooth(response),
+        ),
+        None => Box::<[f32]>::from(response),
+    };
+    let shown_response = if sweep_opts.show_order == 1 {
+        let offset = 0.5 * opts.amp_range;
+        response.iter().map(|&x| x - offset).collect()
+    } else {
+        response
+    };
+
+    // Display it until the user gets bored
+    let spectrum_display = setup_display(&opts)?;
+    let response_len = shown_response.len();
+    let mut freq_range = setup_freq_range(&opts, sample_rate);
+    let setup_resampler = move |display_len, freq_range: &FrequencyRange| -> Result<_> {
+        FourierResampler::check_params(
+            response_len,
+            sample_rate,
+            display_len,
+            freq_range.min_freq(),
+            freq_range.max_freq(),
+        )?;
+        Ok(FourierResampler::new(
+            response_len,
+            sample_rate,
+            display_len,
+            freq_range.min_freq(),
+            freq_range.max_freq(),
+            freq_range.scale(),
+            opts.resampling,
+        ))
+    };
+    let mut display_len = spectrum_display.spectrum_len();
+    let mut resampler = setup_resampler(display_len, &freq_range)?;
+    let shutdown = setup_shutdown()?;
+    spectrum_display.run_event_loop(Box::new(
+        move |display: &mut dyn Display, frame_input: FrameInput| {
+            if shutdown.load(Ordering::Relaxed) {
+                return Ok(FrameResult::Stop);
+            }
+            if let Some(new_spectrum_len) = frame_input.new_spectrum_len {
+                display_len = new_spectrum_len;
+                resampler = setup_resampler(display_len, &freq_range)?;
+            }
+            for command in frame_input.commands {
+                adjust_freq_range(&mut freq_range, command);
+            }
+            if let Some(new_range) = freq_range.commit() {
+                resampler = setup_resampler(display_len, &freq_range)?;
+                display.reframe_frequencies(new_range)?;
+            }
+            display.render(resampler.resample(&shown_response))?;
+            Ok(FrameResult::Continue)
+        },
+    ))
+}
+
+/// Compute the spectrogram of a WAV file, save it to disk
+fn analyze_file(opts: CliOpts, batch_opts: BatchOpts) -> Result<()> {
+    // Validate batch parameters
+    ConfigError::check_value(
+        batch_opts.hop.is_finite() && batch_opts.hop > 0.0,
+        "hop",
+        batch_opts.hop,
+        "a positive hop duration",
+    )?;
+    ConfigError::check_value(
+        batch_opts.bins > 0,
+        "bins",
+        batch_opts.bins,
+        "at least one frequency bin",
+    )?;
+    let extension = batch_opts
+        .output
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    ConfigError::check_compatible(
+        matches!(extension.as_deref(), Some("csv" | "npy")),
+        "Please specify an output file with a .csv or .npy extension",
+    )?;
+    if opts.mode != Mode::Spectrum
+        || opts.weighting != Weighting::Z
+        || opts.smoothing.is_some()
+        || opts.phon_calibration.is_some()
+    {
+        warn!(
+            "Batch analysis computes plain spectra, ignoring --mode, --weighting, --smoothing \
+             and --phon-calibration"
+        );
+    }
+
+    // Load the recording
+    let audio = wav::read(&batch_opts.input)?;
+    let sample_rate = audio.sample_rate;
+    ConfigError::check_value(
+        batch_opts.channel < audio.channels.len(),
+        "channel",
+        batch_opts.channel,
+        format!(
+            "a channel that exists in the input file (0 to {})",
+            audio.channels.len() - 1
+        ),
+    )?;
+    ConfigError::check_nyquist("max-freq", opts.max_freq, sample_rate)?;
+    check_high_pass(opts.detrend, sample_rate)?;
+    let hop = ((batch_opts.hop * sample_rate as f32 / 1000.0).round() as usize).max(1);
+
+    // Set up the analyzer
+    let (anchors, plan_options) = transform_params(&opts, sample_rate);
+    let fourier_len = SteadyQTransform::output_len_for(
+        opts.freq_res,
+        opts.time_res,
+        anchors,
+        sample_rate,
+        plan_options,
+    )?;
+    FourierResampler::check_params(
+        fourier_len,
+        sample_rate,
+        batch_opts.bins,
+        opts.min_freq,
+        opts.max_freq,
+    )?;
+    let builder = Analyzer::builder(sample_rate)
+        .freq_res(opts.freq_res)
+        .time_res(opts.time_res)
+        .window(opts.window.clone())
+        .detrending(opts.detrend)
+        .freq_range(opts.min_freq, opts.max_freq)
+        .anchor_freqs(anchors.0, anchors.1)
+        .plan_options(plan_options)
+        .freq_scale(opts.freq_scale.clone())
+        .resampling(opts.resampling)
+        .output_len(batch_opts.bins);
+
+    // Compute the spectrogram in the requested floating-point precision
+    info!(
+        "Analyzing {} s of audio...",
+        audio.channels[batch_opts.channel].len() as f32 / sample_rate as f32
+    );
+    fn compute<T: Float>(builder: AnalyzerBuilder, samples: &[f32], hop: usize) -> Spectrogram {
+        Spectrogram::compute(&mut builder.build_with_precision::<T>(), samples, hop)
+    }
+    let samples = &audio.channels[batch_opts.channel][..];
+    let spectrogram = match opts.precision {
+        Precision::Single => compute::<f32>(builder, samples, hop),
+        Precision::Double => compute::<f64>(builder, samples, hop),
+    };
+
+    // Save it to disk, making sure that buffered writes succeed
+    fn save(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+        let file = File::create(path)
+            .map_err(|e| anyhow::format_err!("Failed to create {}: {e}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)
+            .and_then(|()| Ok(writer.flush()?))
+            .map_err(|e| anyhow::format_err!("Failed to write {}: {e}", path.display()))
+    }
+    if extension.as_deref() == Some("csv") {
+        save(&batch_opts.output, |writer| spectrogram.write_csv(writer))?;
+    } else {
+        let stem = batch_opts
+            .output
+            .file_stem()
+            .expect("Output path should have a file name")
+            .to_string_lossy();
+        let axis_path = |suffix: &str| {
+            batch_opts
+                .output
+                .with_file_name(format!("{stem}_{suffix}.npy"))
+        };
+        save(&batch_opts.output, |writer| spectrogram.write_npy(writer))?;
+        save(&axis_path("times"), |writer| {
+            spectrogram.write_npy_times(writer)
+        })?;
+        save(&axis_path("freqs"), |writer| {
+            spectrogram.write_npy_freqs(writer)
+        })?;
+    }
+    info!(
+        "Saved {} spectra of {} bins to {}",
+        spectrogram.times().len(),
+        spectrogram.freqs().len(),
+        batch_opts.output.display()
+    );
+    Ok(())
+}
+
+/// Check that the high-pass detrending filter, if any, has a cutoff frequency
+/// below the Nyquist frequency
+fn check_high_pass(detrending: Detrending, sample_rate: usize) -> Result<()> {
+    if let Detrending::HighPass(cutoff) = detrending {
+        ConfigError::check_value(
+            cutoff < (sample_rate / 2) as f32,
+            "detrend",
+            format!("highpass:{cutoff}"),
+            format!("a high-pass cutoff frequency below {} Hz", sample_rate / 2),
+        )?;
+    }
+    Ok(())
+}
+
+/// Deduce the anchor frequencies and plan options of the constant-Q transform
+/// from the CLI options
+fn transform_params(opts: &CliOpts, sample_rate: usize) -> ((f32, f32), PlanOptions) {
+    let default_anchors =
+        SteadyQTransform::default_anchors(opts.min_freq, opts.max_freq, sample_rate);
+    let anchors = (
+        opts.low_anchor.unwrap_or(default_anchors.0),
+        opts.high_anchor.unwrap_or(default_anchors.1),
+    );
+    let plan_options = PlanOptions {
+        ffts_per_octave: opts.ffts_per_octave,
+        blend: !opts.no_blend,
+    };
+    (anchors, plan_options)
+}
+
+/// Check the live analysis settings that can only be validated once the
+/// sampling rate is known, return the constant-Q transform parameters
+fn check_live(opts: &CliOpts, sample_rate: usize) -> Result<((f32, f32), PlanOptions)> {
+    let (anchors, plan_options) = transform_params(opts, sample_rate);
+    let spectrum_len = SteadyQTransform::output_len_for(
+        opts.freq_res,
+        opts.time_res,
+        anchors,
+        sample_rate,
+        plan_options,
+    )?;
+    check_high_pass(opts.detrend, sample_rate)?;
+    check_analysis(opts, sample_rate, spectrum_len)?;
+    Ok((anchors, plan_options))
+}
+
+/// Check the parameters that are specific to the selected analysis mode,
+/// given the length of the spectra that the constant-Q transform computes
+fn check_analysis(opts: &CliOpts, sample_rate: usize, spectrum_len: usize) -> Result<()> {
+    if opts.mode == Mode::Distortion {
+        let nyquist = (sample_rate / 2) as f32;
+        ConfigError::check_value(
+            opts.thd_min_freq < nyquist,
+            "thd-min-freq",
+            opts.thd_min_freq,
+            format!("a frequency below the Nyquist frequency ({nyquist} Hz)"),
+        )?;
+        ConfigError::check_compatible(
+            DistortionAnalyzer::resolves_range(
+                spectrum_len,
+                sample_rate,
+                opts.thd_min_freq,
+                thd_max_freq(opts, sample_rate),
+            ),
+            "The distortion measurement bandwidth (--thd-min-freq to --thd-max-freq) is \
+             narrower than the spectrum resolution, please widen it or use a finer --freq-res",
+        )?;
+    }
+    if opts.mode == Mode::Bands {
+        OctaveBands::check_params(
+            opts.octave_fraction,
+            sample_rate,
+            opts.min_freq,
+            opts.max_freq,
+        )?;
+    }
+    Ok(())
+}
+
+/// Upper bound of the distortion measurement bandwidth, which stops at the
+/// Nyquist frequency
+fn thd_max_freq(opts: &CliOpts, sample_rate: usize) -> f32 {
+    opts.thd_max_freq.min((sample_rate / 2) as f32)
+}
+
+/// Set up the displayed frequency range, which the user can adjust at runtime
+fn setup_freq_range(opts: &CliOpts, sample_rate: usize) -> FrequencyRange {
+    // Zooming out can reveal frequencies up to the Nyquist frequency, and
+    // down to 0Hz unless the frequency scale cannot display it
+    let min_limit = if opts.freq_scale.position(0.0).is_finite() {
+        0.0
+    } else {
+        opts.min_freq
+    };
+    FrequencyRange::new(
+        opts.freq_scale.clone(),
+        (opts.min_freq, opts.max_freq),
+        (min_limit, (sample_rate / 2) as f32),
+    )
+}
+
+/// Apply user commands that adjust the displayed frequency range, ignore
+/// other commands
+fn adjust_freq_range(freq_range: &mut FrequencyRange, command: UserCommand) {
+    match command {
+        UserCommand::ZoomFrequencies { center, factor } => freq_range.zoom(center, factor),
+        UserCommand::PanFrequencies { delta } => freq_range.pan(delta),
+        UserCommand::ResetFrequencies => freq_range.reset(),
+        UserCommand::CycleWeighting
+        | UserCommand::ToggleFreeze
+        | UserCommand::AdjustAmpRange { .. }
+        | UserCommand::ScaleAverages { .. } => {}
+    }
+}
+
+/// Initialize the display
+fn setup_display(opts: &CliOpts) -> Result<Box<dyn Display>> {
+    // Pick the requested display, or the best one that was built in
+    let default_kind = DisplayKind::from_str(DISPLAYS[0])?;
+    let kind = opts.display.unwrap_or(default_kind);
+    match kind {
+        #[cfg(feature = "gui")]
+        DisplayKind::Gui => {
+            let gui = crate::display::GuiDisplay::new(opts.amp_range, opts.spectrogram_refresh);
+            // Fall back to the terminal if the GUI was not explicitly requested
+            #[cfg(feature = "cli")]
+            if let (Err(e), None) = (&gui, opts.display) {
+                log::warn!("Failed to set up the GUI ({e}), falling back to the terminal");
+                return Ok(Box::new(crate::display::CliDisplay::new(
+                    opts.amp_range,
+                    opts.cli_view,
+                    opts.cli_plot,
+                    opts.cli_spectrogram_rate,
+                )?));
+            }
+            Ok(Box::new(gui?))
+        }
+        #[cfg(feature = "cli")]
+        DisplayKind::Cli => Ok(Box::new(crate::display::CliDisplay::new(
+            opts.amp_range,
+            opts.cli_view,
+            opts.cli_plot,
+            opts.cli_spectrogram_rate,
+        )?)),
+        #[cfg(feature = "stream")]
+        DisplayKind::Headless => Ok(Box::new(crate::display::StreamDisplay::new(
+            opts.stream_format,
+            opts.stream_output.as_deref(),
+            opts.stream_bins,
+            opts.freq_scale.clone(),
+            (opts.min_freq, opts.max_freq),
+            opts.stream_rate,
+        )?)),
+    }
+}
+
+/// Handle user shutdown requests (Ctrl+C)
+fn setup_shutdown() -> Result<Arc<AtomicBool>> {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let shutdown_2 = shutdown.clone();
+    ctrlc::set_handler(move || shutdown_2.store(true, Ordering::Relaxed))?;
+    Ok(shutdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_settings() {
+        // Parse command-line arguments, as if they came after "spectre"
+        let parse = |args: &[&str]| parse_args(std::iter::once("spectre").chain(args.to_vec()));
+
+        // Live analysis settings that can't be used at the sampling rate are
+        // reported as configuration errors, not panics
+        assert!(check_live(&parse(&[]), 48_000).is_ok());
+        for args in [
+            "--mode bands --octave-fraction 1 --min-freq 1200 --max-freq 1900",
+            "--mode distortion --thd-min-freq 30000 --thd-max-freq 40000",
+            "--mode distortion --thd-min-freq 1000 --thd-max-freq 1000.1",
+            "--freq-res 0.1 --time-res 1 --low-anchor 100 --high-anchor 200",
+        ] {
+            let opts = parse(&args.split_whitespace().collect::<Vec<_>>());
+            let error = check_live(&opts, 48_000).unwrap_err();
+            assert_eq!(exit_code(&error), EXIT_CONFIG, "{args} gave {error}");
+        }
+
+        // Same goes for batch analysis, whose sampling rate comes from a file
+        let dir = std::env::temp_dir().join(format!("spectre-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("in.wav"), dir.join("out.csv"));
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0".to_vec();
+        wav.extend_from_slice(&48_000u32.to_le_bytes());
+        wav.extend_from_slice(&96_000u32.to_le_bytes());
+        wav.extend_from_slice(b"\x02\0\x10\0data");
+        wav.extend_from_slice(&9600u32.to_le_bytes());
+        wav.resize(wav.len() + 9600, 0);
+        std::fs::write(&input, wav).unwrap();
+        let batch = |settings: &[&str]| {
+            let files = [input.to_str().unwrap(), output.to_str().unwrap()];
+            let mut opts = parse(&[settings, &["batch"], &files[..]].concat());
+            let batch_opts = match opts.command.take() {
+                Some(Command::Batch(batch_opts)) => batch_opts,
+                other => panic!("Unexpected command {other:?}"),
+            };
+            analyze_file(opts, batch_opts)
+        };
+        assert!(batch(&[]).is_ok());
+        let error = batch(&["--freq-res", "100000", "--time-res", "0.01"]).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_CONFIG);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -2,10 +2,47 @@
 
 mod simd;
 
-use realfft::num_complex::Complex;
+use realfft::{
+    num_complex::Complex,
+    num_traits::{self, AsPrimitive, NumAssign},
+    FftNum,
+};
 
 pub use simd::sum_f32_fast;
 
+/// Floating-point type in which signal processing can be carried out
+///
+/// Single precision is enough for most purposes, but double precision may be
+/// needed to trust the lowest levels of long Fourier transforms.
+///
+pub trait Float: FftNum + num_traits::Float + NumAssign + AsPrimitive<f32> {
+    /// Convert a single-precision number, such as an audio sample
+    fn widen(x: f32) -> Self;
+
+    /// Sum a slice of numbers, trading some accuracy for speed
+    fn sum_fast(input: &[Self]) -> Self;
+}
+//
+impl Float for f32 {
+    fn widen(x: f32) -> Self {
+        x
+    }
+
+    fn sum_fast(input: &[Self]) -> Self {
+        sum_f32_fast(input)
+    }
+}
+//
+impl Float for f64 {
+    fn widen(x: f32) -> Self {
+        x.into()
+    }
+
+    fn sum_fast(input: &[Self]) -> Self {
+        input.iter().sum()
+    }
+}
+
 /// Interpolate a table of complex numbers into a series that is ~Nx larger
 pub fn interpolate_c32(
     input: &[Complex<f32>],
@@ -26,6 +63,27 @@ pub fn interpolate_c32(
         .chain(input.last().cloned())
 }
 
+/// Linearly resample a table of complex numbers into a series of a certain
+/// length, such that the first and last elements are kept in place
+///
+/// This is a generalization of `interpolate_c32()` to non-integer ratios
+/// between the output and input lengths, and to any floating-point type.
+///
+pub fn resample_complex<T: Float>(
+    input: &[Complex<T>],
+    output_len: usize,
+) -> impl Iterator<Item = Complex<T>> + '_ {
+    assert!(input.len() >= 2 && output_len >= 2);
+    let last_left_idx = input.len() - 2;
+    let step = (input.len() - 1) as f32 / (output_len - 1) as f32;
+    (0..output_len).map(move |idx| {
+        let position = idx as f32 * step;
+        let left_idx = (position as usize).min(last_left_idx);
+        let weight = T::widen(position - left_idx as f32);
+        input[left_idx] * (T::one() - weight) + input[left_idx + 1] * weight
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -52,7 +110,7 @@ mod tests {
         let output = super::interpolate_c32(&input[..], stride).collect::<Box<[_]>>();
 
         // Check that the interpolant has the right length
-        let expected_len = if input.len() > 0 {
+        let expected_len = if !input.is_empty() {
             stride * (input.len() - 1) + 1
         } else {
             0
@@ -70,4 +128,36 @@ mod tests {
         }
         TestResult::passed()
     }
+    #[quickcheck]
+    fn resample_complex(input: Vec<f32>, stride: usize) -> TestResult {
+        // Ignore silly configurations and keep output below 4KB
+        if input.len() < 3
+            || input.iter().any(|x| !x.is_finite() || x.abs() > 1e30)
+            || stride == 0
+            || input.len().saturating_mul(stride) > 1_024
+        {
+            return TestResult::discard();
+        }
+
+        // Turn real input into complex input
+        let input = input
+            .windows(2)
+            .map(|pair| Complex::new(pair[0], pair[1]))
+            .collect::<Box<[_]>>();
+
+        // With an integer length ratio, resampling should be equivalent to
+        // interpolation, up to rounding errors
+        let output_len = stride * (input.len() - 1) + 1;
+        let output = super::resample_complex(&input[..], output_len).collect::<Box<[_]>>();
+        assert_eq!(output.len(), output_len);
+        assert_eq!(output[0], input[0]);
+        let interpolant = super::interpolate_c32(&input[..], stride);
+        for (idx, (output, expected)) in output.iter().zip(interpolant).enumerate() {
+            let left = input[idx / stride];
+            let right = input[(idx / stride + 1).min(input.len() - 1)];
+            let tolerance = 1e-5 * (left.norm() + right.norm());
+            assert!((output - expected).norm() <= tolerance);
+        }
+        TestResult::passed()
+    }
 }
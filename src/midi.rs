@@ -0,0 +1,304 @@
+//! MIDI remote control, e.g. from a live sound engineer's control surface
+//!
+//! MIDI messages are mapped to actions by a mapping file, where each line
+//! maps a note or control change (CC) to an action:
+//!
+//! ```text
+//! # Comments start with a hash sign
+//! note <channel> <note number> <action>
+//! cc <channel> <controller number> <action>
+//! ```
+//!
+//! Channels go from 1 to 16, and "*" matches any channel. Supported actions
+//! are "freeze" (toggle display freezing), "cycle-window", "cycle-weighting",
+//! "zoom-in", "zoom-out", "pan-left", "pan-right" and "reset-frequencies",
+//! which are triggered by note on messages and by CC values crossing from
+//! below 64 to 64 and above, and "amp-range <min dB> <max dB>", which maps
+//! CC values from 0 to 127 to an amplitude range and cannot be mapped to
+//! notes.
+
+use crate::{
+    display::{UserCommand, PAN_STEP, ZOOM_STEP},
+    Result,
+};
+use spectre::audio::MidiMessage;
+use std::{path::Path, str::FromStr};
+
+/// Mapping that is used when MIDI input is enabled without a mapping file
+const DEFAULT_MAPPING: &str = "
+    note * 60 freeze
+    note * 62 cycle-window
+    note * 64 cycle-weighting
+    note * 65 zoom-in
+    note * 67 zoom-out
+    note * 69 pan-left
+    note * 71 pan-right
+    note * 72 reset-frequencies
+    cc * 7 amp-range 20 160
+";
+
+/// Action triggered by a MIDI message
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MidiAction {
+    /// Switch to the next window function
+    CycleWindow,
+
+    /// Change the displayed amplitude range, in dB
+    AmpRange(f32),
+
+    /// Same effect as a user command from the display
+    Command(UserCommand),
+}
+
+/// Kind of MIDI message that a mapping entry reacts to
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Trigger {
+    /// Note on message with a certain note number
+    Note(u8),
+
+    /// Control change message with a certain controller number
+    ControlChange(u8),
+}
+
+/// Action of a mapping entry, before the MIDI message is known
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MappedAction {
+    /// Action that is triggered by a note on or a high CC value
+    Button(MidiAction),
+
+    /// Amplitude range set from a CC value, between a minimum and maximum
+    AmpRange(f32, f32),
+}
+
+/// Entry of a MIDI mapping
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct MappingEntry {
+    /// MIDI channel (0-15), or None to match any channel
+    channel: Option<u8>,
+
+    /// Messages that this entry reacts to
+    trigger: Trigger,
+
+    /// What should be done when a matching message is received
+    action: MappedAction,
+}
+
+/// Mapping from MIDI messages to analyzer actions
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiMapping {
+    /// Mapping entries, by decreasing priority
+    entries: Box<[MappingEntry]>,
+
+    /// Last CC value received by each entry, used to detect button presses
+    last_values: Box<[u8]>,
+}
+//
+impl MidiMapping {
+    /// Load a mapping file
+    pub fn load(path: &Path) -> Result<Self> {
+        let mapping = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::format_err!("Failed to read {}: {e}", path.display()))?;
+        mapping
+            .parse()
+            .map_err(|e| anyhow::format_err!("Invalid MIDI mapping {}: {e}", path.display()))
+    }
+
+    /// Determine what should be done in response to a MIDI message, if
+    /// anything
+    ///
+    /// If several mapping entries match a message, the first one wins. CC
+    /// buttons only trigger when their value rises from below 64 to 64 and
+    /// above, so that controllers which send a stream of high values while a
+    /// button is held only trigger the action once.
+    ///
+    pub fn action(&mut self, [status, data1, data2]: MidiMessage) -> Option<MidiAction> {
+        let channel = status & 0x0F;
+        let (trigger, value) = match status & 0xF0 {
+            // Note on messages press buttons whatever their velocity
+            0x90 if data2 > 0 => (Trigger::Note(data1), 127),
+            0xB0 => (Trigger::ControlChange(data1), data2),
+            _ => return None,
+        };
+        let idx = self.entries.iter().position(|entry| {
+            entry.trigger == trigger && entry.channel.map_or(true, |c| c == channel)
+        })?;
+        let last_value = std::mem::replace(&mut self.last_values[idx], value);
+        match self.entries[idx].action {
+            MappedAction::Button(action) => {
+                let pressed = match trigger {
+                    Trigger::Note(_) => true,
+                    Trigger::ControlChange(_) => last_value < 64 && value >= 64,
+                };
+                pressed.then(|| action)
+            }
+            MappedAction::AmpRange(min, max) => Some(MidiAction::AmpRange(
+                min + (max - min) * f32::from(value) / 127.0,
+            )),
+        }
+    }
+}
+//
+impl Default for MidiMapping {
+    fn default() -> Self {
+        DEFAULT_MAPPING
+            .parse()
+            .expect("Default MIDI mapping should be valid")
+    }
+}
+//
+impl FromStr for MidiMapping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (idx, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            if words.is_empty() {
+                continue;
+            }
+            let entry =
+                parse_entry(&words).map_err(|e| anyhow::format_err!("Line {}: {e}", idx + 1))?;
+            entries.push(entry);
+        }
+        Ok(Self {
+            last_values: vec![0; entries.len()].into_boxed_slice(),
+            entries: entries.into_boxed_slice(),
+        })
+    }
+}
+
+/// Parse a non-empty line of a mapping file, split into words
+fn parse_entry(words: &[&str]) -> Result<MappingEntry> {
+    anyhow::ensure!(
+        words.len() >= 4,
+        "Expected a message type, channel, number and action"
+    );
+    let channel = match words[1] {
+        "*" => None,
+        channel => {
+            let channel = channel.parse::<u8>()?;
+            anyhow::ensure!((1..=16).contains(&channel), "MIDI channels go from 1 to 16");
+            Some(channel - 1)
+        }
+    };
+    let number = words[2].parse::<u8>()?;
+    anyhow::ensure!(
+        number < 128,
+        "MIDI note and controller numbers go from 0 to 127"
+    );
+    let trigger = match words[0] {
+        "note" => Trigger::Note(number),
+        "cc" => Trigger::ControlChange(number),
+        other => anyhow::bail!("MIDI message type {other} is not supported"),
+    };
+    let button = |action| {
+        anyhow::ensure!(words.len() == 4, "Action {} takes no argument", words[3]);
+        Ok(MappedAction::Button(action))
+    };
+    let action = match words[3] {
+        "freeze" => button(MidiAction::Command(UserCommand::ToggleFreeze))?,
+        "cycle-window" => button(MidiAction::CycleWindow)?,
+        "cycle-weighting" => button(MidiAction::Command(UserCommand::CycleWeighting))?,
+        "zoom-in" => button(MidiAction::Command(UserCommand::ZoomFrequencies {
+            center: 0.5,
+            factor: ZOOM_STEP,
+        }))?,
+        "zoom-out" => button(MidiAction::Command(UserCommand::ZoomFrequencies {
+            center: 0.5,
+            factor: 1.0 / ZOOM_STEP,
+        }))?,
+        "pan-left" => button(MidiAction::Command(UserCommand::PanFrequencies {
+            delta: -PAN_STEP,
+        }))?,
+        "pan-right" => button(MidiAction::Command(UserCommand::PanFrequencies {
+            delta: PAN_STEP,
+        }))?,
+        "reset-frequencies" => button(MidiAction::Command(UserCommand::ResetFrequencies))?,
+        "amp-range" => {
+            anyhow::ensure!(
+                words.len() == 6,
+                "Action amp-range takes a minimum and maximum range in dB"
+            );
+            anyhow::ensure!(
+                matches!(trigger, Trigger::ControlChange(_)),
+                "Action amp-range can only be mapped to control changes"
+            );
+            let (min, max) = (words[4].parse::<f32>()?, words[5].parse::<f32>()?);
+            anyhow::ensure!(
+                min.is_finite() && min > 0.0 && max.is_finite() && max >= min,
+                "Please specify a sensible amplitude range interval"
+            );
+            MappedAction::AmpRange(min, max)
+        }
+        other => anyhow::bail!("MIDI action {other} is not supported"),
+    };
+    Ok(MappingEntry {
+        channel,
+        trigger,
+        action,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapping() {
+        let mut mapping = "
+            # Transport-like buttons on channel 2
+            note 2 36 freeze
+            cc 2 20 cycle-window  # Button that sends CC 127 when pressed
+            cc * 7 amp-range 40 100
+        "
+        .parse::<MidiMapping>()
+        .unwrap();
+
+        // Note on triggers buttons on the right channel, note off doesn't
+        assert_eq!(
+            mapping.action([0x91, 36, 100]),
+            Some(MidiAction::Command(UserCommand::ToggleFreeze))
+        );
+        assert_eq!(mapping.action([0x90, 36, 100]), None);
+        assert_eq!(mapping.action([0x91, 36, 0]), None);
+        assert_eq!(mapping.action([0x81, 36, 64]), None);
+
+        // CC buttons trigger when their value rises to 64 and above
+        assert_eq!(
+            mapping.action([0xB1, 20, 127]),
+            Some(MidiAction::CycleWindow)
+        );
+        assert_eq!(mapping.action([0xB1, 20, 100]), None);
+        assert_eq!(mapping.action([0xB1, 20, 127]), None);
+        assert_eq!(mapping.action([0xB1, 20, 0]), None);
+        assert_eq!(mapping.action([0xB1, 20, 63]), None);
+        assert_eq!(
+            mapping.action([0xB1, 20, 64]),
+            Some(MidiAction::CycleWindow)
+        );
+
+        // CC faders map their whole range to the amplitude range
+        assert_eq!(
+            mapping.action([0xB5, 7, 0]),
+            Some(MidiAction::AmpRange(40.0))
+        );
+        assert_eq!(
+            mapping.action([0xB0, 7, 127]),
+            Some(MidiAction::AmpRange(100.0))
+        );
+
+        // Invalid mappings are rejected with the offending line
+        let error = "note 1 60 freeze\nnote 17 60 freeze"
+            .parse::<MidiMapping>()
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Line 2:"));
+        assert!("note 1 60 amp-range 20 100".parse::<MidiMapping>().is_err());
+
+        // The default mapping is valid
+        assert_eq!(
+            MidiMapping::default().action([0x9F, 60, 1]),
+            Some(MidiAction::Command(UserCommand::ToggleFreeze))
+        );
+    }
+}
@@ -1,6 +1,10 @@
 //! Fourier transform resampling for desired display width
 
-use crate::math;
+use crate::{math, scale::FrequencyScale, Result};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
 
 // Integrate the linear interpolant of a tabulated function between two
 // fractional bin coordinates.
@@ -59,20 +63,145 @@ fn integrate(f: &[f32], start: f32, end: f32) -> f32 {
     }
 }
 
+// Compute the maximum of the linear interpolant of a tabulated function
+// between two fractional bin coordinates.
+fn max_interpolant(f: &[f32], start: f32, end: f32) -> f32 {
+    // The maximum of a piecewise linear function is reached either at one
+    // end of the range or at one of the tabulation points in between
+    debug_assert!(end >= start);
+    debug_assert!(start >= 0.0);
+    debug_assert!(end <= (f.len() - 1) as f32);
+    let interpolate = |x: f32| {
+        let left = (x.floor() as usize).min(f.len() - 2);
+        let fract = x - left as f32;
+        (1.0 - fract) * f[left] + fract * f[left + 1]
+    };
+    let after_start = start.ceil() as usize;
+    let before_end = end.floor() as usize;
+    let inner_max = if before_end >= after_start {
+        f[after_start..=before_end]
+            .iter()
+            .copied()
+            .fold(f32::NEG_INFINITY, f32::max)
+    } else {
+        f32::NEG_INFINITY
+    };
+    interpolate(start).max(interpolate(end)).max(inner_max)
+}
+
+/// Strategy for mapping Fourier transform bins to display bins
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResamplingMode {
+    /// Average the dB magnitudes across each display bin
+    ///
+    /// This produces smooth-looking output, but the result corresponds to no
+    /// physical quantity, and narrow peaks are underestimated when display
+    /// bins are wider than Fourier transform bins.
+    ///
+    Decibel,
+
+    /// Average the power across each display bin, then convert it to dB
+    MeanPower,
+
+    /// Sum the power within each display bin, then convert it to dB
+    ///
+    /// This is the total energy that falls within each display bin, which
+    /// means that broadband signals will look louder in wider bins.
+    ///
+    SumPower,
+
+    /// Display the highest magnitude within each display bin
+    ///
+    /// This preserves the amplitude of narrow peaks, at the expense of
+    /// overestimating the level of broadband signals.
+    ///
+    Max,
+}
+//
+impl FromStr for ResamplingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "db" => Ok(Self::Decibel),
+            "mean-power" => Ok(Self::MeanPower),
+            "sum-power" => Ok(Self::SumPower),
+            "max" => Ok(Self::Max),
+            _ => Err(anyhow::format_err!("Resampling mode {s} is not supported")),
+        }
+    }
+}
+
+/// Reason why a [`FourierResampler`] cannot be set up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResamplerError {
+    /// The Fourier transform should have at least two bins
+    TransformLen(usize),
+
+    /// The sample rate should not be zero
+    SampleRate,
+
+    /// The number of output bins should be between 1 and 2^31-1
+    OutputBins(usize),
+
+    /// The frequency range is empty, or not between 0Hz and the Nyquist
+    /// frequency
+    FreqRange {
+        /// Requested frequency range in Hz
+        range: (f32, f32),
+
+        /// Nyquist frequency in Hz
+        nyquist: f32,
+    },
+}
+//
+impl Display for ResamplerError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::TransformLen(len) => write!(
+                f,
+                "Cannot resample a {len}-bins Fourier transform, at least 2 bins are needed"
+            ),
+            Self::SampleRate => write!(f, "The sample rate should not be zero"),
+            Self::OutputBins(bins) => write!(
+                f,
+                "Cannot resample into {bins} bins, there should be between 1 and {} bins",
+                i32::MAX - 1
+            ),
+            Self::FreqRange {
+                range: (min, max),
+                nyquist,
+            } => write!(
+                f,
+                "Frequency range {min}-{max} Hz is invalid, it should be non-empty and \
+                 between 0 Hz and {nyquist} Hz"
+            ),
+        }
+    }
+}
+//
+impl std::error::Error for ResamplerError {}
+
 /// Fourier transform resampler
 ///
 /// Converts the native Fourier transform into a format that is suitable for
 /// display. Each display bin is modeled as representing a certain frequency
-/// range, and its value is the average of a linear FFT interpolant across
-/// this frequency range.
+/// range, and its value is computed from a linear FFT interpolant across this
+/// frequency range, in a manner that depends on the resampling mode.
 ///
 pub struct FourierResampler {
+    /// Resampling mode
+    mode: ResamplingMode,
+
     /// Output bin borders
     bin_borders: Box<[f32]>,
 
     /// Output bin averaging weights (= reverse bin width)
     bin_weights: Box<[f32]>,
 
+    /// Fourier transform converted to linear power (for power resampling)
+    powers: Box<[f32]>,
+
     /// Resampled FFT storage
     output_bins: Box<[f32]>,
 }
@@ -85,30 +214,32 @@ impl FourierResampler {
         num_output_bins: usize,
         min_freq: f32,
         max_freq: f32,
-        log_scale: bool,
+        scale: &FrequencyScale,
+        mode: ResamplingMode,
     ) -> Self {
         // Compute the Fourier transform bin width and deduce the fractional bin
         // position corresponding to the minimum and maximum frequency.
-        assert!(transform_len >= 2);
-        assert!(sample_rate > 0);
-        assert!(num_output_bins >= 1 && num_output_bins < i32::MAX as usize);
-        assert!(min_freq >= 0.0);
-        assert!(max_freq > min_freq);
-        assert!(max_freq <= (sample_rate / 2) as f32);
+        Self::check_params(
+            transform_len,
+            sample_rate,
+            num_output_bins,
+            min_freq,
+            max_freq,
+        )
+        .unwrap_or_else(|e| panic!("{e}"));
         let bin_width = (sample_rate / 2) as f32 / (transform_len - 1) as f32;
-        let min_bin = min_freq / bin_width;
-        let max_bin = max_freq / bin_width;
-
-        // Find the list of bin borders corresponding to the resampled transform
-        let bin_borders: Box<[_]> = if log_scale {
-            (0..=num_output_bins as i32)
-                .map(|b| min_bin * (max_bin / min_bin).powf(b as f32 / num_output_bins as f32))
-                .collect()
-        } else {
-            (0..=num_output_bins as i32)
-                .map(|b| min_bin + b as f32 * (max_bin - min_bin) / num_output_bins as f32)
-                .collect()
-        };
+        let min_pos = scale.position(min_freq);
+        let max_pos = scale.position(max_freq);
+
+        // Find the list of bin borders corresponding to the resampled transform,
+        // which are evenly spaced in the units of the frequency scale
+        let max_bin = (transform_len - 1) as f32;
+        let bin_borders: Box<[_]> = (0..=num_output_bins as i32)
+            .map(|b| {
+                let pos = min_pos + b as f32 * (max_pos - min_pos) / num_output_bins as f32;
+                (scale.frequency(pos) / bin_width).clamp(0.0, max_bin)
+            })
+            .collect();
 
         // Compute the averaging weights
         let bin_weights = bin_borders
@@ -117,22 +248,133 @@ impl FourierResampler {
             .collect();
 
         // Return the resulting resamplign harness
+        let powers_len = match mode {
+            ResamplingMode::MeanPower | ResamplingMode::SumPower => transform_len,
+            ResamplingMode::Decibel | ResamplingMode::Max => 0,
+        };
         Self {
+            mode,
             bin_borders,
             bin_weights,
+            powers: vec![0.0; powers_len].into_boxed_slice(),
             output_bins: vec![0.0; num_output_bins].into_boxed_slice(),
         }
     }
 
-    /// Resample a Fourier transform
+    /// Check that a resampler can be set up with certain parameters, which
+    /// have the same meaning as in `new()`
+    ///
+    /// The constructor panics with the error reported by this function, so
+    /// it should be used when the parameters come from the user.
+    ///
+    pub fn check_params(
+        transform_len: usize,
+        sample_rate: usize,
+        num_output_bins: usize,
+        min_freq: f32,
+        max_freq: f32,
+    ) -> std::result::Result<(), ResamplerError> {
+        if transform_len < 2 {
+            return Err(ResamplerError::TransformLen(transform_len));
+        }
+        if sample_rate == 0 {
+            return Err(ResamplerError::SampleRate);
+        }
+        if !(1..i32::MAX as usize).contains(&num_output_bins) {
+            return Err(ResamplerError::OutputBins(num_output_bins));
+        }
+        let nyquist = (sample_rate / 2) as f32;
+        if !(min_freq >= 0.0 && max_freq > min_freq && max_freq <= nyquist) {
+            return Err(ResamplerError::FreqRange {
+                range: (min_freq, max_freq),
+                nyquist,
+            });
+        }
+        Ok(())
+    }
+
+    /// Resample a Fourier transform, given as magnitudes in dB
     pub fn resample(&mut self, fourier: &[f32]) -> &[f32] {
+        // Convert the Fourier transform to linear power if needed
+        let power_domain = !self.powers.is_empty();
+        if power_domain {
+            assert_eq!(fourier.len(), self.powers.len());
+            for (power, &magnitude) in self.powers.iter_mut().zip(fourier) {
+                *power = 10.0f32.powf(magnitude / 10.0);
+            }
+        }
+
+        // Compute the display bins
         for (bin, (borders, &weight)) in self
             .output_bins
             .iter_mut()
             .zip(self.bin_borders.windows(2).zip(&self.bin_weights[..]))
         {
-            *bin = integrate(fourier, borders[0], borders[1]) * weight;
+            let (start, end) = (borders[0], borders[1]);
+            *bin = match self.mode {
+                ResamplingMode::Decibel => integrate(fourier, start, end) * weight,
+                ResamplingMode::MeanPower => {
+                    10.0 * (integrate(&self.powers[..], start, end) * weight).log10()
+                }
+                ResamplingMode::SumPower => {
+                    // When display bins are narrower than Fourier transform
+                    // bins, integrating over them would spread the power of
+                    // one Fourier transform bin over several display bins, so
+                    // count at least one whole bin to avoid that.
+                    let integral = integrate(&self.powers[..], start, end);
+                    10.0 * (integral * weight.max(1.0)).log10()
+                }
+                ResamplingMode::Max => max_interpolant(fourier, start, end),
+            };
         }
         &self.output_bins[..]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use more_asserts::*;
+
+    #[test]
+    fn narrow_peak() {
+        // Set up a Fourier transform with a single -6dB bin peak over a -60dB
+        // floor, resampled into display bins that span 8 transform bins each
+        let sample_rate = 48_000;
+        let transform_len = 1025;
+        let mut fourier = vec![-60.0; transform_len];
+        fourier[500] = -6.0;
+        let setup_resampler = |mode| {
+            FourierResampler::new(
+                transform_len,
+                sample_rate,
+                128,
+                0.0,
+                (sample_rate / 2) as f32,
+                &FrequencyScale::Linear,
+                mode,
+            )
+        };
+        let peak_bin = |output: &[f32]| output.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+        // Averaging dBs loses a lot of the peak's amplitude
+        let mut resampler = setup_resampler(ResamplingMode::Decibel);
+        assert_lt!(peak_bin(resampler.resample(&fourier)), -40.0);
+
+        // Averaging power loses less, but still some
+        let mut resampler = setup_resampler(ResamplingMode::MeanPower);
+        let mean_power_peak = peak_bin(resampler.resample(&fourier));
+        assert_lt!(
+            (mean_power_peak - (-6.0 - 10.0 * 8.0f32.log10())).abs(),
+            0.5
+        );
+
+        // Summing power preserves the peak's energy
+        let mut resampler = setup_resampler(ResamplingMode::SumPower);
+        assert_lt!((peak_bin(resampler.resample(&fourier)) + 6.0).abs(), 0.5);
+
+        // Taking the max preserves the peak's amplitude
+        let mut resampler = setup_resampler(ResamplingMode::Max);
+        assert_eq!(peak_bin(resampler.resample(&fourier)), -6.0);
+    }
+}
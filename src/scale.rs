@@ -0,0 +1,370 @@
+//! Frequency scales used to lay out spectra on the display
+
+use crate::Result;
+use std::str::FromStr;
+
+/// Frequency scale
+///
+/// Maps frequencies in Hz to positions on a display axis, such that display
+/// bins are evenly spaced in the scale's units.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrequencyScale {
+    /// Linear scale, best for harmonic analysis
+    Linear,
+
+    /// Logarithmic scale, which roughly matches musical pitch perception
+    Logarithmic,
+
+    /// Mel scale (O'Shaughnessy's formula), common in speech processing
+    Mel,
+
+    /// Bark critical band rate scale (Traunmüller's formula)
+    Bark,
+
+    /// Equivalent rectangular bandwidth rate scale (Glasberg & Moore)
+    Erb,
+
+    /// Custom scale, defined by a strictly increasing list of frequencies in
+    /// Hz that are evenly spaced on the display, with linear interpolation in
+    /// between and linear extrapolation beyond the ends of the table
+    Table(Box<[f32]>),
+}
+//
+impl FrequencyScale {
+    /// Convert a frequency in Hz to a position in scale units
+    pub fn position(&self, freq: f32) -> f32 {
+        match self {
+            Self::Linear => freq,
+            Self::Logarithmic => freq.ln(),
+            Self::Mel => 2595.0 * (1.0 + freq / 700.0).log10(),
+            Self::Bark => 26.81 * freq / (1960.0 + freq) - 0.53,
+            Self::Erb => 21.4 * (1.0 + 0.00437 * freq).log10(),
+            Self::Table(freqs) => {
+                let idx = Self::segment(freqs, |idx| freqs[idx] > freq);
+                let (freq1, freq2) = (freqs[idx], freqs[idx + 1]);
+                idx as f32 + (freq - freq1) / (freq2 - freq1)
+            }
+        }
+    }
+
+    /// Convert a position in scale units to a frequency in Hz
+    pub fn frequency(&self, position: f32) -> f32 {
+        match self {
+            Self::Linear => position,
+            Self::Logarithmic => position.exp(),
+            Self::Mel => 700.0 * (10.0f32.powf(position / 2595.0) - 1.0),
+            Self::Bark => 1960.0 * (position + 0.53) / (26.28 - position),
+            Self::Erb => (10.0f32.powf(position / 21.4) - 1.0) / 0.00437,
+            Self::Table(freqs) => {
+                let idx = Self::segment(freqs, |idx| idx as f32 > position);
+                let (freq1, freq2) = (freqs[idx], freqs[idx + 1]);
+                freq1 + (position - idx as f32) * (freq2 - freq1)
+            }
+        }
+    }
+
+    /// Find the segment of a lookup table which should be used to interpolate
+    /// or extrapolate a value, given a predicate that tells if a table entry
+    /// is past that value
+    fn segment(freqs: &[f32], past_value: impl Fn(usize) -> bool) -> usize {
+        (1..freqs.len() - 1)
+            .find(|&idx| past_value(idx))
+            .unwrap_or(freqs.len() - 1)
+            - 1
+    }
+}
+//
+impl FromStr for FrequencyScale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "log" => Ok(Self::Logarithmic),
+            "mel" => Ok(Self::Mel),
+            "bark" => Ok(Self::Bark),
+            "erb" => Ok(Self::Erb),
+            _ => {
+                let table = s
+                    .strip_prefix("table:")
+                    .ok_or_else(|| anyhow::format_err!("Frequency scale {s} is not supported"))?;
+                let freqs = table
+                    .split(',')
+                    .map(|freq| freq.trim().parse::<f32>())
+                    .collect::<std::result::Result<Box<[_]>, _>>()?;
+                if freqs.len() < 2
+                    || freqs.iter().any(|freq| !freq.is_finite())
+                    || freqs.windows(2).any(|pair| pair[1] <= pair[0])
+                {
+                    anyhow::bail!(
+                        "Frequency scale tables must contain at least two strictly \
+                         increasing frequencies"
+                    );
+                }
+                Ok(Self::Table(freqs))
+            }
+        }
+    }
+}
+
+/// Smallest visible frequency range, as a fraction of the zoomable range
+const MIN_ZOOM_FRACTION: f32 = 1e-3;
+
+/// Visible frequency range, which can be zoomed and panned at runtime
+///
+/// Ranges are manipulated in the units of a frequency scale, so that zooming
+/// around a point of the display keeps that point in place, whatever scale is
+/// used. Coordinates along the display axis are relative, 0.0 being the
+/// minimum visible frequency and 1.0 being the maximum visible frequency.
+///
+#[derive(Clone, Debug)]
+pub struct FrequencyRange {
+    /// Frequency scale of the display
+    scale: FrequencyScale,
+
+    /// Range of positions that the visible range can't go beyond
+    limits: (f32, f32),
+
+    /// Same range in Hz, which converting positions back to frequencies must
+    /// not exceed despite rounding errors (e.g. at the Nyquist frequency)
+    freq_limits: (f32, f32),
+
+    /// Range of positions that was initially visible
+    initial: (f32, f32),
+
+    /// Range of positions that is currently visible
+    current: (f32, f32),
+
+    /// Range of positions that the display was last told about
+    displayed: (f32, f32),
+}
+//
+impl FrequencyRange {
+    /// Set up a frequency range, given the frequency scale of the display,
+    /// the initially visible range and the range that zooming out can reveal,
+    /// all in Hz
+    pub fn new(
+        scale: FrequencyScale,
+        (min_freq, max_freq): (f32, f32),
+        (min_limit, max_limit): (f32, f32),
+    ) -> Self {
+        assert!(min_limit <= min_freq && min_freq < max_freq && max_freq <= max_limit);
+        let initial = (scale.position(min_freq), scale.position(max_freq));
+        let limits = (scale.position(min_limit), scale.position(max_limit));
+        assert!(limits.0.is_finite() && limits.1.is_finite());
+        Self {
+            scale,
+            limits,
+            freq_limits: (min_limit, max_limit),
+            initial,
+            current: initial,
+            displayed: initial,
+        }
+    }
+
+    /// Frequency scale of the display
+    pub fn scale(&self) -> &FrequencyScale {
+        &self.scale
+    }
+
+    /// Minimum visible frequency in Hz
+    pub fn min_freq(&self) -> f32 {
+        self.frequency(self.current.0)
+    }
+
+    /// Maximum visible frequency in Hz
+    pub fn max_freq(&self) -> f32 {
+        self.frequency(self.current.1)
+    }
+
+    /// Zoom by a certain factor (> 1 to zoom in, < 1 to zoom out), keeping
+    /// a certain relative position of the display axis in place
+    pub fn zoom(&mut self, center: f32, factor: f32) {
+        assert!(factor > 0.0);
+        let (start, end) = self.current;
+        let pivot = start + center.clamp(0.0, 1.0) * (end - start);
+        let min_width = MIN_ZOOM_FRACTION * (self.limits.1 - self.limits.0);
+        let max_width = self.limits.1 - self.limits.0;
+        let width = ((end - start) / factor).clamp(min_width, max_width);
+        let start = pivot - center.clamp(0.0, 1.0) * width;
+        self.set_current(start, start + width);
+    }
+
+    /// Shift the visible range by a certain fraction of its width (> 0 to
+    /// move towards higher frequencies, < 0 to move towards lower ones)
+    pub fn pan(&mut self, delta: f32) {
+        let (start, end) = self.current;
+        let shift = delta * (end - start);
+        self.set_current(start + shift, end + shift);
+    }
+
+    /// Make a certain frequency range visible, given in Hz
+    ///
+    /// The range is shrunk or moved as needed to fit within the range that
+    /// zooming out can reveal.
+    ///
+    pub fn set_visible(&mut self, (min_freq, max_freq): (f32, f32)) {
+        assert!(min_freq < max_freq);
+        let start = self.scale.position(min_freq).max(self.limits.0);
+        let end = self.scale.position(max_freq).min(self.limits.1);
+        let min_width = MIN_ZOOM_FRACTION * (self.limits.1 - self.limits.0);
+        self.set_current(start, start + (end - start).max(min_width));
+    }
+
+    /// Center frequency in Hz of each bin of a spectrum that covers the
+    /// visible range with a certain number of bins
+    pub fn bin_frequencies(&self, num_bins: usize) -> Box<[f32]> {
+        let (start, end) = self.current;
+        (0..num_bins)
+            .map(|bin| {
+                let rel_pos = (bin as f32 + 0.5) / num_bins as f32;
+                self.frequency(start + rel_pos * (end - start))
+            })
+            .collect()
+    }
+
+    /// Go back to the initially visible range
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+
+    /// Truth that the visible range has changed since the last call to
+    /// `commit()`, in which case the new range is returned in relative
+    /// coordinates of the old one
+    ///
+    /// This is the information that a display needs in order to rescale
+    /// what it has previously drawn so that it matches the new range.
+    ///
+    pub fn commit(&mut self) -> Option<(f32, f32)> {
+        if self.current == self.displayed {
+            return None;
+        }
+        let (old_start, old_end) = self.displayed;
+        let relative = |pos: f32| (pos - old_start) / (old_end - old_start);
+        let result = (relative(self.current.0), relative(self.current.1));
+        self.displayed = self.current;
+        Some(result)
+    }
+
+    /// Convert a position to a frequency in Hz, within the limits
+    fn frequency(&self, position: f32) -> f32 {
+        let (min_limit, max_limit) = self.freq_limits;
+        self.scale.frequency(position).clamp(min_limit, max_limit)
+    }
+
+    /// Move the visible range, keeping its width but not its position when
+    /// it hits the limits of the zoomable range
+    fn set_current(&mut self, start: f32, end: f32) {
+        let width = end - start;
+        let start = start.clamp(self.limits.0, self.limits.1 - width);
+        self.current = (start, start + width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use more_asserts::*;
+
+    #[test]
+    fn scales() {
+        // Check some reference values
+        let check = |scale: &str, freq: f32, position: f32| {
+            let scale = FrequencyScale::from_str(scale).unwrap();
+            assert_lt!((scale.position(freq) - position).abs(), 0.02);
+        };
+        check("linear", 1000.0, 1000.0);
+        check("log", 1000.0, 1000.0f32.ln());
+        check("mel", 1000.0, 1000.0);
+        check("bark", 1000.0, 8.53);
+        check("erb", 1000.0, 15.62);
+        check("table:100,1000,10000", 550.0, 0.5);
+        check("table:100,1000,10000", 10000.0, 2.0);
+        check("table:100,1000,10000", 19000.0, 3.0);
+
+        // Check that conversions round-trip
+        for scale in ["linear", "log", "mel", "bark", "erb", "table:20,200,20000"] {
+            let scale = FrequencyScale::from_str(scale).unwrap();
+            for freq in [20.0, 440.0, 1000.0, 8000.0, 20000.0] {
+                let round_trip = scale.frequency(scale.position(freq));
+                assert_lt!((round_trip / freq - 1.0).abs(), 1e-3);
+            }
+        }
+
+        // Check that invalid tables are rejected
+        assert!(FrequencyScale::from_str("table:1000").is_err());
+        assert!(FrequencyScale::from_str("table:1000,100").is_err());
+        assert!(FrequencyScale::from_str("table:abc,100").is_err());
+        assert!(FrequencyScale::from_str("octave").is_err());
+    }
+
+    #[test]
+    fn zoom_and_pan() {
+        let mut range = FrequencyRange::new(
+            FrequencyScale::Logarithmic,
+            (20.0, 20_000.0),
+            (20.0, 24_000.0),
+        );
+        assert_eq!(range.commit(), None);
+
+        // Zooming in around the middle of the display keeps it in place
+        let middle = (20.0f32 * 20_000.0).sqrt();
+        range.zoom(0.5, 2.0);
+        assert_lt!(
+            (range.min_freq() * range.max_freq() / middle.powi(2) - 1.0).abs(),
+            1e-3
+        );
+        assert_lt!(
+            (range.max_freq() / range.min_freq() / 1000.0f32.sqrt() - 1.0).abs(),
+            1e-3
+        );
+        let (start, end) = range.commit().unwrap();
+        assert_lt!((start - 0.25).abs(), 1e-5);
+        assert_lt!((end - 0.75).abs(), 1e-5);
+        assert_eq!(range.commit(), None);
+
+        // Panning stops at the limits of the zoomable range
+        range.pan(-10.0);
+        assert_lt!((range.min_freq() / 20.0 - 1.0).abs(), 1e-3);
+        range.pan(10.0);
+        assert_lt!((range.max_freq() / 24_000.0 - 1.0).abs(), 1e-3);
+
+        // Zooming out can't go beyond these limits either
+        range.zoom(0.0, 1e-3);
+        assert_lt!((range.min_freq() / 20.0 - 1.0).abs(), 1e-3);
+        assert_lt!((range.max_freq() / 24_000.0 - 1.0).abs(), 1e-3);
+
+        // Resetting brings back the initial range
+        range.reset();
+        assert_lt!((range.min_freq() / 20.0 - 1.0).abs(), 1e-3);
+        assert_lt!((range.max_freq() / 20_000.0 - 1.0).abs(), 1e-3);
+
+        // Explicitly set ranges are clipped to the limits
+        range.set_visible((100.0, 1_000.0));
+        assert_lt!((range.min_freq() / 100.0 - 1.0).abs(), 1e-3);
+        assert_lt!((range.max_freq() / 1_000.0 - 1.0).abs(), 1e-3);
+        let freqs = range.bin_frequencies(2);
+        assert_lt!((freqs[0] / 10.0f32.powf(2.25) - 1.0).abs(), 1e-3);
+        range.set_visible((1.0, 30_000.0));
+        assert_lt!((range.min_freq() / 20.0 - 1.0).abs(), 1e-3);
+        assert_lt!((range.max_freq() / 24_000.0 - 1.0).abs(), 1e-3);
+
+        // Rounding errors never take the range beyond the Nyquist frequency
+        let nyquist = 22_050.0;
+        for scale in [
+            FrequencyScale::Logarithmic,
+            FrequencyScale::Mel,
+            FrequencyScale::Erb,
+        ] {
+            let mut range = FrequencyRange::new(scale, (20.0, nyquist), (20.0, nyquist));
+            assert_le!(range.max_freq(), nyquist);
+            range.zoom(0.5, 2.0);
+            range.pan(10.0);
+            assert_le!(range.max_freq(), nyquist);
+            range.zoom(0.0, 1e-3);
+            assert_le!(range.max_freq(), nyquist);
+            assert_ge!(range.min_freq(), 20.0);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Fractional-octave smoothing of spectra
+
+/// Fractional-octave smoothing
+///
+/// Replaces each bin of a spectrum with linearly spaced bins by the mean power
+/// of a window of constant relative width (1/N octave) centered around it, as
+/// room acoustics measurement tools do. Since windows get wider as frequency
+/// increases, this removes the jaggedness of high frequency spectra without
+/// blurring low frequency details.
+///
+/// Window averages are computed in O(1) using prefix sums of the power
+/// spectrum, which are kept in double precision so that quiet bins next to
+/// loud ones remain accurate down to about -150dB below the total power.
+///
+pub struct OctaveSmoothing {
+    /// For each bin, fractional bin coordinates of the averaging window
+    windows: Box<[(f32, f32)]>,
+
+    /// Prefix sums of the power spectrum (entry i is the sum of bins 0..i)
+    prefix_sums: Box<[f64]>,
+
+    /// Smoothed spectrum
+    output: Box<[f32]>,
+}
+//
+impl OctaveSmoothing {
+    /// Prepare to smooth spectra of a certain length with 1/N-octave windows
+    pub fn new(bands_per_octave: usize, spectrum_len: usize) -> Self {
+        assert!(bands_per_octave > 0);
+        assert!(spectrum_len >= 2);
+
+        // In the coordinate system of prefix sums, bin i covers [i, i+1[ and
+        // is centered on its frequency. Its averaging window covers
+        // frequencies i * 2^(+/-1/2N), but no less than the bin itself and no
+        // more than the spectrum.
+        let half_ratio = 2.0f32.powf(0.5 / bands_per_octave as f32);
+        let max_coord = spectrum_len as f32;
+        let windows = (0..spectrum_len)
+            .map(|bin| {
+                let bin = bin as f32;
+                let start = (bin / half_ratio + 0.5).min(bin);
+                let end = (bin * half_ratio + 0.5).max(bin + 1.0).min(max_coord);
+                (start, end)
+            })
+            .collect();
+        Self {
+            windows,
+            prefix_sums: vec![0.0; spectrum_len + 1].into_boxed_slice(),
+            output: vec![0.0; spectrum_len].into_boxed_slice(),
+        }
+    }
+
+    /// Smooth a spectrum, given as magnitudes in dB
+    pub fn smooth(&mut self, spectrum: &[f32]) -> &[f32] {
+        // Compute prefix sums of the power spectrum
+        assert_eq!(spectrum.len(), self.output.len());
+        let mut sum = 0.0;
+        for (prefix_sum, &magnitude) in self.prefix_sums[1..].iter_mut().zip(spectrum) {
+            sum += 10.0f64.powf(magnitude as f64 / 10.0);
+            *prefix_sum = sum;
+        }
+
+        // Use them to compute the mean power within each window, treating the
+        // power as constant across each bin
+        let prefix_sums = &self.prefix_sums[..];
+        let cumulative_power = |coord: f32| {
+            let bin = (coord as usize).min(prefix_sums.len() - 2);
+            let fract = (coord - bin as f32) as f64;
+            prefix_sums[bin] + fract * (prefix_sums[bin + 1] - prefix_sums[bin])
+        };
+        for (dest, &(start, end)) in self.output.iter_mut().zip(self.windows.iter()) {
+            let power = (cumulative_power(end) - cumulative_power(start)) / (end - start) as f64;
+            *dest = (10.0 * power.log10()) as f32;
+        }
+        &self.output[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use more_asserts::*;
+
+    #[test]
+    fn smoothing() {
+        // Smooth a spectrum with alternating -10dB and -20dB bins
+        let len = 10_000;
+        let spectrum = (0..len)
+            .map(|bin| if bin % 2 == 0 { -10.0 } else { -20.0 })
+            .collect::<Vec<_>>();
+        let mut smoothing = OctaveSmoothing::new(3, len);
+        let smoothed = smoothing.smooth(&spectrum);
+
+        // Low bins are left alone, as windows would be narrower than a bin
+        assert_eq!(smoothed[1], -20.0);
+        assert_eq!(smoothed[2], -10.0);
+
+        // High bins converge towards the mean power of -10dB and -20dB
+        let mean_power = 10.0 * (0.5f32 * (0.1 + 0.01)).log10();
+        for &magnitude in &smoothed[100..] {
+            assert_lt!((magnitude - mean_power).abs(), 0.1);
+        }
+    }
+}
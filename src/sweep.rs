@@ -0,0 +1,365 @@
+//! Impulse response measurement using exponential sine sweeps
+
+use realfft::{num_complex::Complex, RealFftPlanner};
+
+/// Exponential sine sweep, and associated inverse filter
+///
+/// This implements the measurement method of A. Farina ("Simultaneous
+/// measurement of impulse response and distortion with a swept-sine
+/// technique", AES 108th convention, 2000). An exponential sweep is played
+/// through the system under test, and the recorded response is convolved with
+/// an inverse filter that turns the sweep into a Dirac impulse. This yields
+/// the impulse response of the system, where the responses associated with
+/// each harmonic distortion order are neatly separated in time, ahead of the
+/// linear impulse response.
+///
+pub struct ExponentialSweep {
+    /// Sweep signal
+    signal: Box<[f32]>,
+
+    /// Inverse filter (amplitude-compensated time reversal of the sweep)
+    inverse_filter: Box<[f32]>,
+
+    /// Sweep rate L in samples: the instantaneous frequency of the sweep at
+    /// sample n is start_freq * exp(n / L)
+    rate: f32,
+}
+//
+impl ExponentialSweep {
+    /// Prepare an exponential sweep from `start_freq` to `end_freq` (in Hz),
+    /// with a certain duration (in s) and amplitude (relative to full scale)
+    pub fn new(
+        start_freq: f32,
+        end_freq: f32,
+        duration: f32,
+        amplitude: f32,
+        sample_rate: usize,
+    ) -> Self {
+        // Validate the sweep parameters
+        assert!(start_freq > 0.0 && start_freq.is_finite());
+        assert!(end_freq > start_freq && end_freq <= (sample_rate / 2) as f32);
+        assert!(duration > 0.0 && duration.is_finite());
+        assert!(amplitude > 0.0 && amplitude <= 1.0);
+
+        // Determine the sweep length and rate
+        let len = (duration * sample_rate as f32) as usize;
+        assert!(len >= 2, "Sweep is too short");
+        let rate = len as f64 / (end_freq as f64 / start_freq as f64).ln();
+
+        // Generate the sweep, with short fades at both ends to avoid clicks.
+        // Phase is computed in double precision as it gets very large.
+        let start_omega = std::f64::consts::TAU * start_freq as f64 / sample_rate as f64;
+        let fade_in_len = ((sample_rate as f32 / start_freq) as usize).min(len / 2);
+        let fade_out_len = (len / 100).max(1);
+        let signal = (0..len)
+            .map(|n| {
+                let phase = start_omega * rate * ((n as f64 / rate).exp() - 1.0);
+                let fade = if n < fade_in_len {
+                    half_hann(n, fade_in_len)
+                } else if n >= len - fade_out_len {
+                    half_hann(len - 1 - n, fade_out_len)
+                } else {
+                    1.0
+                };
+                amplitude * fade * phase.sin() as f32
+            })
+            .collect::<Box<[_]>>();
+
+        // Build the inverse filter by time-reversing the sweep and applying an
+        // exponential decay, which compensates the 1/f energy distribution of
+        // the exponential sweep.
+        let mut inverse_filter = signal
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(n, &x)| x * (-(n as f64) / rate).exp() as f32)
+            .collect::<Box<[_]>>();
+
+        // Normalize the inverse filter so that it has unit gain at the center
+        // of the sweep's frequency range once convolved with the sweep
+        let fft_len = (2 * len).next_power_of_two();
+        let sweep_spectrum = spectrum(&signal, fft_len);
+        let inverse_spectrum = spectrum(&inverse_filter, fft_len);
+        let center_freq = (start_freq * end_freq).sqrt();
+        let center_bin = (center_freq * fft_len as f32 / sample_rate as f32).round() as usize;
+        let gain = (sweep_spectrum[center_bin] * inverse_spectrum[center_bin]).norm();
+        for x in inverse_filter.iter_mut() {
+            *x /= gain;
+        }
+
+        // Return the sweep to the caller
+        Self {
+            signal,
+            inverse_filter,
+            rate: rate as f32,
+        }
+    }
+
+    /// Access the sweep signal
+    pub fn signal(&self) -> &[f32] {
+        &self.signal[..]
+    }
+
+    /// Turn a recording of the system's response to the sweep into an impulse
+    /// response, assuming that the recording started with the sweep
+    pub fn deconvolve(&self, recording: &[f32]) -> ImpulseResponse {
+        // Prepare for FFT-based linear convolution
+        let conv_len = recording.len() + self.inverse_filter.len() - 1;
+        let fft_len = conv_len.next_power_of_two();
+        let mut planner = RealFftPlanner::<f32>::new();
+
+        // Multiply the spectra of the recording and inverse filter
+        let mut product = spectrum(recording, fft_len);
+        let inverse_spectrum = spectrum(&self.inverse_filter, fft_len);
+        let norm = 1.0 / fft_len as f32;
+        for (dest, &src) in product.iter_mut().zip(inverse_spectrum.iter()) {
+            *dest *= src * norm;
+        }
+
+        // Go back to the time domain
+        // NOTE: Inverse real FFT wants purely real DC and Nyquist coefficients,
+        //       which they are up to rounding errors.
+        product[0].im = 0.0;
+        product.last_mut().expect("Spectrum can't be empty").im = 0.0;
+        let ifft = planner.plan_fft_inverse(fft_len);
+        let mut data = ifft.make_output_vec();
+        ifft.process(&mut product, &mut data)
+            .expect("Failed to compute inverse FFT");
+        data.truncate(conv_len);
+
+        // Locate the linear impulse response, which cannot start before the
+        // end of the inverse filter since the system under test is causal
+        let linear_peak = data
+            .iter()
+            .enumerate()
+            .skip(self.inverse_filter.len() - 1)
+            .fold((0, 0.0f32), |(max_idx, max), (idx, &x)| {
+                if x.abs() > max {
+                    (idx, x.abs())
+                } else {
+                    (max_idx, max)
+                }
+            })
+            .0;
+        ImpulseResponse {
+            data: data.into_boxed_slice(),
+            linear_peak,
+            rate: self.rate,
+        }
+    }
+}
+
+/// Deconvolved response of a system to an exponential sweep
+pub struct ImpulseResponse {
+    /// Deconvolution output
+    data: Box<[f32]>,
+
+    /// Position of the peak of the linear impulse response
+    linear_peak: usize,
+
+    /// Sweep rate in samples (see ExponentialSweep)
+    rate: f32,
+}
+//
+impl ImpulseResponse {
+    /// Extract the impulse response associated with a certain harmonic order
+    /// (1 being the linear response), windowed to at most `max_len` samples
+    ///
+    /// The impulse response of harmonic order N is located L·ln(N) samples
+    /// ahead of the linear impulse response, and is windowed so that it does
+    /// not overlap with the response of order N-1.
+    ///
+    pub fn harmonic(&self, order: usize, max_len: usize) -> Box<[f32]> {
+        // Determine the window around the harmonic impulse response, keeping
+        // a bit of room for pre-ringing before the peak
+        assert!(order >= 1);
+        assert!(max_len >= 2);
+        let offset = |order: usize| (self.rate * (order as f32).ln()).round() as usize;
+        let pre_len = max_len / 16;
+        let post_len = if order == 1 {
+            max_len - pre_len
+        } else {
+            (offset(order) - offset(order - 1))
+                .saturating_sub(pre_len)
+                .clamp(1, max_len - pre_len)
+        };
+
+        // Extract the windowed impulse response
+        let center = self.linear_peak as isize - offset(order) as isize;
+        let fade_out_len = (post_len / 4).max(1);
+        (0..pre_len + post_len)
+            .map(|idx| {
+                let data_idx = center - pre_len as isize + idx as isize;
+                let x = if data_idx >= 0 && (data_idx as usize) < self.data.len() {
+                    self.data[data_idx as usize]
+                } else {
+                    0.0
+                };
+                let window = if idx < pre_len {
+                    half_hann(idx, pre_len)
+                } else if idx >= pre_len + post_len - fade_out_len {
+                    half_hann(pre_len + post_len - 1 - idx, fade_out_len)
+                } else {
+                    1.0
+                };
+                x * window
+            })
+            .collect()
+    }
+
+    /// Compute the frequency responses of the first `num_orders` harmonic
+    /// orders, using impulse responses of at most `max_len` samples
+    pub fn harmonic_responses(&self, num_orders: usize, max_len: usize) -> HarmonicResponses {
+        // Compute the magnitude response of each harmonic order, as a
+        // function of the frequency of its own output
+        assert!(num_orders >= 1);
+        let fft_len = max_len.next_power_of_two();
+        let raw_responses = (1..=num_orders)
+            .map(|order| {
+                spectrum(&self.harmonic(order, max_len), fft_len)
+                    .iter()
+                    .map(|coeff| 10.0 * coeff.norm_sqr().log10())
+                    .collect::<Box<[_]>>()
+            })
+            .collect::<Box<[_]>>();
+
+        // Express the harmonic responses as a function of the excitation
+        // frequency, relative to the linear response
+        let linear = &raw_responses[0];
+        let orders = std::iter::once(linear.clone())
+            .chain(raw_responses.iter().enumerate().skip(1).map(|(idx, raw)| {
+                let order = idx + 1;
+                linear
+                    .iter()
+                    .enumerate()
+                    .map(|(bin, &linear)| {
+                        raw.get(bin * order)
+                            .map(|&harmonic| harmonic - linear)
+                            .unwrap_or(f32::NEG_INFINITY)
+                    })
+                    .collect()
+            }))
+            .collect();
+        HarmonicResponses { orders }
+    }
+}
+
+/// Frequency responses of each harmonic order of a system
+pub struct HarmonicResponses {
+    /// Magnitude responses in dB, on a linear frequency grid going from 0 to
+    /// the Nyquist frequency. The linear response is absolute, whereas the
+    /// response of harmonic order N at frequency f is the level of the N-th
+    /// harmonic produced by an input at frequency f, relative to the linear
+    /// response at frequency f.
+    orders: Box<[Box<[f32]>]>,
+}
+//
+impl HarmonicResponses {
+    /// Response of a certain harmonic order (1 being the linear response)
+    pub fn order(&self, order: usize) -> &[f32] {
+        &self.orders[order - 1][..]
+    }
+
+    /// Average level of a certain harmonic distortion order relative to the
+    /// linear response in dB, for excitations between `start_freq` and
+    /// `end_freq` (in Hz) whose harmonic is below the Nyquist frequency
+    ///
+    /// Returns None if no such excitation was measured, e.g. because the
+    /// harmonic is above the Nyquist frequency over the whole range.
+    ///
+    pub fn average_distortion(
+        &self,
+        order: usize,
+        start_freq: f32,
+        end_freq: f32,
+        sample_rate: usize,
+    ) -> Option<f32> {
+        assert!(order >= 2);
+        let response = self.order(order);
+        let bin_width = (sample_rate / 2) as f32 / (response.len() - 1) as f32;
+        let start_bin = (start_freq / bin_width).ceil() as usize;
+        let end_bin = ((end_freq / bin_width).floor() as usize).min(response.len() - 1);
+        let (sum, count) = response
+            .get(start_bin..=end_bin)?
+            .iter()
+            .filter(|level| level.is_finite())
+            .fold((0.0, 0), |(sum, count), &level| {
+                (sum + 10.0f32.powf(level / 10.0), count + 1)
+            });
+        (count > 0).then(|| 10.0 * (sum / count as f32).log10())
+    }
+}
+
+/// Half of a Hann window, going from 0 at idx = 0 to 1 at idx = len
+fn half_hann(idx: usize, len: usize) -> f32 {
+    (std::f32::consts::FRAC_PI_2 * idx as f32 / len as f32)
+        .sin()
+        .powi(2)
+}
+
+/// Compute the spectrum of a zero-padded signal with a certain FFT length
+fn spectrum(signal: &[f32], fft_len: usize) -> Box<[Complex<f32>]> {
+    let mut planner = RealFftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(fft_len);
+    let mut input = fft.make_input_vec();
+    input[..signal.len()].copy_from_slice(signal);
+    let mut output = fft.make_output_vec();
+    fft.process(&mut input, &mut output)
+        .expect("Failed to compute FFT");
+    output.into_boxed_slice()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use more_asserts::*;
+
+    #[test]
+    fn distorting_system() {
+        // Measure a system with some latency and a bit of 2nd order distortion
+        let sample_rate = 48_000;
+        let sweep = ExponentialSweep::new(20.0, 20_000.0, 2.0, 0.5, sample_rate);
+        let latency = 100;
+        let recording = std::iter::repeat(0.0)
+            .take(latency)
+            .chain(sweep.signal().iter().map(|&x| x + 0.1 * x * x))
+            .chain(std::iter::repeat(0.0).take(sample_rate / 2))
+            .collect::<Box<[_]>>();
+        let impulse_response = sweep.deconvolve(&recording);
+        assert_eq!(
+            impulse_response.linear_peak,
+            sweep.signal().len() - 1 + latency
+        );
+
+        // Linear response should be flat in the middle of the sweep range
+        let responses = impulse_response.harmonic_responses(3, 4096);
+        let bin_width = sample_rate as f32 / 4096.0;
+        for (bin, &level) in responses.order(1).iter().enumerate() {
+            let freq = bin as f32 * bin_width;
+            if (200.0..=10_000.0).contains(&freq) {
+                assert_lt!(level.abs(), 0.5, "Linear response at {freq} Hz");
+            }
+        }
+
+        // An input of amplitude A produces a 2nd harmonic of amplitude
+        // 0.1·A²/2, i.e. -32dB at A=0.5, and no 3rd harmonic
+        let h2 = responses
+            .average_distortion(2, 200.0, 5_000.0, sample_rate)
+            .unwrap();
+        let h3 = responses
+            .average_distortion(3, 200.0, 5_000.0, sample_rate)
+            .unwrap();
+        assert_lt!((h2 + 32.0).abs(), 1.0);
+        assert_lt!(h3, h2 - 20.0);
+
+        // Harmonics above the Nyquist frequency cannot be measured
+        assert_eq!(
+            responses.average_distortion(3, 10_000.0, 20_000.0, sample_rate),
+            None
+        );
+        assert_eq!(
+            responses.average_distortion(2, 5_000.0, 200.0, sample_rate),
+            None
+        );
+    }
+}
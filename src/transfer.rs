@@ -0,0 +1,215 @@
+//! Dual-channel transfer function measurement
+
+use crate::{fourier::SteadyQTransform, math::Float};
+use realfft::num_complex::Complex;
+
+/// Dual-channel transfer function analyzer
+///
+/// Compares a reference signal (typically the signal sent to a loudspeaker)
+/// with a measurement signal (typically what a microphone picked up), and
+/// estimates the transfer function of the system in between. Since any
+/// excitation signal with enough spectral content will do, this can be used
+/// with live music instead of the usual test signals.
+///
+/// The estimate is based on averaged auto- and cross-spectra: if X and Y are
+/// the reference and measurement spectra, Gxx = <|X|²>, Gyy = <|Y|²> and
+/// Gxy = <X*·Y>, then the H1 transfer function estimate is Gxy / Gxx and the
+/// magnitude-squared coherence is |Gxy|² / (Gxx·Gyy). The coherence tells how
+/// much of the measurement can be linearly explained by the reference, and
+/// thus how much the transfer function estimate can be trusted at a given
+/// frequency.
+///
+/// Spectra are averaged in the floating-point precision of the underlying
+/// constant-Q transforms.
+///
+pub struct TransferFunction<T: Float = f32> {
+    /// Transform of the reference signal
+    reference: SteadyQTransform<T>,
+
+    /// Transform of the measurement signal
+    measurement: SteadyQTransform<T>,
+
+    /// Reference history, which is longer than the reference transform's
+    /// input so that the reference can be delayed to match the measurement
+    reference_history: Box<[f32]>,
+
+    /// Averaged reference power spectrum Gxx
+    reference_power: Box<[T]>,
+
+    /// Averaged measurement power spectrum Gyy
+    measurement_power: Box<[T]>,
+
+    /// Averaged cross-spectrum Gxy
+    cross_spectrum: Box<[Complex<T>]>,
+
+    /// Number of spectra that are averaged together in steady state
+    num_averages: usize,
+
+    /// Number of spectra that have been averaged so far (saturates at
+    /// num_averages, after which an exponential moving average is used)
+    num_averaged: usize,
+
+    /// Transfer function magnitude in dB
+    magnitude: Box<[f32]>,
+
+    /// Transfer function phase in degrees
+    phase: Box<[f32]>,
+
+    /// Magnitude-squared coherence (between 0 and 1)
+    coherence: Box<[f32]>,
+}
+//
+impl<T: Float> TransferFunction<T> {
+    /// Get ready to measure transfer functions, given a pair of identically
+    /// configured constant-Q transforms, a reference delay in samples that
+    /// compensates for the propagation time of the system under test, and
+    /// the number of spectra to be averaged together.
+    pub fn new(
+        reference: SteadyQTransform<T>,
+        mut measurement: SteadyQTransform<T>,
+        reference_delay: usize,
+        num_averages: usize,
+    ) -> Self {
+        assert_eq!(reference.output_len(), measurement.output_len());
+        assert!(num_averages > 0);
+        let input_len = measurement.input().len();
+        let output_len = measurement.output_len();
+        Self {
+            reference,
+            measurement,
+            reference_history: vec![0.0; input_len + reference_delay].into_boxed_slice(),
+            reference_power: vec![T::zero(); output_len].into_boxed_slice(),
+            measurement_power: vec![T::zero(); output_len].into_boxed_slice(),
+            cross_spectrum: vec![Complex::new(T::zero(), T::zero()); output_len].into_boxed_slice(),
+            num_averages,
+            num_averaged: 0,
+            magnitude: vec![0.0; output_len].into_boxed_slice(),
+            phase: vec![0.0; output_len].into_boxed_slice(),
+            coherence: vec![0.0; output_len].into_boxed_slice(),
+        }
+    }
+
+    /// Access the reference input buffer
+    ///
+    /// This buffer is longer than the measurement input buffer by the
+    /// reference delay, and its most recent samples are ignored.
+    ///
+    pub fn reference_input(&mut self) -> &mut [f32] {
+        &mut self.reference_history[..]
+    }
+
+    /// Access the measurement input buffer
+    pub fn measurement_input(&mut self) -> &mut [f32] {
+        self.measurement.input()
+    }
+
+    /// Query the output length
+    pub fn output_len(&self) -> usize {
+        self.measurement.output_len()
+    }
+
+    /// Forget about previously averaged spectra
+    pub fn reset(&mut self) {
+        self.num_averaged = 0;
+    }
+
+    /// Integrate the current inputs into the averaged spectra, and update the
+    /// transfer function and coherence estimates accordingly
+    pub fn compute(&mut self) {
+        // Feed the delayed reference to the reference transform
+        let reference_input = self.reference.input();
+        let reference_len = reference_input.len();
+        reference_input.copy_from_slice(&self.reference_history[..reference_len]);
+
+        // Compute the spectra of both signals
+        let reference = self.reference.compute_complex();
+        let measurement = self.measurement.compute_complex();
+
+        // Use a plain average until enough spectra have been accumulated, then
+        // switch to an exponential moving average
+        if self.num_averaged < self.num_averages {
+            self.num_averaged += 1;
+        }
+        let new_weight = T::one()
+            / T::from_usize(self.num_averaged).expect("Average count should be representable");
+        let old_weight = T::one() - new_weight;
+
+        // Update the averaged spectra and deduce the transfer function
+        for (((((x, y), gxx), gyy), gxy), ((mag, phase), coherence)) in reference
+            .iter()
+            .zip(measurement.iter())
+            .zip(self.reference_power.iter_mut())
+            .zip(self.measurement_power.iter_mut())
+            .zip(self.cross_spectrum.iter_mut())
+            .zip(
+                self.magnitude
+                    .iter_mut()
+                    .zip(self.phase.iter_mut())
+                    .zip(self.coherence.iter_mut()),
+            )
+        {
+            *gxx = old_weight * *gxx + new_weight * x.norm_sqr();
+            *gyy = old_weight * *gyy + new_weight * y.norm_sqr();
+            *gxy = *gxy * old_weight + (x.conj() * y) * new_weight;
+            let gxy_norm_sqr = gxy.norm_sqr();
+            // NOTE: |H1| in dB is 20*log10(|Gxy|/Gxx) = 10*log10(|Gxy|²/Gxx²)
+            *mag = 10.0 * (gxy_norm_sqr / (*gxx * *gxx)).log10().as_();
+            *phase = gxy.arg().to_degrees().as_();
+            *coherence = (gxy_norm_sqr / (*gxx * *gyy)).as_();
+        }
+    }
+
+    /// Magnitude of the H1 transfer function estimate in dB
+    pub fn magnitude(&self) -> &[f32] {
+        &self.magnitude[..]
+    }
+
+    /// Phase of the H1 transfer function estimate in degrees, in [-180, 180]
+    pub fn phase(&self) -> &[f32] {
+        &self.phase[..]
+    }
+
+    /// Magnitude-squared coherence, from 0 (no linear relationship between
+    /// reference and measurement) to 1 (perfect linear relationship)
+    pub fn coherence(&self) -> &[f32] {
+        &self.coherence[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use more_asserts::*;
+
+    #[test]
+    fn scaled_noise() {
+        // Set up a transfer function analyzer
+        let new_fourier = || SteadyQTransform::new(20.0, 20.0, (20.0, 20_000.0), 48_000, "hann");
+        let mut transfer = TransferFunction::new(new_fourier(), new_fourier(), 0, 8);
+
+        // Feed it with pseudorandom noise and an attenuated copy thereof
+        let mut state = 0x2545_f491_u32;
+        for _ in 0..8 {
+            for (reference, measurement) in transfer
+                .reference_history
+                .iter_mut()
+                .zip(transfer.measurement.input().iter_mut())
+            {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                *reference = state as f32 / u32::MAX as f32 - 0.5;
+                *measurement = 0.5 * *reference;
+            }
+            transfer.compute();
+        }
+
+        // Check that the gain is -6dB, with zero phase and perfect coherence
+        let output_len = transfer.output_len();
+        for bin in 1..output_len - 1 {
+            assert_lt!((transfer.magnitude()[bin] + 6.02).abs(), 0.01);
+            assert_lt!(transfer.phase()[bin].abs(), 0.1);
+            assert_gt!(transfer.coherence()[bin], 0.999);
+        }
+    }
+}
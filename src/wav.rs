@@ -0,0 +1,216 @@
+//! Minimal WAV file decoding, for offline analysis of recordings
+
+use crate::Result;
+use std::path::Path;
+
+/// Format tag of integer PCM data
+const FORMAT_PCM: u16 = 1;
+
+/// Format tag of IEEE floating-point data
+const FORMAT_FLOAT: u16 = 3;
+
+/// Format tag of WAVE_FORMAT_EXTENSIBLE, where the actual format tag is
+/// stored at the beginning of the subformat GUID
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Decoded audio from a WAV file
+#[derive(Clone, Debug, PartialEq)]
+pub struct WavAudio {
+    /// Sampling rate in Hz
+    pub sample_rate: usize,
+
+    /// Samples of each channel, normalized such that full scale is 1.0
+    pub channels: Box<[Box<[f32]>]>,
+}
+
+/// Read and decode a WAV file
+pub fn read(path: impl AsRef<Path>) -> Result<WavAudio> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)
+        .map_err(|e| anyhow::format_err!("Failed to read {}: {e}", path.display()))?;
+    decode(&bytes)
+}
+
+/// Decode the contents of a WAV file
+///
+/// Integer PCM data from 8 to 32 bits and 32/64-bit floating-point data are
+/// supported, including when stored using the WAVE_FORMAT_EXTENSIBLE header.
+///
+pub fn decode(bytes: &[u8]) -> Result<WavAudio> {
+    // Check the RIFF header
+    anyhow::ensure!(
+        bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WAVE",
+        "Not a WAV file"
+    );
+
+    // Go through the chunks, looking for the format and data
+    let mut format = None;
+    let mut data = None;
+    let mut remainder = &bytes[12..];
+    while remainder.len() >= 8 {
+        let id = &remainder[0..4];
+        let len =
+            u32::from_le_bytes([remainder[4], remainder[5], remainder[6], remainder[7]]) as usize;
+        let body = &remainder[8..];
+        // Some writers leave the data length unspecified when streaming
+        let len = len.min(body.len());
+        match id {
+            b"fmt " => format = Some(Format::decode(&body[..len])?),
+            b"data" => data = Some(&body[..len]),
+            _ => {}
+        }
+        // Chunks are padded to an even number of bytes
+        remainder = &body[(len + len % 2).min(body.len())..];
+    }
+    let format = format.ok_or_else(|| anyhow::format_err!("WAV file has no format chunk"))?;
+    let data = data.ok_or_else(|| anyhow::format_err!("WAV file has no data chunk"))?;
+
+    // Deinterleave and normalize the samples
+    let bytes_per_sample = format.bits_per_sample / 8;
+    let frame_len = bytes_per_sample * format.num_channels;
+    let num_frames = data.len() / frame_len;
+    let mut channels = vec![Vec::with_capacity(num_frames); format.num_channels];
+    for frame in data.chunks_exact(frame_len) {
+        for (channel, sample) in channels
+            .iter_mut()
+            .zip(frame.chunks_exact(bytes_per_sample))
+        {
+            channel.push(format.decode_sample(sample));
+        }
+    }
+    Ok(WavAudio {
+        sample_rate: format.sample_rate,
+        channels: channels.into_iter().map(Vec::into_boxed_slice).collect(),
+    })
+}
+
+/// Sample format of a WAV file
+struct Format {
+    /// Whether samples are floating-point (as opposed to integer) numbers
+    is_float: bool,
+
+    /// Number of interleaved channels
+    num_channels: usize,
+
+    /// Sampling rate in Hz
+    sample_rate: usize,
+
+    /// Number of bits per sample
+    bits_per_sample: usize,
+}
+//
+impl Format {
+    /// Decode the format chunk of a WAV file
+    fn decode(chunk: &[u8]) -> Result<Self> {
+        anyhow::ensure!(chunk.len() >= 16, "WAV format chunk is too short");
+        let u16_at = |idx: usize| u16::from_le_bytes([chunk[idx], chunk[idx + 1]]);
+        let u32_at = |idx: usize| {
+            u32::from_le_bytes([chunk[idx], chunk[idx + 1], chunk[idx + 2], chunk[idx + 3]])
+        };
+        let mut format_tag = u16_at(0);
+        if format_tag == FORMAT_EXTENSIBLE {
+            anyhow::ensure!(
+                chunk.len() >= 26,
+                "WAV extensible format chunk is too short"
+            );
+            format_tag = u16_at(24);
+        }
+        let format = Self {
+            is_float: format_tag == FORMAT_FLOAT,
+            num_channels: u16_at(2) as usize,
+            sample_rate: u32_at(4) as usize,
+            bits_per_sample: u16_at(14) as usize,
+        };
+        anyhow::ensure!(format.num_channels > 0, "WAV file has no channels");
+        anyhow::ensure!(format.sample_rate > 0, "WAV file has no sampling rate");
+        match (format_tag, format.bits_per_sample) {
+            (FORMAT_PCM, 8 | 16 | 24 | 32) | (FORMAT_FLOAT, 32 | 64) => Ok(format),
+            (tag, bits) => Err(anyhow::format_err!(
+                "WAV sample format {tag} with {bits} bits per sample is not supported"
+            )),
+        }
+    }
+
+    /// Decode a single sample, normalizing it such that full scale is 1.0
+    fn decode_sample(&self, bytes: &[u8]) -> f32 {
+        match (self.is_float, bytes.len()) {
+            (true, 4) => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            (true, 8) => {
+                let mut buf = [0; 8];
+                buf.copy_from_slice(bytes);
+                f64::from_le_bytes(buf) as f32
+            }
+            // 8-bit PCM is unsigned, other bit depths are signed
+            (false, 1) => (f32::from(bytes[0]) - 128.0) / 128.0,
+            (false, len) => {
+                // Left-align the sample into an i32, then normalize
+                let mut buf = [0; 4];
+                buf[4 - len..].copy_from_slice(bytes);
+                i32::from_le_bytes(buf) as f32 / -(i32::MIN as f32)
+            }
+            _ => unreachable!("Sample format should have been validated"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_formats() {
+        // Build a WAV file from a format chunk and interleaved sample data
+        fn wav_file(format_tag: u16, channels: u16, bits: u16, data: &[u8]) -> Vec<u8> {
+            let mut fmt = Vec::new();
+            fmt.extend_from_slice(&format_tag.to_le_bytes());
+            fmt.extend_from_slice(&channels.to_le_bytes());
+            fmt.extend_from_slice(&44_100u32.to_le_bytes());
+            let block_align = channels * bits / 8;
+            fmt.extend_from_slice(&(44_100 * u32::from(block_align)).to_le_bytes());
+            fmt.extend_from_slice(&block_align.to_le_bytes());
+            fmt.extend_from_slice(&bits.to_le_bytes());
+            let mut file = b"RIFF\0\0\0\0WAVE".to_vec();
+            for (id, body) in [
+                (b"fmt ", &fmt[..]),
+                (b"LIST", &[1, 2, 3][..]),
+                (b"data", data),
+            ] {
+                file.extend_from_slice(id);
+                file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+                file.extend_from_slice(body);
+                if body.len() % 2 == 1 {
+                    file.push(0);
+                }
+            }
+            file
+        }
+
+        // Stereo 16-bit PCM
+        let data = [0i16, i16::MIN, 16384, -16384]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        let audio = decode(&wav_file(FORMAT_PCM, 2, 16, &data)).unwrap();
+        assert_eq!(audio.sample_rate, 44_100);
+        assert_eq!(audio.channels.len(), 2);
+        assert_eq!(&audio.channels[0][..], &[0.0, 0.5]);
+        assert_eq!(&audio.channels[1][..], &[-1.0, -0.5]);
+
+        // Mono 24-bit PCM
+        let data = [0x00, 0x00, 0x40, 0x00, 0x00, 0xC0];
+        let audio = decode(&wav_file(FORMAT_PCM, 1, 24, &data)).unwrap();
+        assert_eq!(&audio.channels[0][..], &[0.5, -0.5]);
+
+        // Mono 32-bit float
+        let data = [0.25f32, -0.75]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        let audio = decode(&wav_file(FORMAT_FLOAT, 1, 32, &data)).unwrap();
+        assert_eq!(&audio.channels[0][..], &[0.25, -0.75]);
+
+        // Unsupported formats and garbage are rejected
+        assert!(decode(&wav_file(FORMAT_FLOAT, 1, 16, &[0, 0])).is_err());
+        assert!(decode(b"RIFF\0\0\0\0AVI ").is_err());
+    }
+}
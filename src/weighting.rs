@@ -0,0 +1,178 @@
+//! Frequency weighting curves
+
+use crate::Result;
+use std::str::FromStr;
+
+/// Frequency weighting curve, as defined by IEC 61672-1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Weighting {
+    /// A-weighting, which approximates the sensitivity of human hearing to
+    /// quiet sounds, and is used for most environmental noise measurements
+    A,
+
+    /// C-weighting, which approximates the sensitivity of human hearing to
+    /// loud sounds, and is used for peak level measurements
+    C,
+
+    /// Z-weighting, i.e. no weighting at all
+    Z,
+}
+//
+impl Weighting {
+    /// Gain of the weighting filter at a certain frequency in Hz, in dB
+    ///
+    /// Gains are normalized to 0dB at 1kHz, as mandated by the standard.
+    ///
+    pub fn gain(self, freq: f32) -> f32 {
+        // Pole frequencies of the weighting filters in Hz
+        const F1: f64 = 20.598_997;
+        const F2: f64 = 107.652_65;
+        const F3: f64 = 737.862_23;
+        const F4: f64 = 12_194.217;
+
+        // Normalization constants that bring the gain at 1kHz to 0dB
+        const A1000: f64 = 1.9997;
+        const C1000: f64 = 0.0619;
+
+        // Evaluate the analog filter transfer functions
+        let f2 = (freq as f64).powi(2);
+        let gain = match self {
+            Self::A => {
+                let response = F4.powi(2) * f2.powi(2)
+                    / ((f2 + F1.powi(2))
+                        * ((f2 + F2.powi(2)) * (f2 + F3.powi(2))).sqrt()
+                        * (f2 + F4.powi(2)));
+                20.0 * response.log10() + A1000
+            }
+            Self::C => {
+                let response = F4.powi(2) * f2 / ((f2 + F1.powi(2)) * (f2 + F4.powi(2)));
+                20.0 * response.log10() + C1000
+            }
+            Self::Z => 0.0,
+        };
+
+        // A- and C-weighting have zero gain at 0Hz, which would translate into
+        // an infinitely negative dB gain. Clamp that to a finite value which
+        // is far below anything that can be displayed.
+        gain.max(MIN_GAIN as f64) as f32
+    }
+
+    /// Next weighting curve, for cycling through them at runtime
+    pub fn next(self) -> Self {
+        match self {
+            Self::A => Self::C,
+            Self::C => Self::Z,
+            Self::Z => Self::A,
+        }
+    }
+}
+//
+impl FromStr for Weighting {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "a" | "A" => Ok(Self::A),
+            "c" | "C" => Ok(Self::C),
+            "z" | "Z" => Ok(Self::Z),
+            _ => Err(anyhow::format_err!(
+                "Frequency weighting {s} is not supported"
+            )),
+        }
+    }
+}
+
+/// Lowest gain that a weighting curve can have, in dB
+const MIN_GAIN: f32 = -200.0;
+
+/// Frequency weighting of Fourier transform magnitudes
+pub struct FrequencyWeighting {
+    /// Active weighting curve
+    weighting: Weighting,
+
+    /// Audio sampling rate
+    sample_rate: usize,
+
+    /// Gain of the weighting curve at each Fourier transform bin, in dB
+    bin_gains: Box<[f32]>,
+
+    /// Weighted magnitudes
+    output: Box<[f32]>,
+}
+//
+impl FrequencyWeighting {
+    /// Prepare to weight Fourier transforms of a certain output length, given
+    /// the audio sampling rate and the initial weighting curve
+    pub fn new(weighting: Weighting, fourier_len: usize, sample_rate: usize) -> Self {
+        assert!(fourier_len >= 2);
+        let mut result = Self {
+            weighting,
+            sample_rate,
+            bin_gains: vec![0.0; fourier_len].into_boxed_slice(),
+            output: vec![0.0; fourier_len].into_boxed_slice(),
+        };
+        result.set_weighting(weighting);
+        result
+    }
+
+    /// Query the active weighting curve
+    pub fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+
+    /// Switch to a different weighting curve
+    pub fn set_weighting(&mut self, weighting: Weighting) {
+        self.weighting = weighting;
+        let bin_width = (self.sample_rate / 2) as f32 / (self.bin_gains.len() - 1) as f32;
+        for (bin, gain) in self.bin_gains.iter_mut().enumerate() {
+            *gain = weighting.gain(bin as f32 * bin_width);
+        }
+    }
+
+    /// Apply the weighting curve to Fourier transform magnitudes in dBFS
+    pub fn apply<'out>(&'out mut self, magnitudes: &'out [f32]) -> &'out [f32] {
+        assert_eq!(magnitudes.len(), self.output.len());
+        if self.weighting == Weighting::Z {
+            return magnitudes;
+        }
+        for ((dest, &magnitude), &gain) in self
+            .output
+            .iter_mut()
+            .zip(magnitudes)
+            .zip(self.bin_gains.iter())
+        {
+            *dest = magnitude + gain;
+        }
+        &self.output[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use more_asserts::*;
+
+    #[test]
+    fn iec_61672_table() {
+        // Values from IEC 61672-1 table 3, which are given with 0.1dB precision
+        // at the exact base-10 frequencies 1kHz * 10^(n/10). Format is
+        // (n, nominal frequency, A-weighting, C-weighting).
+        const TABLE: [(i32, f32, f32, f32); 7] = [
+            (-15, 31.5, -39.4, -3.0),
+            (-12, 63.0, -26.2, -0.8),
+            (-9, 125.0, -16.1, -0.2),
+            (0, 1000.0, 0.0, 0.0),
+            (6, 4000.0, 1.0, -0.8),
+            (9, 8000.0, -1.1, -3.0),
+            (12, 16000.0, -6.6, -8.5),
+        ];
+        for (n, nominal_freq, a_gain, c_gain) in TABLE {
+            let freq = 1000.0 * 10.0f32.powf(n as f32 / 10.0);
+            assert_le!((freq / nominal_freq - 1.0).abs(), 0.02);
+            assert_le!((Weighting::A.gain(freq) - a_gain).abs(), 0.05);
+            assert_le!((Weighting::C.gain(freq) - c_gain).abs(), 0.05);
+            assert_eq!(Weighting::Z.gain(freq), 0.0);
+        }
+        assert_eq!(Weighting::A.gain(0.0), MIN_GAIN);
+    }
+}